@@ -1,9 +1,25 @@
+pub mod closure;
+pub mod diff;
+pub mod dominators;
+pub mod dot;
+pub mod dump_db;
+pub mod duplicates;
 pub mod exec_nix_store;
+pub mod graph;
+pub mod parse_err;
 pub mod parsing;
+pub mod path_info;
+pub mod stats;
+pub mod store_path;
+pub mod why_depends;
 
 use super::tree::{Path, Tree, TreePathMap};
+use graph::NixQueryGraph;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
 use std::path::PathBuf;
 use std::str::FromStr;
+use store_path::{StorePath, StorePathErr};
 
 /// This corresponds to a nix store path.
 ///
@@ -13,7 +29,10 @@ use std::str::FromStr;
 /// let nix_query_drv =
 ///     NixQueryDrv::from("/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10");
 /// ```
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+///
+/// This is serialized as a string holding the full store path.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct NixQueryDrv(PathBuf);
 
 impl<T: ?Sized + AsRef<std::ffi::OsStr>> From<&T> for NixQueryDrv {
@@ -39,6 +58,46 @@ impl NixQueryDrv {
         self.drv_name().cmp(&other.drv_name())
     }
 
+    /// Compare by package name, and then by version using the same rules as
+    /// `builtins.compareVersions`.  See `store_path::compare_versions`.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::nix_query_tree::NixQueryDrv;
+    /// use std::cmp::Ordering;
+    ///
+    /// let gcc_9 = NixQueryDrv::from("/nix/store/az4kl5slhbkmmy4vj98z3hzxxkan7zza-gcc-9.3.0");
+    /// let gcc_10 = NixQueryDrv::from("/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-gcc-10.2.0");
+    ///
+    /// assert_eq!(gcc_10.cmp_drv_name(&gcc_9), Ordering::Less);
+    /// assert_eq!(gcc_9.cmp_version(&gcc_10), Ordering::Less);
+    /// ```
+    pub fn cmp_version(&self, other: &Self) -> std::cmp::Ordering {
+        let (package_name, version) = self.package_name_and_version();
+        let (other_package_name, other_version) =
+            other.package_name_and_version();
+        package_name
+            .cmp(&other_package_name)
+            .then_with(|| {
+                store_path::compare_versions(&version, &other_version)
+            })
+            .then_with(|| self.cmp_drv_name(other))
+    }
+
+    /// Parse this `NixQueryDrv` into a `StorePath`.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::nix_query_tree::NixQueryDrv;
+    ///
+    /// let nix_query_drv =
+    ///     NixQueryDrv::from("/nix/store/az4kl5slhbkmmy4vj98z3hzxxkan7zza-gnugrep-3.3");
+    /// assert_eq!(nix_query_drv.store_path().unwrap().pname, "gnugrep");
+    ///
+    /// assert!(NixQueryDrv::from("/nix/store/foo").store_path().is_err());
+    /// ```
+    pub fn store_path(&self) -> Result<StorePath, StorePathErr> {
+        StorePath::new(&self.0)
+    }
+
     /// Pull out the hash and derivation name from a `NixQueryDrv`
     ///
     /// ```
@@ -51,9 +110,16 @@ impl NixQueryDrv {
     ///     String::from("az4kl5slhbkmmy4vj98z3hzxxkan7zza-gnugrep-3.3")
     /// );
     /// ```
+    ///
+    /// This works for paths in any store directory.  If this isn't a
+    /// well-formed store path, this is just the file name.
     pub fn hash_and_drv_name(&self) -> String {
-        let drv_str = self.0.to_string_lossy();
-        String::from((drv_str).trim_start_matches("/nix/store/"))
+        match self.store_path() {
+            Ok(store_path) => {
+                format!("{}-{}", store_path.hash, store_path.name)
+            }
+            Err(_) => self.file_name_or_full_path(),
+        }
     }
 
     /// Pull out a truncated hash and derivation name from a `NixQueryDrv`
@@ -67,20 +133,28 @@ impl NixQueryDrv {
     ///     nix_query_drv.short_hash_and_drv_name(),
     ///     String::from("az4kl5s..gnugrep-3.3")
     /// );
+    ///
+    /// let opt_drv =
+    ///     NixQueryDrv::from("/opt/nix/store/az4kl5slhbkmmy4vj98z3hzxxkan7zza-gnugrep-3.3");
+    /// assert_eq!(
+    ///     opt_drv.short_hash_and_drv_name(),
+    ///     String::from("az4kl5s..gnugrep-3.3")
+    /// );
+    ///
+    /// let ill_formed_drv = NixQueryDrv::from("/nix/store/gnugrep-3.3");
+    /// assert_eq!(
+    ///     ill_formed_drv.short_hash_and_drv_name(),
+    ///     String::from("gnugrep-3.3")
+    /// );
     /// ```
+    ///
+    /// If this isn't a well-formed store path, this is just the file name.
     pub fn short_hash_and_drv_name(&self) -> String {
-        let drv_str = self.0.to_string_lossy();
-        let drv_str_no_store =
-            String::from(drv_str.trim_start_matches("/nix/store/"));
-        let option_drv_name = drv_str_no_store
-            .find('-')
-            .and_then(|i| drv_str_no_store.get(i + 1..));
-        let option_short_hash = drv_str_no_store.get(0..7);
-        match (option_drv_name, option_short_hash) {
-            (Some(drv_name), Some(short_hash)) => {
-                format!("{}..{}", short_hash, drv_name)
+        match self.store_path() {
+            Ok(store_path) => {
+                format!("{}..{}", &store_path.hash[..7], store_path.name)
             }
-            _ => panic!("Ill-formed nix path"),
+            Err(_) => self.file_name_or_full_path(),
         }
     }
 
@@ -94,26 +168,128 @@ impl NixQueryDrv {
     /// assert_eq!(nix_query_drv.drv_name(), String::from("gnugrep-3.3"));
     /// ```
     ///
-    /// * Panics
-    ///
-    /// This panics if the derivation name doesn't have a `-` in it.  All nix derivations have
-    /// a `-` in them after the hash.
+    /// If this isn't a well-formed store path, this is just the file name.
     pub fn drv_name(&self) -> String {
-        let drv_str = self.0.to_string_lossy();
-        let option_dash_index = drv_str.find('-');
-        match option_dash_index {
-            None => drv_str.into_owned(),
-            Some(dash_index) => {
-                let option_just_drv_name = drv_str.get(dash_index + 1..);
-                match option_just_drv_name {
-                    None => {
-                        panic!("Nix paths will always have a dash in them.")
-                    }
-                    Some(drv_name) => drv_name.to_string(),
-                }
+        match self.store_path() {
+            Ok(store_path) => store_path.name,
+            Err(_) => self.file_name_or_full_path(),
+        }
+    }
+
+    fn file_name_or_full_path(&self) -> String {
+        self.0
+            .file_name()
+            .unwrap_or_else(|| self.0.as_os_str())
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Split the derivation name into a package name and a version.  See
+    /// `store_path::parse_drv_name`.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::nix_query_tree::NixQueryDrv;
+    ///
+    /// let nix_query_drv =
+    ///     NixQueryDrv::from("/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-interactive-4.4-p23");
+    /// assert_eq!(
+    ///     nix_query_drv.package_name_and_version(),
+    ///     (String::from("bash-interactive"), String::from("4.4-p23"))
+    /// );
+    ///
+    /// let nix_query_drv =
+    ///     NixQueryDrv::from("/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh");
+    /// assert_eq!(
+    ///     nix_query_drv.package_name_and_version(),
+    ///     (String::from("multiple-outputs.sh"), String::new())
+    /// );
+    /// ```
+    pub fn package_name_and_version(&self) -> (String, String) {
+        store_path::parse_drv_name(&self.drv_name())
+    }
+
+    /// The package name part of `package_name_and_version`.
+    pub fn package_name(&self) -> String {
+        self.package_name_and_version().0
+    }
+
+    /// Whether this is a `.drv` file, as opposed to the output of a
+    /// derivation.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::nix_query_tree::NixQueryDrv;
+    ///
+    /// let hello_drv =
+    ///     NixQueryDrv::from("/nix/store/jymg0kanmlgbcv35wxd8d660rw0fawhv-hello-2.10.drv");
+    /// assert!(hello_drv.is_drv());
+    ///
+    /// let hello =
+    ///     NixQueryDrv::from("/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10");
+    /// assert!(!hello.is_drv());
+    /// ```
+    pub fn is_drv(&self) -> bool {
+        self.0.extension() == Some(OsStr::new("drv"))
+    }
+
+    /// The output this store path is for, recognised from a well-known
+    /// output suffix on the derivation name.  See `store_path::OUTPUT_NAMES`.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::nix_query_tree::NixQueryDrv;
+    ///
+    /// let gcc_lib =
+    ///     NixQueryDrv::from("/nix/store/hlnxw4k6931bachvg5sv0cyaissimswb-gcc-7.4.0-lib");
+    /// assert_eq!(gcc_lib.output(), Some(String::from("lib")));
+    ///
+    /// let gcc =
+    ///     NixQueryDrv::from("/nix/store/ghzg4kg0sjif58smj2lfm2bdvjwim85y-gcc-7.4.0");
+    /// assert_eq!(gcc.output(), None);
+    /// ```
+    pub fn output(&self) -> Option<String> {
+        self.store_path()
+            .ok()
+            .and_then(|store_path| store_path.output)
+    }
+
+    /// The derivation name without any output suffix.  The outputs of a
+    /// single derivation all have the same `drv_name_without_output`.
+    pub fn drv_name_without_output(&self) -> String {
+        let drv_name = self.drv_name();
+        self.output()
+            .and_then(|output| {
+                drv_name
+                    .strip_suffix(&format!("-{}", output))
+                    .map(String::from)
+            })
+            .unwrap_or(drv_name)
+    }
+
+    /// The derivation name, with the output in parentheses.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::nix_query_tree::NixQueryDrv;
+    ///
+    /// let gcc_lib =
+    ///     NixQueryDrv::from("/nix/store/hlnxw4k6931bachvg5sv0cyaissimswb-gcc-7.4.0-lib");
+    /// assert_eq!(gcc_lib.drv_name_and_output(), String::from("gcc-7.4.0 (lib)"));
+    ///
+    /// let gcc =
+    ///     NixQueryDrv::from("/nix/store/ghzg4kg0sjif58smj2lfm2bdvjwim85y-gcc-7.4.0");
+    /// assert_eq!(gcc.drv_name_and_output(), String::from("gcc-7.4.0"));
+    /// ```
+    pub fn drv_name_and_output(&self) -> String {
+        match self.output() {
+            None => self.drv_name(),
+            Some(output) => {
+                format!("{} ({})", self.drv_name_without_output(), output)
             }
         }
     }
+
+    /// Compare outputs, with the default output first.
+    pub fn cmp_output(&self, other: &Self) -> std::cmp::Ordering {
+        self.output().cmp(&other.output())
+    }
 }
 
 impl FromStr for NixQueryDrv {
@@ -135,14 +311,39 @@ impl std::fmt::Display for NixQueryDrv {
 /// this nix store entry.
 ///
 /// See `NixQueryEntry`.
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+///
+/// This is serialized as a boolean, where `true` is `Recurse::Yes`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[serde(from = "bool", into = "bool")]
 pub enum Recurse {
     Yes,
     No,
 }
 
+impl From<bool> for Recurse {
+    fn from(b: bool) -> Recurse {
+        if b {
+            Recurse::Yes
+        } else {
+            Recurse::No
+        }
+    }
+}
+
+impl From<Recurse> for bool {
+    fn from(recurse: Recurse) -> bool {
+        recurse == Recurse::Yes
+    }
+}
+
 /// `NixQueryDrv` coupled with a marker for a recursive entry.
 ///
+/// The metadata from `nix path-info` is deliberately not kept here.  It is
+/// loaded in the background after the tree is displayed, it is the same for
+/// every instance of a path, and entries are hashed, compared and
+/// serialized (with a stable schema) as just a path and a marker.  See
+/// `NixStoreRes::path_info` instead.
+///
 /// ```
 /// use nix_query_tree_viewer::nix_query_tree::{NixQueryEntry, Recurse};
 /// use std::str::FromStr;
@@ -154,9 +355,34 @@ pub enum Recurse {
 /// assert_eq!(nix_query_entry, Ok(actual_nix_query_entry));
 /// ```
 ///
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+/// This is serialized as an object with a `path` and a `recurse` field:
+///
+/// ```json
+/// { "path": "/nix/store/az4kl5slhbkmmy4vj98z3hzxxkan7zza-gnugrep-3.3", "recurse": true }
+/// ```
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[serde(from = "SerdeNixQueryEntry", into = "SerdeNixQueryEntry")]
 pub struct NixQueryEntry(pub NixQueryDrv, pub Recurse);
 
+/// The serialized form of a `NixQueryEntry`.
+#[derive(Serialize, Deserialize)]
+struct SerdeNixQueryEntry {
+    path: NixQueryDrv,
+    recurse: Recurse,
+}
+
+impl From<SerdeNixQueryEntry> for NixQueryEntry {
+    fn from(entry: SerdeNixQueryEntry) -> NixQueryEntry {
+        NixQueryEntry(entry.path, entry.recurse)
+    }
+}
+
+impl From<NixQueryEntry> for SerdeNixQueryEntry {
+    fn from(NixQueryEntry(path, recurse): NixQueryEntry) -> SerdeNixQueryEntry {
+        SerdeNixQueryEntry { path, recurse }
+    }
+}
+
 impl FromStr for NixQueryEntry {
     type Err = nom::Err<(String, nom::error::ErrorKind)>;
 
@@ -189,6 +415,10 @@ impl NixQueryEntry {
         self.0.cmp_drv_name(&other.0)
     }
 
+    pub fn cmp_version(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.cmp_version(&other.0)
+    }
+
     pub fn hash_and_drv_name(&self) -> String {
         self.0.hash_and_drv_name()
     }
@@ -200,6 +430,10 @@ impl NixQueryEntry {
     pub fn drv_name(&self) -> String {
         self.0.drv_name()
     }
+
+    pub fn drv_name_and_output(&self) -> String {
+        self.0.drv_name_and_output()
+    }
 }
 
 /// A `Tree` representing the result from `nix store --query --tree`.
@@ -220,7 +454,10 @@ impl NixQueryEntry {
 ///
 /// assert!(nix_query_tree.is_ok());
 /// ```
-#[derive(Clone, Debug, Eq, PartialEq)]
+///
+/// This is serialized as the underlying `Tree<NixQueryEntry>`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct NixQueryTree(pub Tree<NixQueryEntry>);
 
 impl NixQueryTree {
@@ -234,6 +471,46 @@ impl NixQueryTree {
     pub fn lookup(&self, path: Path) -> Option<&NixQueryEntry> {
         self.0.lookup(path)
     }
+
+    /// The store path that this tree is for.
+    pub fn root(&self) -> &NixQueryDrv {
+        &self.0.item.0
+    }
+
+    /// Lookup the `NixQueryEntry` at `path`, along with every ancestor from
+    /// the root down.  See `Tree::lookup_with_ancestors`.
+    pub fn lookup_with_ancestors(
+        &self,
+        path: Path,
+    ) -> Option<Vec<&NixQueryEntry>> {
+        self.0.lookup_with_ancestors(path)
+    }
+
+    /// Find the instance of `nix_query_drv` in the tree that has its
+    /// references as children.
+    ///
+    /// This is the first instance that is not a `[...]` entry.  For a tree
+    /// parsed from `nix-store --query --tree` this is always the first
+    /// instance, but a lazily loaded tree may expand a later instance.
+    pub fn lookup_expanded<'a>(
+        &self,
+        path_map: &'a NixQueryPathMap,
+        nix_query_drv: &NixQueryDrv,
+    ) -> Option<&'a Path> {
+        let paths = path_map.lookup_all(nix_query_drv);
+        paths
+            .iter()
+            .find(|path| {
+                self.lookup((*path).clone()).map(|entry| entry.1)
+                    == Some(Recurse::No)
+            })
+            .or_else(|| paths.first())
+    }
+
+    /// Build the deduplicated `NixQueryGraph` for this tree.
+    pub fn graph(&self) -> NixQueryGraph {
+        NixQueryGraph::new(self, &self.path_map())
+    }
 }
 
 impl FromStr for NixQueryTree {
@@ -270,12 +547,31 @@ impl FromStr for NixQueryTree {
 /// let expected_path = Some(Path::from(vec![2, 0, 1]));
 ///
 /// assert_eq!(map.lookup_first(&pcre_drv), expected_path.as_ref());
+///
+/// let glibc_drv = NixQueryDrv::from("/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27");
+///
+/// assert_eq!(map.lookup_all(&glibc_drv).len(), 3);
+/// assert_eq!(map.lookup_nth(&glibc_drv, 2), Some(&Path::from(vec![2, 0])));
 /// ```
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct NixQueryPathMap(pub TreePathMap<NixQueryDrv>);
 
 impl NixQueryPathMap {
+    /// Add the `Path` of a new node, keeping the `Path`s for `k` in the order
+    /// of a pre-order walk of the tree.
+    pub fn insert(&mut self, k: NixQueryDrv, path: Path) {
+        self.0.insert_ordered(k, path);
+    }
+
     pub fn lookup_first(&self, k: &NixQueryDrv) -> Option<&Path> {
         self.0.lookup_first(k)
     }
+
+    pub fn lookup_all(&self, k: &NixQueryDrv) -> &[Path] {
+        self.0.lookup_all(k)
+    }
+
+    pub fn lookup_nth(&self, k: &NixQueryDrv, n: usize) -> Option<&Path> {
+        self.0.lookup_nth(k, n)
+    }
 }
@@ -0,0 +1,237 @@
+use std::collections::{HashMap, HashSet};
+
+use super::super::tree::Tree;
+use super::{NixQueryDrv, NixQueryEntry, NixQueryTree, Recurse};
+
+/// The direct references of every path in a closure, without any of the
+/// structure of a `NixQueryTree`.
+///
+/// This is what outputs that list every reference edge explicitly (like
+/// `nix-store --query --graph`) are read into, before being turned into the
+/// `NixQueryTree` that `nix-store --query --tree` would have printed.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::closure::Closure;
+/// use nix_query_tree_viewer::nix_query_tree::NixQueryDrv;
+///
+/// let hello =
+///     NixQueryDrv::from("/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10");
+/// let glibc =
+///     NixQueryDrv::from("/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27");
+///
+/// let mut closure = Closure::new();
+/// closure.insert_reference(hello.clone(), glibc.clone());
+///
+/// assert_eq!(closure.roots(), vec![&hello]);
+/// assert_eq!(closure.tree(&hello).root(), &hello);
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Closure {
+    /// Every path, in the order it was first inserted.
+    paths: Vec<NixQueryDrv>,
+    references: HashMap<NixQueryDrv, Vec<NixQueryDrv>>,
+}
+
+impl Closure {
+    pub fn new() -> Self {
+        Closure::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn contains(&self, nix_query_drv: &NixQueryDrv) -> bool {
+        self.references.contains_key(nix_query_drv)
+    }
+
+    /// Insert a path without any references.  Inserting a path that is
+    /// already in the closure does nothing.
+    pub fn insert_path(&mut self, nix_query_drv: NixQueryDrv) {
+        if !self.contains(&nix_query_drv) {
+            self.paths.push(nix_query_drv.clone());
+            self.references.insert(nix_query_drv, vec![]);
+        }
+    }
+
+    /// Record that `referrer` references `reference`, inserting either path
+    /// if it isn't in the closure yet.
+    pub fn insert_reference(
+        &mut self,
+        referrer: NixQueryDrv,
+        reference: NixQueryDrv,
+    ) {
+        if !self.contains(&referrer) {
+            self.paths.push(referrer.clone());
+        }
+        let references = self.references.entry(referrer).or_default();
+        if !references.contains(&reference) {
+            references.push(reference.clone());
+        }
+        self.insert_path(reference);
+    }
+
+    /// The direct references of `nix_query_drv`, in the order they were
+    /// inserted.
+    pub fn references(&self, nix_query_drv: &NixQueryDrv) -> &[NixQueryDrv] {
+        self.references
+            .get(nix_query_drv)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The paths that no other path in the closure references, in the order
+    /// they were first inserted.  A closure of a single store path has
+    /// exactly one of these.
+    pub fn roots(&self) -> Vec<&NixQueryDrv> {
+        let referenced: HashSet<&NixQueryDrv> = self
+            .references
+            .iter()
+            .flat_map(|(referrer, references)| {
+                references
+                    .iter()
+                    .filter(move |reference| *reference != referrer)
+            })
+            .collect();
+        self.paths
+            .iter()
+            .filter(|nix_query_drv| !referenced.contains(nix_query_drv))
+            .collect()
+    }
+
+    /// The references of `nix_query_drv` in the order `nix-store --query
+    /// --tree` prints them.
+    ///
+    /// `nix-store` sorts the references topologically, so that a reference
+    /// comes before every other reference that (possibly indirectly) refers
+    /// to it.  Ties are broken by walking the references in order of their
+    /// full path, which is the order `nix-store` keeps them in.
+    fn sorted_references(
+        &self,
+        nix_query_drv: &NixQueryDrv,
+    ) -> Vec<&NixQueryDrv> {
+        let mut references: Vec<&NixQueryDrv> =
+            self.references(nix_query_drv).iter().collect();
+        references.sort_by(|drv_a, drv_b| drv_a.cmp_hash(drv_b));
+        let reference_set: HashSet<&NixQueryDrv> =
+            references.iter().copied().collect();
+
+        let mut sorted: Vec<&NixQueryDrv> = vec![];
+        let mut visited: HashSet<&NixQueryDrv> = HashSet::new();
+        for reference in &references {
+            self.visit_references(
+                reference,
+                &reference_set,
+                &mut visited,
+                &mut sorted,
+            );
+        }
+        sorted
+    }
+
+    /// Add `nix_query_drv` to `sorted` after every path in `paths` that it
+    /// refers to, directly or through other paths in `paths`.
+    fn visit_references<'a>(
+        &'a self,
+        nix_query_drv: &'a NixQueryDrv,
+        paths: &HashSet<&'a NixQueryDrv>,
+        visited: &mut HashSet<&'a NixQueryDrv>,
+        sorted: &mut Vec<&'a NixQueryDrv>,
+    ) {
+        if !visited.insert(nix_query_drv) {
+            return;
+        }
+        let mut references: Vec<&NixQueryDrv> =
+            self.references(nix_query_drv).iter().collect();
+        references.sort_by(|drv_a, drv_b| drv_a.cmp_hash(drv_b));
+        for reference in references {
+            if reference != nix_query_drv && paths.contains(reference) {
+                self.visit_references(reference, paths, visited, sorted);
+            }
+        }
+        sorted.push(nix_query_drv);
+    }
+
+    fn subtree(
+        &self,
+        nix_query_drv: &NixQueryDrv,
+        done: &mut HashSet<NixQueryDrv>,
+    ) -> Tree<NixQueryEntry> {
+        if !done.insert(nix_query_drv.clone()) {
+            return Tree::singleton(NixQueryEntry(
+                nix_query_drv.clone(),
+                Recurse::Yes,
+            ));
+        }
+        let mut children = vec![];
+        for reference in self.sorted_references(nix_query_drv) {
+            children.push(self.subtree(reference, done));
+        }
+        Tree::new(NixQueryEntry(nix_query_drv.clone(), Recurse::No), children)
+    }
+
+    /// Build the tree that `nix-store --query --tree root` would print for
+    /// this closure.
+    ///
+    /// Every path is expanded the first time it is seen in a depth-first
+    /// walk, and is a `[...]` entry after that.
+    pub fn tree(&self, root: &NixQueryDrv) -> NixQueryTree {
+        NixQueryTree(self.subtree(root, &mut HashSet::new()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_tree_matches_nix_store_order() {
+        let raw_tree = indoc!(
+            "/nix/store/m3dzp25n0g4fwlygdhvak1kk8xz906n9-bash-4.4-p23.drv
+            +---/nix/store/58y89v7rl254dc2cygcfd5wzhv0kjm4m-bash44-013.drv
+            +---/nix/store/9krlzvny65gdc8s7kpb6lkx8cd02c25b-default-builder.sh
+            +---/nix/store/bfil786fxmnjcwc7mqpm0mk4xnm2cphg-bootstrap-tools.drv
+            |   +---/nix/store/b7irlwi2wjlx5aj1dghx4c8k3ax6m56q-busybox.drv
+            +---/nix/store/64si0sfawzz464jj6qljxn1brpqw20pi-bison-3.4.2.drv
+                +---/nix/store/9krlzvny65gdc8s7kpb6lkx8cd02c25b-default-builder.sh [...]
+                +---/nix/store/bfil786fxmnjcwc7mqpm0mk4xnm2cphg-bootstrap-tools.drv [...]
+            "
+        );
+        let expected = NixQueryTree::from_str(raw_tree).unwrap();
+
+        let drv = |base_name: &str| {
+            NixQueryDrv::from(&format!("/nix/store/{}", base_name))
+        };
+        let bash = drv("m3dzp25n0g4fwlygdhvak1kk8xz906n9-bash-4.4-p23.drv");
+        let patch = drv("58y89v7rl254dc2cygcfd5wzhv0kjm4m-bash44-013.drv");
+        let builder =
+            drv("9krlzvny65gdc8s7kpb6lkx8cd02c25b-default-builder.sh");
+        let bootstrap =
+            drv("bfil786fxmnjcwc7mqpm0mk4xnm2cphg-bootstrap-tools.drv");
+        let busybox = drv("b7irlwi2wjlx5aj1dghx4c8k3ax6m56q-busybox.drv");
+        let bison = drv("64si0sfawzz464jj6qljxn1brpqw20pi-bison-3.4.2.drv");
+
+        // Insert the edges in an order unrelated to the expected output.
+        let mut closure = Closure::new();
+        closure.insert_reference(bison.clone(), bootstrap.clone());
+        closure.insert_reference(bash.clone(), bison.clone());
+        closure.insert_reference(bash.clone(), builder.clone());
+        closure.insert_reference(bison.clone(), builder.clone());
+        closure.insert_reference(bootstrap.clone(), busybox.clone());
+        closure.insert_reference(bash.clone(), bootstrap.clone());
+        closure.insert_reference(bash.clone(), patch.clone());
+        closure.insert_reference(bash.clone(), patch.clone());
+
+        assert_eq!(closure.len(), 6);
+        assert_eq!(closure.roots(), vec![&bash]);
+        assert_eq!(closure.tree(&bash), expected);
+        assert_eq!(closure.tree(&bash).graph(), expected.graph());
+    }
+}
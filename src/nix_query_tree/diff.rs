@@ -0,0 +1,507 @@
+use std::collections::{HashMap, HashSet};
+
+use super::super::tree::Tree;
+use super::graph::NixQueryGraph;
+use super::{NixQueryDrv, NixQueryEntry, NixQueryTree, Recurse};
+
+/// How a single store path changed between two closures.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DiffKind {
+    Unchanged,
+    Added,
+    Removed,
+    VersionChanged,
+    /// The same package and version, but a different hash.
+    Rebuilt,
+}
+
+/// A package that is in both closures, but with a different store path.
+/// This is used both for version changes and for rebuilds of the same
+/// version.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct VersionChange {
+    pub package_name: String,
+    pub old: NixQueryDrv,
+    pub new: NixQueryDrv,
+}
+
+/// A single node in the merged tree of a `NixQueryTreeDiff`.
+///
+/// Nodes that are only in the old tree have no `new` drv, and nodes that are
+/// only in the new tree have no `old` drv.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct DiffEntry {
+    pub old: Option<NixQueryDrv>,
+    pub new: Option<NixQueryDrv>,
+    pub recurse: Recurse,
+    pub kind: DiffKind,
+}
+
+impl DiffEntry {
+    /// The drv to show for this entry.  This prefers the new drv.
+    ///
+    /// * Panics
+    ///
+    /// This panics if neither `old` nor `new` is set, which never happens for
+    /// the entries in `NixQueryTreeDiff::merged`.
+    pub fn drv(&self) -> &NixQueryDrv {
+        self.new
+            .as_ref()
+            .or(self.old.as_ref())
+            .expect("A DiffEntry always has at least one drv.")
+    }
+}
+
+/// The difference between two `NixQueryTree`s, for instance an old and a new
+/// system closure.
+///
+/// Store paths are matched up by package name (see
+/// `NixQueryDrv::package_name`), so a package that was upgraded shows up as a
+/// `VersionChange` instead of as one removed and one added path.  A store path
+/// that changed hash without changing version, which is most of them after a
+/// nixpkgs update, is reported as a rebuild.
+///
+/// ```
+/// use indoc::indoc;
+/// use nix_query_tree_viewer::nix_query_tree::diff::NixQueryTreeDiff;
+/// use nix_query_tree_viewer::nix_query_tree::NixQueryTree;
+/// use std::str::FromStr;
+///
+/// let old_tree = NixQueryTree::from_str(indoc!(
+///         "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+///         +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+///         +---/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43
+///         "
+///     )).unwrap();
+/// let new_tree = NixQueryTree::from_str(indoc!(
+///         "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+///         +---/nix/store/681354n3k44r8z90m35hm8945vsp95h1-glibc-2.30
+///         +---/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh
+///         "
+///     )).unwrap();
+/// let diff = NixQueryTreeDiff::new(&old_tree, &new_tree);
+///
+/// assert_eq!(diff.added.len(), 1);
+/// assert_eq!(diff.removed.len(), 1);
+/// assert_eq!(diff.version_changes.len(), 1);
+/// assert_eq!(diff.version_changes[0].package_name, "glibc");
+/// assert!(diff.rebuilds.is_empty());
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NixQueryTreeDiff {
+    /// Store paths only in the new closure, in the order they first appear.
+    pub added: Vec<NixQueryDrv>,
+    /// Store paths only in the old closure, in the order they first appear.
+    pub removed: Vec<NixQueryDrv>,
+    pub version_changes: Vec<VersionChange>,
+    /// Packages in both closures at the same version, but with a different
+    /// hash.
+    pub rebuilds: Vec<VersionChange>,
+    /// The old and new trees merged together.
+    pub merged: Tree<DiffEntry>,
+}
+
+impl NixQueryTreeDiff {
+    pub fn new(old_tree: &NixQueryTree, new_tree: &NixQueryTree) -> Self {
+        let old_graph: NixQueryGraph = old_tree.graph();
+        let new_graph: NixQueryGraph = new_tree.graph();
+
+        let old_only: Vec<&NixQueryDrv> = old_graph
+            .iter()
+            .map(|(_, drv)| drv)
+            .filter(|drv| new_graph.index(drv).is_none())
+            .collect();
+        let new_only: Vec<&NixQueryDrv> = new_graph
+            .iter()
+            .map(|(_, drv)| drv)
+            .filter(|drv| old_graph.index(drv).is_none())
+            .collect();
+
+        let mut old_only_by_name: HashMap<String, Vec<&NixQueryDrv>> =
+            HashMap::new();
+        for drv in &old_only {
+            old_only_by_name
+                .entry(drv.package_name())
+                .or_default()
+                .push(drv);
+        }
+
+        // Pair up each new-only drv with the first unpaired old-only drv that
+        // has the same package name.  Drvs with the same version are paired
+        // first, so that a rebuild doesn't get paired with an unrelated
+        // version of the same package.
+        let mut paired: HashSet<&NixQueryDrv> = HashSet::new();
+        let mut rebuilds: Vec<VersionChange> = vec![];
+        let mut version_changes: Vec<VersionChange> = vec![];
+        for &same_version in &[true, false] {
+            for new_drv in &new_only {
+                if paired.contains(new_drv) {
+                    continue;
+                }
+                let (package_name, new_version) =
+                    new_drv.package_name_and_version();
+                let option_old_drv = old_only_by_name
+                    .get_mut(&package_name)
+                    .and_then(|old_drvs| {
+                        let pos = old_drvs.iter().position(|old_drv| {
+                            !same_version
+                                || old_drv.package_name_and_version().1
+                                    == new_version
+                        })?;
+                        Some(old_drvs.remove(pos))
+                    });
+                if let Some(old_drv) = option_old_drv {
+                    paired.insert(old_drv);
+                    paired.insert(*new_drv);
+                    let change = VersionChange {
+                        package_name,
+                        old: old_drv.clone(),
+                        new: (*new_drv).clone(),
+                    };
+                    if same_version {
+                        rebuilds.push(change);
+                    } else {
+                        version_changes.push(change);
+                    }
+                }
+            }
+        }
+
+        let changed: HashSet<&NixQueryDrv> = version_changes
+            .iter()
+            .flat_map(|change| vec![&change.old, &change.new])
+            .collect();
+        let rebuilt: HashSet<&NixQueryDrv> = rebuilds
+            .iter()
+            .flat_map(|change| vec![&change.old, &change.new])
+            .collect();
+        let removed: Vec<NixQueryDrv> = old_only
+            .into_iter()
+            .filter(|drv| !paired.contains(drv))
+            .cloned()
+            .collect();
+        let added: Vec<NixQueryDrv> = new_only
+            .into_iter()
+            .filter(|drv| !paired.contains(drv))
+            .cloned()
+            .collect();
+
+        let classifier = Classifier {
+            added: added.iter().collect(),
+            removed: removed.iter().collect(),
+            changed,
+            rebuilt,
+        };
+        let merged = classifier.merge(&old_tree.0, &new_tree.0);
+
+        NixQueryTreeDiff {
+            added,
+            removed,
+            version_changes,
+            rebuilds,
+            merged,
+        }
+    }
+
+    /// Whether the two closures contain exactly the same store paths.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.version_changes.is_empty()
+            && self.rebuilds.is_empty()
+    }
+}
+
+/// Helper for building the merged tree of a `NixQueryTreeDiff`.
+struct Classifier<'a> {
+    added: HashSet<&'a NixQueryDrv>,
+    removed: HashSet<&'a NixQueryDrv>,
+    changed: HashSet<&'a NixQueryDrv>,
+    rebuilt: HashSet<&'a NixQueryDrv>,
+}
+
+impl Classifier<'_> {
+    fn kind(&self, drv: &NixQueryDrv) -> DiffKind {
+        if self.changed.contains(drv) {
+            DiffKind::VersionChanged
+        } else if self.rebuilt.contains(drv) {
+            DiffKind::Rebuilt
+        } else if self.added.contains(drv) {
+            DiffKind::Added
+        } else if self.removed.contains(drv) {
+            DiffKind::Removed
+        } else {
+            DiffKind::Unchanged
+        }
+    }
+
+    fn only(
+        &self,
+        tree: &Tree<NixQueryEntry>,
+        is_old: bool,
+    ) -> Tree<DiffEntry> {
+        tree.map(&|NixQueryEntry(drv, recurse)| DiffEntry {
+            old: if is_old { Some(drv.clone()) } else { None },
+            new: if is_old { None } else { Some(drv.clone()) },
+            recurse: *recurse,
+            kind: self.kind(drv),
+        })
+    }
+
+    /// The kind of a node that is in both trees, going by its old and new
+    /// drv.  The two drvs have the same package name, except for the roots,
+    /// which are always merged.
+    fn pair_kind(old_drv: &NixQueryDrv, new_drv: &NixQueryDrv) -> DiffKind {
+        if old_drv == new_drv {
+            DiffKind::Unchanged
+        } else if old_drv.package_name_and_version().1
+            == new_drv.package_name_and_version().1
+        {
+            DiffKind::Rebuilt
+        } else {
+            DiffKind::VersionChanged
+        }
+    }
+
+    /// Merge an old and a new node.  Children are matched up by store path
+    /// first, then by package name and version, and then by package name
+    /// alone, keeping the order of the new tree.  Old children without a
+    /// match are added at the end.
+    fn merge(
+        &self,
+        old_tree: &Tree<NixQueryEntry>,
+        new_tree: &Tree<NixQueryEntry>,
+    ) -> Tree<DiffEntry> {
+        let old_drv: &NixQueryDrv = &old_tree.item.0;
+        let new_drv: &NixQueryDrv = &new_tree.item.0;
+
+        let mut unmatched_old: Vec<&Tree<NixQueryEntry>> =
+            old_tree.children.iter().collect();
+        let mut children: Vec<Tree<DiffEntry>> = new_tree
+            .children
+            .iter()
+            .map(|new_child| {
+                let new_child_drv = &new_child.item.0;
+                let new_name_and_version =
+                    new_child_drv.package_name_and_version();
+                let option_pos = unmatched_old
+                    .iter()
+                    .position(|old_child| &old_child.item.0 == new_child_drv)
+                    .or_else(|| {
+                        unmatched_old.iter().position(|old_child| {
+                            old_child.item.0.package_name_and_version()
+                                == new_name_and_version
+                        })
+                    })
+                    .or_else(|| {
+                        unmatched_old.iter().position(|old_child| {
+                            old_child.item.0.package_name()
+                                == new_name_and_version.0
+                        })
+                    });
+                match option_pos {
+                    None => self.only(new_child, false),
+                    Some(pos) => {
+                        let old_child = unmatched_old.remove(pos);
+                        self.merge(old_child, new_child)
+                    }
+                }
+            })
+            .collect();
+        children.extend(
+            unmatched_old
+                .into_iter()
+                .map(|old_child| self.only(old_child, true)),
+        );
+
+        Tree::new(
+            DiffEntry {
+                old: Some(old_drv.clone()),
+                new: Some(new_drv.clone()),
+                recurse: new_tree.item.1,
+                kind: Self::pair_kind(old_drv, new_drv),
+            },
+            children,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_diff_merged_tree() {
+        let old_tree = NixQueryTree::from_str(indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            +---/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43
+            "
+        ))
+        .unwrap();
+        let new_tree = NixQueryTree::from_str(indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh
+            +---/nix/store/681354n3k44r8z90m35hm8945vsp95h1-glibc-2.30
+            "
+        ))
+        .unwrap();
+        let hello_drv: NixQueryDrv =
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10".into();
+        let old_glibc_drv: NixQueryDrv =
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27".into();
+        let new_glibc_drv: NixQueryDrv =
+            "/nix/store/681354n3k44r8z90m35hm8945vsp95h1-glibc-2.30".into();
+        let pcre_drv: NixQueryDrv =
+            "/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43".into();
+        let multiple_outputs_drv: NixQueryDrv =
+            "/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh"
+                .into();
+
+        let diff = NixQueryTreeDiff::new(&old_tree, &new_tree);
+
+        let actual_merged = Tree::new(
+            DiffEntry {
+                old: Some(hello_drv.clone()),
+                new: Some(hello_drv),
+                recurse: Recurse::No,
+                kind: DiffKind::Unchanged,
+            },
+            vec![
+                Tree::singleton(DiffEntry {
+                    old: None,
+                    new: Some(multiple_outputs_drv.clone()),
+                    recurse: Recurse::No,
+                    kind: DiffKind::Added,
+                }),
+                Tree::singleton(DiffEntry {
+                    old: Some(old_glibc_drv.clone()),
+                    new: Some(new_glibc_drv.clone()),
+                    recurse: Recurse::No,
+                    kind: DiffKind::VersionChanged,
+                }),
+                Tree::singleton(DiffEntry {
+                    old: Some(pcre_drv.clone()),
+                    new: None,
+                    recurse: Recurse::No,
+                    kind: DiffKind::Removed,
+                }),
+            ],
+        );
+
+        assert_eq!(diff.added, vec![multiple_outputs_drv]);
+        assert_eq!(diff.removed, vec![pcre_drv]);
+        assert_eq!(
+            diff.version_changes,
+            vec![VersionChange {
+                package_name: String::from("glibc"),
+                old: old_glibc_drv,
+                new: new_glibc_drv,
+            }]
+        );
+        assert_eq!(diff.merged, actual_merged);
+    }
+
+    #[test]
+    fn test_diff_same_version_rebuild() {
+        let old_tree = NixQueryTree::from_str(indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            "
+        ))
+        .unwrap();
+        let new_tree = NixQueryTree::from_str(indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/681354n3k44r8z90m35hm8945vsp95h1-glibc-2.27
+            "
+        ))
+        .unwrap();
+        let hello_drv: NixQueryDrv =
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10".into();
+        let old_glibc_drv: NixQueryDrv =
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27".into();
+        let new_glibc_drv: NixQueryDrv =
+            "/nix/store/681354n3k44r8z90m35hm8945vsp95h1-glibc-2.27".into();
+
+        let diff = NixQueryTreeDiff::new(&old_tree, &new_tree);
+
+        let actual_merged = Tree::new(
+            DiffEntry {
+                old: Some(hello_drv.clone()),
+                new: Some(hello_drv),
+                recurse: Recurse::No,
+                kind: DiffKind::Unchanged,
+            },
+            vec![Tree::singleton(DiffEntry {
+                old: Some(old_glibc_drv.clone()),
+                new: Some(new_glibc_drv.clone()),
+                recurse: Recurse::No,
+                kind: DiffKind::Rebuilt,
+            })],
+        );
+
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert!(diff.version_changes.is_empty());
+        assert_eq!(
+            diff.rebuilds,
+            vec![VersionChange {
+                package_name: String::from("glibc"),
+                old: old_glibc_drv,
+                new: new_glibc_drv,
+            }]
+        );
+        assert_eq!(diff.merged, actual_merged);
+        assert!(!diff.is_empty());
+    }
+
+    #[test]
+    fn test_diff_merged_root_kind() {
+        let old_tree = NixQueryTree::from_str(indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            "
+        ))
+        .unwrap();
+        let new_tree = NixQueryTree::from_str(indoc!(
+            "/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-hello-2.12
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            "
+        ))
+        .unwrap();
+
+        let diff = NixQueryTreeDiff::new(&old_tree, &new_tree);
+
+        assert_eq!(diff.merged.item.kind, DiffKind::VersionChanged);
+        assert_eq!(diff.merged.children[0].item.kind, DiffKind::Unchanged);
+
+        // Roots are always merged, even if their package names differ, so
+        // they are never shown as added.
+        let new_tree = NixQueryTree::from_str(indoc!(
+            "/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-world-1.0
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            "
+        ))
+        .unwrap();
+
+        let diff = NixQueryTreeDiff::new(&old_tree, &new_tree);
+
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.merged.item.kind, DiffKind::VersionChanged);
+    }
+
+    #[test]
+    fn test_diff_same_tree_is_empty() {
+        let tree = NixQueryTree::from_str(indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            "
+        ))
+        .unwrap();
+
+        assert!(NixQueryTreeDiff::new(&tree, &tree).is_empty());
+    }
+}
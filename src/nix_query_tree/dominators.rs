@@ -0,0 +1,286 @@
+use super::graph::{NixQueryGraph, NodeIndex};
+
+/// The dominator tree of a `NixQueryGraph`.
+///
+/// A node dominates another node if every chain of references from the root
+/// to the other node passes through it.  The immediate dominator of a node is
+/// the closest of its dominators, and is its parent in the dominator tree.
+/// For instance, a library that is only ever pulled in by one package has
+/// that package as its immediate dominator, even if it is referenced from
+/// several places inside that package's closure.
+///
+/// ```
+/// use indoc::indoc;
+/// use nix_query_tree_viewer::nix_query_tree::dominators::DominatorTree;
+/// use nix_query_tree_viewer::nix_query_tree::{NixQueryDrv, NixQueryTree};
+/// use std::str::FromStr;
+///
+/// let raw_tree = indoc!(
+///         "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+///         +---/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh
+///         |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+///         +---/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43
+///             +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+///         "
+///     );
+/// let graph = NixQueryTree::from_str(raw_tree).unwrap().graph();
+/// let dominator_tree = DominatorTree::new(&graph);
+/// let glibc_drv =
+///     NixQueryDrv::from("/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27");
+/// let glibc = graph.index(&glibc_drv).unwrap();
+///
+/// // glibc is reachable through two different packages, so only the root
+/// // dominates it.
+/// assert_eq!(dominator_tree.immediate_dominator(glibc), Some(graph.root()));
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DominatorTree {
+    immediate_dominators: Vec<Option<NodeIndex>>,
+    children: Vec<Vec<NodeIndex>>,
+}
+
+impl DominatorTree {
+    /// Compute the dominator tree of `graph`, rooted at `graph.root()`.
+    ///
+    /// This uses the iterative algorithm from "A Simple, Fast Dominance
+    /// Algorithm" by Cooper, Harvey and Kennedy.
+    pub fn new(graph: &NixQueryGraph) -> Self {
+        let len = graph.len();
+        let mut immediate_dominators: Vec<Option<NodeIndex>> = vec![None; len];
+        let mut children: Vec<Vec<NodeIndex>> = vec![vec![]; len];
+        if graph.is_empty() {
+            return DominatorTree {
+                immediate_dominators,
+                children,
+            };
+        }
+
+        let root = graph.root();
+        let post_order = post_order(graph);
+        let mut post_order_numbers: Vec<Option<usize>> = vec![None; len];
+        for (number, &node) in post_order.iter().enumerate() {
+            post_order_numbers[node] = Some(number);
+        }
+
+        let intersect = |immediate_dominators: &[Option<NodeIndex>],
+                         mut finger_a: NodeIndex,
+                         mut finger_b: NodeIndex| {
+            while finger_a != finger_b {
+                while post_order_numbers[finger_a]
+                    < post_order_numbers[finger_b]
+                {
+                    finger_a = immediate_dominators[finger_a]
+                        .expect("only processed nodes are intersected");
+                }
+                while post_order_numbers[finger_b]
+                    < post_order_numbers[finger_a]
+                {
+                    finger_b = immediate_dominators[finger_b]
+                        .expect("only processed nodes are intersected");
+                }
+            }
+            finger_a
+        };
+
+        immediate_dominators[root] = Some(root);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &node in post_order.iter().rev() {
+                if node == root {
+                    continue;
+                }
+                let new_immediate_dominator = graph
+                    .referrers(node)
+                    .iter()
+                    .copied()
+                    .filter(|&referrer| {
+                        referrer != node
+                            && immediate_dominators[referrer].is_some()
+                    })
+                    .fold(None, |option_dominator, referrer| {
+                        Some(match option_dominator {
+                            None => referrer,
+                            Some(dominator) => intersect(
+                                &immediate_dominators,
+                                referrer,
+                                dominator,
+                            ),
+                        })
+                    });
+                if new_immediate_dominator.is_some()
+                    && immediate_dominators[node] != new_immediate_dominator
+                {
+                    immediate_dominators[node] = new_immediate_dominator;
+                    changed = true;
+                }
+            }
+        }
+
+        // The root has no immediate dominator of its own.
+        immediate_dominators[root] = None;
+        for (node, option_dominator) in immediate_dominators.iter().enumerate()
+        {
+            if let Some(dominator) = option_dominator {
+                children[*dominator].push(node);
+            }
+        }
+
+        DominatorTree {
+            immediate_dominators,
+            children,
+        }
+    }
+
+    /// The root of the dominator tree, which is the root of the graph.
+    pub fn root(&self) -> NodeIndex {
+        0
+    }
+
+    /// The immediate dominator of `node`.  This is `None` for the root, and
+    /// for nodes that can't be reached from the root.
+    pub fn immediate_dominator(&self, node: NodeIndex) -> Option<NodeIndex> {
+        self.immediate_dominators.get(node).copied().flatten()
+    }
+
+    /// The nodes whose immediate dominator is `node`, in index order.
+    ///
+    /// * Panics
+    ///
+    /// This panics if `node` is not a node in the graph.
+    pub fn children(&self, node: NodeIndex) -> &[NodeIndex] {
+        &self.children[node]
+    }
+
+    /// Whether every chain of references from the root to `node` passes
+    /// through `dominator`.  Every node dominates itself.
+    pub fn dominates(&self, dominator: NodeIndex, node: NodeIndex) -> bool {
+        let mut curr = Some(node);
+        while let Some(curr_node) = curr {
+            if curr_node == dominator {
+                return true;
+            }
+            curr = self.immediate_dominator(curr_node);
+        }
+        false
+    }
+
+    /// Compute the retained size of every node, given the size of each node
+    /// on its own.
+    ///
+    /// The retained size of a node is the total size of all the nodes it
+    /// dominates, including itself.  This is how much would be removed from
+    /// the closure if the node were no longer referenced.
+    pub fn retained_sizes(&self, size: impl Fn(NodeIndex) -> u64) -> Vec<u64> {
+        let mut retained_sizes: Vec<u64> =
+            (0..self.children.len()).map(size).collect();
+        if self.children.is_empty() {
+            return retained_sizes;
+        }
+
+        // Children are summed up before their dominators by walking a
+        // pre-order of the dominator tree backwards.
+        let mut pre_order: Vec<NodeIndex> = vec![];
+        let mut stack: Vec<NodeIndex> = vec![self.root()];
+        while let Some(node) = stack.pop() {
+            pre_order.push(node);
+            stack.extend(self.children(node));
+        }
+        for &node in pre_order.iter().rev() {
+            if let Some(dominator) = self.immediate_dominator(node) {
+                retained_sizes[dominator] += retained_sizes[node];
+            }
+        }
+        retained_sizes
+    }
+}
+
+/// The nodes reachable from the root of `graph`, in post-order.
+fn post_order(graph: &NixQueryGraph) -> Vec<NodeIndex> {
+    let mut visited: Vec<bool> = vec![false; graph.len()];
+    let mut post_order: Vec<NodeIndex> = vec![];
+    // Each entry is a node and the index of its next reference to visit.
+    let mut stack: Vec<(NodeIndex, usize)> = vec![(graph.root(), 0)];
+    visited[graph.root()] = true;
+    while let Some((node, next_reference)) = stack.pop() {
+        match graph.references(node).get(next_reference) {
+            None => post_order.push(node),
+            Some(&reference) => {
+                stack.push((node, next_reference + 1));
+                if !visited[reference] {
+                    visited[reference] = true;
+                    stack.push((reference, 0));
+                }
+            }
+        }
+    }
+    post_order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::nix_query_tree::{NixQueryDrv, NixQueryTree};
+    use indoc::indoc;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_dominator_tree() {
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh
+            |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            |   |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            |   +---/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43
+            |       +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            +---/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23
+            |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+            "
+        );
+        let graph = NixQueryTree::from_str(raw_input).unwrap().graph();
+        let dominator_tree = DominatorTree::new(&graph);
+
+        let index = |raw_drv: &str| graph.index(&NixQueryDrv::from(raw_drv));
+        let hello = graph.root();
+        let multiple_outputs = index(
+            "/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh",
+        )
+        .unwrap();
+        let glibc =
+            index("/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27")
+                .unwrap();
+        let pcre =
+            index("/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43")
+                .unwrap();
+        let bash =
+            index("/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23")
+                .unwrap();
+
+        assert_eq!(dominator_tree.immediate_dominator(hello), None);
+        assert_eq!(
+            dominator_tree.immediate_dominator(multiple_outputs),
+            Some(hello)
+        );
+        assert_eq!(dominator_tree.immediate_dominator(glibc), Some(hello));
+        assert_eq!(dominator_tree.immediate_dominator(bash), Some(hello));
+        // pcre is only reachable through multiple-outputs.sh.
+        assert_eq!(
+            dominator_tree.immediate_dominator(pcre),
+            Some(multiple_outputs)
+        );
+        assert_eq!(dominator_tree.children(multiple_outputs), &[pcre]);
+        assert!(dominator_tree.dominates(hello, pcre));
+        assert!(!dominator_tree.dominates(glibc, pcre));
+
+        let sizes = |node: NodeIndex| (node as u64 + 1) * 10;
+        let retained_sizes = dominator_tree.retained_sizes(sizes);
+        assert_eq!(retained_sizes[hello], 150);
+        assert_eq!(
+            retained_sizes[multiple_outputs],
+            sizes(multiple_outputs) + sizes(pcre)
+        );
+        assert_eq!(retained_sizes[glibc], sizes(glibc));
+    }
+}
@@ -0,0 +1,290 @@
+use nom::branch::alt;
+use nom::bytes::complete::{is_not, tag, take, take_while1};
+use nom::character::complete::{char, multispace0};
+use nom::combinator::{all_consuming, map, opt};
+use nom::multi::{fold_many0, many0, separated_list};
+use nom::sequence::{
+    delimited, pair, preceded, separated_pair, terminated, tuple,
+};
+use nom::IResult;
+use std::path::Path;
+
+use super::closure::Closure;
+use super::store_path::DEFAULT_STORE_DIR;
+use super::{NixQueryDrv, NixQueryTree};
+
+/// A single statement in the body of a DOT graph.  Attributes like `label`
+/// and `color` are only there for drawing the graph, so they are thrown
+/// away.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum DotStatement {
+    Node(String),
+    Edge(String, String),
+}
+
+/// A piece of a quoted DOT ID, between escaped quotes.
+enum IdFragment<'a> {
+    Literal(&'a str),
+    Escaped(&'a str),
+}
+
+fn parse_quoted_id(input: &str) -> IResult<&str, String> {
+    delimited(
+        char('"'),
+        fold_many0(
+            alt((
+                map(is_not("\"\\"), IdFragment::Literal),
+                // Only `\"` is an escape sequence in DOT.  Every other
+                // backslash is kept as it is.
+                map(
+                    preceded(char('\\'), alt((tag("\""), take(1_usize)))),
+                    IdFragment::Escaped,
+                ),
+            )),
+            String::new(),
+            |mut id, fragment| {
+                match fragment {
+                    IdFragment::Literal(literal) => id.push_str(literal),
+                    IdFragment::Escaped("\"") => id.push('"'),
+                    IdFragment::Escaped(escaped) => {
+                        id.push('\\');
+                        id.push_str(escaped);
+                    }
+                }
+                id
+            },
+        ),
+        char('"'),
+    )(input)
+}
+
+fn parse_bare_id(input: &str) -> IResult<&str, String> {
+    map(
+        take_while1(|c: char| c.is_alphanumeric() || "_.-+#/".contains(c)),
+        String::from,
+    )(input)
+}
+
+fn parse_id(input: &str) -> IResult<&str, String> {
+    delimited(
+        multispace0,
+        alt((parse_quoted_id, parse_bare_id)),
+        multispace0,
+    )(input)
+}
+
+fn parse_attr_list(input: &str) -> IResult<&str, ()> {
+    map(
+        delimited(
+            char('['),
+            separated_list(
+                alt((char(','), char(';'))),
+                separated_pair(parse_id, char('='), parse_id),
+            ),
+            preceded(multispace0, char(']')),
+        ),
+        |_| (),
+    )(input)
+}
+
+fn parse_statement(input: &str) -> IResult<&str, DotStatement> {
+    map(
+        terminated(
+            pair(parse_id, opt(preceded(tag("->"), parse_id))),
+            tuple((
+                opt(parse_attr_list),
+                multispace0,
+                opt(char(';')),
+                multispace0,
+            )),
+        ),
+        |(id, option_target)| match option_target {
+            None => DotStatement::Node(id),
+            Some(target) => DotStatement::Edge(id, target),
+        },
+    )(input)
+}
+
+fn parse_graph(input: &str) -> IResult<&str, Vec<DotStatement>> {
+    delimited(
+        tuple((multispace0, tag("digraph"), opt(parse_id), multispace0)),
+        delimited(
+            pair(char('{'), multispace0),
+            many0(parse_statement),
+            char('}'),
+        ),
+        multispace0,
+    )(input)
+}
+
+/// Turn a DOT node ID into a store path.  Older versions of `nix-store` use
+/// the full store path as the ID, while newer ones only use the part after
+/// the store directory.
+fn id_to_drv(store_dir: &Path, id: &str) -> NixQueryDrv {
+    if Path::new(id).is_absolute() {
+        NixQueryDrv::from(id)
+    } else {
+        NixQueryDrv::from(&store_dir.join(id))
+    }
+}
+
+/// Parse the output of `nix-store --query --graph` into the `Closure` it
+/// describes, where IDs that aren't full paths are taken to be in
+/// `store_dir`.
+///
+/// Each edge in the output goes from a reference to the path referring to
+/// it.
+pub fn nix_query_dot_closure_parser_in<'a>(
+    input: &'a str,
+    store_dir: &Path,
+) -> Result<Closure, nom::Err<(&'a str, nom::error::ErrorKind)>> {
+    let (_, statements) = all_consuming(parse_graph)(input)?;
+    let mut closure = Closure::new();
+    for statement in statements {
+        match statement {
+            DotStatement::Node(id) => {
+                closure.insert_path(id_to_drv(store_dir, &id));
+            }
+            DotStatement::Edge(reference, referrer) => closure
+                .insert_reference(
+                    id_to_drv(store_dir, &referrer),
+                    id_to_drv(store_dir, &reference),
+                ),
+        }
+    }
+    Ok(closure)
+}
+
+/// Like `nix_query_dot_parser`, but for a store in `store_dir` instead of
+/// the default `/nix/store`.
+pub fn nix_query_dot_parser_in<'a>(
+    input: &'a str,
+    store_dir: &Path,
+) -> Result<NixQueryTree, nom::Err<(&'a str, nom::error::ErrorKind)>> {
+    let closure = nix_query_dot_closure_parser_in(input, store_dir)?;
+    match closure.roots().as_slice() {
+        [root] => Ok(closure.tree(root)),
+        _ => Err(nom::Err::Error((input, nom::error::ErrorKind::Verify))),
+    }
+}
+
+/// Parse the Graphviz DOT output of `nix-store --query --graph` into the
+/// `NixQueryTree` that `nix-store --query --tree` would have printed for the
+/// same path.
+///
+/// Unlike the tree output, the DOT output has an edge for every reference, so
+/// the tree can be rebuilt exactly.  The one thing that is lost is whether a
+/// path refers to itself, since `nix-store` leaves those edges out of the
+/// graph.  This fails if the graph doesn't have exactly one path that nothing
+/// else refers to.
+///
+/// ```
+/// use indoc::indoc;
+/// use nix_query_tree_viewer::nix_query_tree::dot::nix_query_dot_parser;
+/// use nix_query_tree_viewer::nix_query_tree::NixQueryTree;
+/// use std::str::FromStr;
+///
+/// let raw_dot = indoc!(
+///         r##"digraph G {
+///         "qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10" [label = "hello-2.10", shape = box, style = filled, fillcolor = "#ff0000"];
+///         "pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27" -> "qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10" [color = "black"];
+///         "pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27" [label = "glibc-2.27", shape = box, style = filled, fillcolor = "#ff0000"];
+///         }
+///         "##
+///     );
+/// let raw_tree = indoc!(
+///         "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+///         +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+///         "
+///     );
+///
+/// assert_eq!(
+///     nix_query_dot_parser(raw_dot),
+///     Ok(NixQueryTree::from_str(raw_tree).unwrap())
+/// );
+/// ```
+pub fn nix_query_dot_parser(
+    input: &str,
+) -> Result<NixQueryTree, nom::Err<(&str, nom::error::ErrorKind)>> {
+    nix_query_dot_parser_in(input, Path::new(DEFAULT_STORE_DIR))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_parse_id() {
+        assert_eq!(
+            parse_id(r#" "a\"b\lc" ,"#),
+            Ok((",", String::from(r#"a"b\lc"#)))
+        );
+        assert_eq!(parse_id("G {"), Ok(("{", String::from("G"))));
+    }
+
+    #[test]
+    fn test_nix_query_dot_parser() {
+        // The edges that a tree would hide behind `[...]` entries, like
+        // bash -> glibc, are all there in the graph.
+        let raw_input = indoc!(
+            r##"digraph G {
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10" [label = "hello-2.10", shape = box, style = filled, fillcolor = "#ff0000"];
+            "/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23" -> "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10" [color = "red"];
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27" -> "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10" [color = "green"];
+            "/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23" [label = "bash-4.4-p23", shape = box, style = filled, fillcolor = "#ff0000"];
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27" -> "/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23" [color = "blue"];
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27" [label = "glibc-2.27", shape = box, style = filled, fillcolor = "#ff0000"];
+            }
+            "##
+        );
+        let raw_tree = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            +---/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23
+                +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            "
+        );
+        let expected = NixQueryTree::from_str(raw_tree).unwrap();
+        let nix_query_tree = nix_query_dot_parser(raw_input).unwrap();
+
+        assert_eq!(nix_query_tree, expected);
+        assert_eq!(nix_query_tree.graph().edge_count(), 3);
+    }
+
+    #[test]
+    fn test_nix_query_dot_parser_in() {
+        let raw_input = indoc!(
+            r#"digraph G {
+            "pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27" -> "qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10"
+            }"#
+        );
+        let nix_query_tree =
+            nix_query_dot_parser_in(raw_input, Path::new("/tmp/store"))
+                .unwrap();
+
+        assert_eq!(
+            nix_query_tree.root(),
+            &NixQueryDrv::from(
+                "/tmp/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10"
+            )
+        );
+    }
+
+    #[test]
+    fn test_nix_query_dot_parser_errors() {
+        // Two paths that nothing refers to.
+        let two_roots = indoc!(
+            r#"digraph G {
+            "qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10";
+            "pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27";
+            }"#
+        );
+        assert!(nix_query_dot_parser(two_roots).is_err());
+        assert!(nix_query_dot_parser("digraph G {}").is_err());
+        assert!(nix_query_dot_parser("digraph G { \"a\" -> }").is_err());
+        assert!(nix_query_dot_parser("graph G { \"a\" }").is_err());
+    }
+}
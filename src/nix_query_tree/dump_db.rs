@@ -0,0 +1,180 @@
+use nom::bytes::complete::take_while;
+use nom::character::complete::char;
+use nom::combinator::{all_consuming, map, map_res, opt, verify};
+use nom::multi::{count, many0};
+use nom::sequence::{pair, terminated};
+use nom::IResult;
+
+use super::path_info::PathInfo;
+use super::NixQueryDrv;
+
+fn parse_line(input: &str) -> IResult<&str, &str> {
+    terminated(take_while(|c: char| c != '\n'), char('\n'))(input)
+}
+
+fn parse_path(input: &str) -> IResult<&str, NixQueryDrv> {
+    map(
+        verify(parse_line, |line: &str| line.starts_with('/')),
+        NixQueryDrv::from,
+    )(input)
+}
+
+/// The hash and size lines that `nix-store --dump-db` outputs, but
+/// `nix-store --register-validity` doesn't expect.  The hash is never empty
+/// and never starts with a `/`, which is how it is told apart from the
+/// deriver line that comes next.
+fn parse_hash_and_size(input: &str) -> IResult<&str, (&str, u64)> {
+    pair(
+        verify(parse_line, |line: &str| {
+            !line.is_empty() && !line.starts_with('/')
+        }),
+        map_res(parse_line, str::parse::<u64>),
+    )(input)
+}
+
+fn parse_registration(input: &str) -> IResult<&str, PathInfo> {
+    let (input, path) = parse_path(input)?;
+    let (input, option_hash_and_size) = opt(parse_hash_and_size)(input)?;
+    let (input, deriver) = parse_line(input)?;
+    let (input, reference_count) =
+        map_res(parse_line, str::parse::<usize>)(input)?;
+    let (input, references) = count(parse_path, reference_count)(input)?;
+
+    Ok((
+        input,
+        PathInfo {
+            path,
+            // The hash is written in base 16, without the algorithm in front
+            // of it.
+            nar_hash: option_hash_and_size
+                .map(|(hash, _)| format!("sha256:{}", hash)),
+            nar_size: option_hash_and_size.map(|(_, size)| size),
+            closure_size: None,
+            registration_time: None,
+            deriver: Some(deriver)
+                .filter(|deriver| !deriver.is_empty())
+                .map(NixQueryDrv::from),
+            signatures: vec![],
+            references,
+        },
+    ))
+}
+
+/// Parse the output of `nix-store --dump-db`, which has the references of
+/// every path in the store.
+///
+/// This is the format read by `nix-store --load-db`.  For each path it has
+/// a line with the path, a line with its hash, a line with its size, a line
+/// with its deriver (which is empty if the deriver isn't known), a line with
+/// the number of references, and then a line for each reference.  The input
+/// to `nix-store --register-validity`, which leaves out the hash and size
+/// lines, is accepted as well.
+///
+/// ```
+/// use indoc::indoc;
+/// use nix_query_tree_viewer::nix_query_tree::dump_db::nix_store_dump_db_parser;
+/// use nix_query_tree_viewer::nix_query_tree::NixQueryDrv;
+///
+/// let raw_dump = indoc!(
+///         "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+///         7b7f27a1e9c8eb5dd16ab34c6fb1d3b7d3fb0a2a1ac0b9c3b1e1c7e0e2d3f4a5
+///         206120
+///         /nix/store/jymg0kanmlgbcv35wxd8d660rw0fawhv-hello-2.10.drv
+///         1
+///         /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+///         "
+///     );
+/// let path_infos = nix_store_dump_db_parser(raw_dump).unwrap();
+///
+/// assert_eq!(path_infos.len(), 1);
+/// assert_eq!(path_infos[0].nar_size, Some(206120));
+/// assert_eq!(
+///     path_infos[0].references,
+///     vec![NixQueryDrv::from("/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27")]
+/// );
+/// ```
+pub fn nix_store_dump_db_parser(
+    input: &str,
+) -> Result<Vec<PathInfo>, nom::Err<(&str, nom::error::ErrorKind)>> {
+    all_consuming(many0(parse_registration))(input)
+        .map(|(_, path_infos)| path_infos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+
+    #[test]
+    fn test_nix_store_dump_db_parser() {
+        let hello: NixQueryDrv =
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10".into();
+        let glibc: NixQueryDrv =
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27".into();
+        let hello_drv: NixQueryDrv =
+            "/nix/store/jymg0kanmlgbcv35wxd8d660rw0fawhv-hello-2.10.drv".into();
+
+        let raw_dump = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            0a1b
+            206120
+            /nix/store/jymg0kanmlgbcv35wxd8d660rw0fawhv-hello-2.10.drv
+            2
+            /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            /nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            2c3d
+            28912304
+
+            0
+            "
+        );
+        assert_eq!(
+            nix_store_dump_db_parser(raw_dump),
+            Ok(vec![
+                PathInfo {
+                    path: hello.clone(),
+                    nar_hash: Some(String::from("sha256:0a1b")),
+                    nar_size: Some(206_120),
+                    closure_size: None,
+                    registration_time: None,
+                    deriver: Some(hello_drv),
+                    signatures: vec![],
+                    references: vec![glibc.clone(), hello],
+                },
+                PathInfo {
+                    path: glibc.clone(),
+                    nar_hash: Some(String::from("sha256:2c3d")),
+                    nar_size: Some(28_912_304),
+                    closure_size: None,
+                    registration_time: None,
+                    deriver: None,
+                    signatures: vec![],
+                    references: vec![],
+                },
+            ])
+        );
+
+        let raw_registration = indoc!(
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+
+            1
+            /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            "
+        );
+        let path_infos = nix_store_dump_db_parser(raw_registration).unwrap();
+        assert_eq!(path_infos[0].nar_size, None);
+        assert_eq!(path_infos[0].references, vec![glibc]);
+
+        assert_eq!(nix_store_dump_db_parser(""), Ok(vec![]));
+        // Fewer references than the count says there are.
+        assert!(nix_store_dump_db_parser(indoc!(
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+
+            1
+            "
+        ))
+        .is_err());
+    }
+}
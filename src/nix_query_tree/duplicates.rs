@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+
+use super::{NixQueryDrv, NixQueryTree};
+
+/// A package that appears in a closure as more than one distinct store path.
+///
+/// This is either several versions of the same package, or the same version
+/// with different hashes, for instance because of an override.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DuplicateGroup {
+    /// The package name shared by every variant.  See `StorePath::pname`.
+    pub package_name: String,
+
+    /// The output shared by every variant, like `lib`.  This is `None` for
+    /// the default output.
+    pub output: Option<String>,
+
+    /// Every distinct store path for this package, sorted by version and
+    /// then by hash.
+    pub variants: Vec<NixQueryDrv>,
+}
+
+/// Find every package in the tree that has more than one distinct store
+/// path.
+///
+/// Store paths are grouped by package name and output, so `gcc-7.4.0` and
+/// `gcc-7.4.0-lib` are not counted as duplicates of each other.  `.drv` files
+/// are grouped separately from outputs, and paths that can't be parsed as a
+/// `StorePath` are ignored.  Groups are sorted by package name.
+///
+/// ```
+/// use indoc::indoc;
+/// use nix_query_tree_viewer::nix_query_tree::duplicates::find_duplicates;
+/// use nix_query_tree_viewer::nix_query_tree::NixQueryTree;
+/// use std::str::FromStr;
+///
+/// let raw_tree = indoc!(
+///         "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+///         +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+///         +---/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh
+///             +---/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-glibc-2.30
+///             +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+///         "
+///     );
+/// let nix_query_tree = NixQueryTree::from_str(raw_tree).unwrap();
+/// let duplicates = find_duplicates(&nix_query_tree);
+///
+/// assert_eq!(duplicates.len(), 1);
+/// assert_eq!(duplicates[0].package_name, "glibc");
+/// assert_eq!(duplicates[0].variants.len(), 2);
+/// ```
+pub fn find_duplicates(nix_query_tree: &NixQueryTree) -> Vec<DuplicateGroup> {
+    let mut groups: BTreeMap<(String, Option<String>, bool), Vec<NixQueryDrv>> =
+        BTreeMap::new();
+
+    for (_, nix_query_entry) in nix_query_tree.0.iter_pre_order() {
+        let drv: &NixQueryDrv = &nix_query_entry.0;
+        if let Ok(store_path) = drv.store_path() {
+            let variants = groups
+                .entry((store_path.pname, store_path.output, store_path.is_drv))
+                .or_default();
+            if !variants.contains(drv) {
+                variants.push(drv.clone());
+            }
+        }
+    }
+
+    groups
+        .into_iter()
+        .filter(|(_, variants)| variants.len() > 1)
+        .map(|((package_name, output, _), mut variants)| {
+            variants.sort_by(|drv_a, drv_b| {
+                drv_a.cmp_version(drv_b).then_with(|| drv_a.cmp_hash(drv_b))
+            });
+            DuplicateGroup {
+                package_name,
+                output,
+                variants,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_find_duplicates() {
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            +---/nix/store/hlnxw4k6931bachvg5sv0cyaissimswb-gcc-7.4.0-lib
+            +---/nix/store/ghzg4kg0sjif58smj2lfm2bdvjwim85y-gcc-7.4.0
+            +---/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh
+                +---/nix/store/681354n3k44r8z90m35hm8945vsp95h1-glibc-2.27
+                +---/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-glibc-2.10
+                +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            "
+        );
+        let nix_query_tree = NixQueryTree::from_str(raw_input).unwrap();
+
+        let glibc_2_10: NixQueryDrv =
+            "/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-glibc-2.10".into();
+        let glibc_2_27_a: NixQueryDrv =
+            "/nix/store/681354n3k44r8z90m35hm8945vsp95h1-glibc-2.27".into();
+        let glibc_2_27_b: NixQueryDrv =
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27".into();
+
+        assert_eq!(
+            find_duplicates(&nix_query_tree),
+            vec![DuplicateGroup {
+                package_name: String::from("glibc"),
+                output: None,
+                variants: vec![glibc_2_10, glibc_2_27_a, glibc_2_27_b],
+            }]
+        );
+    }
+}
@@ -1,8 +1,19 @@
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Output};
 
+use super::dominators::DominatorTree;
+use super::dump_db;
+use super::duplicates::{self, DuplicateGroup};
+use super::graph::{NixQueryGraph, NodeIndex};
+use super::parse_err::ParseErr;
 use super::parsing;
-use super::{NixQueryEntry, NixQueryPathMap, NixQueryTree};
+use super::path_info::{self, PathInfo};
+use super::stats::NixQueryTreeStats;
+use super::why_depends::{self, Excerpts, WhyDepends};
+use super::{
+    NixQueryDrv, NixQueryEntry, NixQueryPathMap, NixQueryTree, Recurse,
+};
 use crate::tree;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -10,43 +21,507 @@ pub enum NixStoreErr {
     CommandErr(String),
     Utf8Err(String),
     NixStoreErr(String),
-    ParseErr(String),
+    /// `raw` is the whole text that couldn't be parsed, so that it can be
+    /// shown with the line `parse_err` is on highlighted.
+    ParseErr {
+        raw: String,
+        parse_err: ParseErr,
+    },
+}
+
+impl NixStoreErr {
+    fn parse_err(raw: &str, parse_err: ParseErr) -> Self {
+        NixStoreErr::ParseErr {
+            raw: String::from(raw),
+            parse_err,
+        }
+    }
+
+    /// A `ParseErr` for the point in `raw` where a nom parser stopped.
+    fn nom_parse_err(
+        raw: &str,
+        nom_err: &nom::Err<(&str, nom::error::ErrorKind)>,
+    ) -> Self {
+        NixStoreErr::parse_err(raw, ParseErr::new(raw, nom_err))
+    }
+
+    /// A `ParseErr` for the error `serde_json` returned for `raw`.
+    fn json_parse_err(raw: &str, json_err: &serde_json::Error) -> Self {
+        NixStoreErr::parse_err(raw, ParseErr::from_json(raw, json_err))
+    }
 }
 
 impl std::fmt::Display for NixStoreErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let string = match self {
-            NixStoreErr::CommandErr(string) => string,
-            NixStoreErr::Utf8Err(string) => string,
-            NixStoreErr::NixStoreErr(string) => string,
-            NixStoreErr::ParseErr(string) => string,
-        };
-        write!(f, "{}", string)
+        match self {
+            NixStoreErr::CommandErr(string) => write!(f, "{}", string),
+            NixStoreErr::Utf8Err(string) => write!(f, "{}", string),
+            NixStoreErr::NixStoreErr(string) => write!(f, "{}", string),
+            NixStoreErr::ParseErr { parse_err, .. } => {
+                write!(f, "{}", parse_err)
+            }
+        }
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+/// The result of successfully running `nix-store --query --tree`.
+///
+/// A `NixStoreRes` can also be built up lazily with `NixStoreRes::new_lazy`
+/// and `NixStoreRes::insert_references`, running `nix-store --query
+/// --references` only for the paths that are actually looked at.  `unloaded`
+/// holds every `NixQueryDrv` in the tree whose references haven't been
+/// loaded yet.  It is always empty for a `NixStoreRes` created with
+/// `NixStoreRes::new`.
+///
+/// `path_infos` holds the metadata from `nix path-info` for each path in the
+/// tree, once it has been loaded with `run_path_info`.  It is kept here
+/// instead of in each `NixQueryEntry`, since every instance of a path has
+/// the same metadata.
+///
+/// `invalid_paths` holds every path in the tree that
+/// `nix-store --check-validity` reported as missing or invalid in the local
+/// store, once it has been run with `run_check_validity`.
+///
+/// `excerpts` holds the excerpts shown by `nix why-depends --precise` for
+/// each reference, for a `NixStoreRes` created with
+/// `NixStoreRes::from_why_depends`.
+///
+/// `dominator_tree` is the dominator tree of `graph`.  `retained_sizes` holds
+/// the retained size of each node in `graph`, computed from the NAR sizes in
+/// `path_infos`.  It is empty until `path_infos` has been loaded.  Both are
+/// left out of date by `insert_references`, until `update_dominators` is
+/// called.
+///
+/// This is serialized as an object with the raw output of `nix-store` and
+/// the parsed tree.  `map` and `graph` are
+/// not serialized, since they are rebuilt from `tree` when deserializing.
+/// `dominator_tree`, `unloaded`, `path_infos`, `retained_sizes`,
+/// `invalid_paths` and `excerpts` are not serialized either:
+///
+/// ```json
+/// {
+///   "raw": "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10\n...",
+///   "tree": {
+///     "item": { "path": "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10", "recurse": false },
+///     "children": [ ... ]
+///   }
+/// }
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize)]
+#[serde(from = "SerdeNixStoreRes")]
 pub struct NixStoreRes {
     pub raw: String,
     pub tree: NixQueryTree,
     pub map: NixQueryPathMap,
+    pub graph: NixQueryGraph,
+    pub dominator_tree: Option<DominatorTree>,
+    pub unloaded: HashSet<NixQueryDrv>,
+    pub path_infos: HashMap<NixQueryDrv, PathInfo>,
+    pub retained_sizes: Vec<u64>,
+    pub invalid_paths: HashSet<NixQueryDrv>,
+    pub excerpts: Excerpts,
+}
+
+/// The serialized form of a `NixStoreRes`.
+#[derive(serde::Deserialize)]
+struct SerdeNixStoreRes {
+    raw: String,
+    tree: NixQueryTree,
+}
+
+impl From<SerdeNixStoreRes> for NixStoreRes {
+    fn from(res: SerdeNixStoreRes) -> NixStoreRes {
+        NixStoreRes::new(&res.raw, res.tree)
+    }
+}
+
+impl serde::Serialize for NixStoreRes {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("NixStoreRes", 2)?;
+        state.serialize_field("raw", &self.raw)?;
+        state.serialize_field("tree", &self.tree)?;
+        state.end()
+    }
 }
 
 impl NixStoreRes {
     pub fn new(raw: &str, tree: NixQueryTree) -> Self {
         let map: NixQueryPathMap = tree.path_map();
+        let graph: NixQueryGraph = NixQueryGraph::new(&tree, &map);
+        let dominator_tree = DominatorTree::new(&graph);
         NixStoreRes {
             raw: String::from(raw),
             tree,
             map,
+            graph,
+            dominator_tree: Some(dominator_tree),
+            unloaded: HashSet::new(),
+            path_infos: HashMap::new(),
+            retained_sizes: vec![],
+            invalid_paths: HashSet::new(),
+            excerpts: Excerpts::new(),
+        }
+    }
+
+    /// Create a `NixStoreRes` from the output of `nix path-info --json
+    /// --recursive`, with the metadata of every path already loaded.  See
+    /// `path_info::path_info_tree_parser`.
+    pub fn from_path_info(raw: &str) -> Result<Self, NixStoreErr> {
+        let (tree, path_infos) = path_info::path_info_tree_parser(raw)
+            .map_err(|json_err| NixStoreErr::json_parse_err(raw, &json_err))?;
+        let mut nix_store_res = NixStoreRes::new(raw, tree);
+        nix_store_res.insert_path_infos(path_infos);
+        Ok(nix_store_res)
+    }
+
+    /// Create a `NixStoreRes` from the output of `nix why-depends`, keeping
+    /// the excerpts shown with `--precise`.
+    pub fn from_why_depends(raw: &str, why_depends: WhyDepends) -> Self {
+        let mut nix_store_res = NixStoreRes::new(raw, why_depends.tree);
+        nix_store_res.excerpts = why_depends.excerpts;
+        nix_store_res
+    }
+
+    /// The excerpts of the files in `referrer` that refer to `reference`,
+    /// from `nix why-depends --precise`.
+    pub fn excerpts(
+        &self,
+        referrer: &NixQueryDrv,
+        reference: &NixQueryDrv,
+    ) -> &[String] {
+        why_depends::lookup_excerpts(&self.excerpts, referrer, reference)
+    }
+
+    /// Create a `NixStoreRes` for `root` without running `nix-store`, from
+    /// the paths in its closure and the references of each of them.
+    ///
+    /// `requisites` is the output of `nix-store --query --requisites`, and
+    /// `path_infos` has the references of at least those paths, as in the
+    /// output of `nix-store --dump-db`.  The tree is rebuilt in the same
+    /// order that `nix-store --query --tree` would print it, and the
+    /// metadata in `path_infos` is loaded as well.  `raw` is used as the raw
+    /// output.
+    pub fn from_requisites(
+        raw: &str,
+        root: &NixQueryDrv,
+        requisites: &[NixQueryDrv],
+        path_infos: Vec<PathInfo>,
+    ) -> Result<Self, NixStoreErr> {
+        let requisite_set: HashSet<&NixQueryDrv> = requisites.iter().collect();
+        if !requisite_set.contains(root) {
+            return Err(NixStoreErr::NixStoreErr(format!(
+                "{} is not one of the requisites",
+                root
+            )));
+        }
+        let path_infos: Vec<PathInfo> = path_infos
+            .into_iter()
+            .filter(|path_info| requisite_set.contains(&path_info.path))
+            .collect();
+        let known_paths: HashSet<&NixQueryDrv> =
+            path_infos.iter().map(|path_info| &path_info.path).collect();
+        if let Some(unknown_path) = requisites
+            .iter()
+            .find(|requisite| !known_paths.contains(requisite))
+        {
+            return Err(NixStoreErr::NixStoreErr(format!(
+                "the references of {} are not known",
+                unknown_path
+            )));
+        }
+
+        let tree = path_info::path_infos_closure(&path_infos).tree(root);
+        let mut nix_store_res = NixStoreRes::new(raw, tree);
+        nix_store_res.insert_path_infos(path_infos);
+        Ok(nix_store_res)
+    }
+
+    /// Create a `NixStoreRes` with only `root` in it, whose references
+    /// haven't been loaded yet.
+    pub fn new_lazy(root: NixQueryDrv) -> Self {
+        let mut nix_store_res = NixStoreRes::new(
+            "",
+            NixQueryTree(tree::Tree::singleton(NixQueryEntry(
+                root.clone(),
+                Recurse::No,
+            ))),
+        );
+        nix_store_res.unloaded.insert(root);
+        nix_store_res
+    }
+
+    /// Add metadata from `nix path-info`, replacing any metadata already
+    /// loaded for the same paths.
+    pub fn insert_path_infos(&mut self, path_infos: Vec<PathInfo>) {
+        for path_info in path_infos {
+            self.path_infos.insert(path_info.path.clone(), path_info);
+        }
+        self.update_retained_sizes();
+    }
+
+    /// Compute `dominator_tree` and `retained_sizes` if they are out of date.
+    ///
+    /// `insert_references` doesn't do this itself, since that would walk the
+    /// whole closure every time a single path is expanded.
+    pub fn update_dominators(&mut self) {
+        if self.dominator_tree.is_none() {
+            self.dominator_tree = Some(DominatorTree::new(&self.graph));
+            self.update_retained_sizes();
+        }
+    }
+
+    /// Recompute `retained_sizes` from `dominator_tree` and `path_infos`.
+    /// Paths without a known NAR size count as empty.
+    fn update_retained_sizes(&mut self) {
+        let dominator_tree = match &self.dominator_tree {
+            Some(dominator_tree) if !self.path_infos.is_empty() => {
+                dominator_tree
+            }
+            _ => {
+                self.retained_sizes = vec![];
+                return;
+            }
+        };
+
+        let graph = &self.graph;
+        let path_infos = &self.path_infos;
+        self.retained_sizes = dominator_tree.retained_sizes(|node| {
+            graph
+                .node(node)
+                .and_then(|nix_query_drv| path_infos.get(nix_query_drv))
+                .and_then(|path_info| path_info.nar_size)
+                .unwrap_or(0)
+        });
+    }
+
+    /// The retained size of `nix_query_drv`: the total NAR size of every path
+    /// that is only in the closure because of it, including itself.  This is
+    /// how much the closure would shrink if nothing referenced
+    /// `nix_query_drv` anymore.
+    ///
+    /// This is `None` until the metadata from `nix path-info` has been
+    /// loaded, and while the dominators are out of date (see
+    /// `update_dominators`).
+    pub fn retained_size(&self, nix_query_drv: &NixQueryDrv) -> Option<u64> {
+        let index = self.graph.index(nix_query_drv)?;
+        self.retained_sizes.get(index).copied()
+    }
+
+    /// The metadata from `nix path-info` for `nix_query_drv`, if it has been
+    /// loaded.
+    pub fn path_info(&self, nix_query_drv: &NixQueryDrv) -> Option<&PathInfo> {
+        self.path_infos.get(nix_query_drv)
+    }
+
+    /// Record the paths that `nix-store --check-validity` reported as missing
+    /// or invalid.
+    pub fn insert_invalid_paths(&mut self, invalid_paths: Vec<NixQueryDrv>) {
+        self.invalid_paths.extend(invalid_paths);
+    }
+
+    /// Whether `nix_query_drv` is missing or invalid in the local store.
+    ///
+    /// This is `false` for every path until the validity of the paths has
+    /// been checked.
+    pub fn is_invalid(&self, nix_query_drv: &NixQueryDrv) -> bool {
+        self.invalid_paths.contains(nix_query_drv)
+    }
+
+    /// Whether the references of `nix_query_drv` have been loaded.
+    pub fn is_loaded(&self, nix_query_drv: &NixQueryDrv) -> bool {
+        !self.unloaded.contains(nix_query_drv)
+    }
+
+    /// Add the references of the `NixQueryDrv` at `path` to the tree, from the
+    /// output of `nix-store --query --references`.
+    ///
+    /// Just like with `nix-store --query --tree`, a reference whose own
+    /// references have already been loaded is added as a `[...]` entry.
+    /// Every other instance of the `NixQueryDrv` at `path` is turned into a
+    /// `[...]` entry, and their `tree::Path`s are returned.
+    ///
+    /// This returns `None` and does nothing if the entry at `path` is not
+    /// `nix_query_drv`, or its references have already been loaded.
+    ///
+    /// `map` and `graph` are only updated for the new entries, and
+    /// `dominator_tree` and `retained_sizes` are left out of date.  See
+    /// `update_dominators`.
+    pub fn insert_references(
+        &mut self,
+        path: &tree::Path,
+        nix_query_drv: &NixQueryDrv,
+        nix_store_references: &NixStoreReferences,
+    ) -> Option<Vec<tree::Path>> {
+        if self.tree.lookup(path.clone())?.0 != *nix_query_drv
+            || !self.unloaded.remove(nix_query_drv)
+        {
+            return None;
+        }
+
+        let mut children: Vec<tree::Tree<NixQueryEntry>> = vec![];
+        for reference in &nix_store_references.references {
+            let already_loaded = self.map.lookup_first(reference).is_some()
+                && self.is_loaded(reference);
+            let recurse = if already_loaded || reference == nix_query_drv {
+                Recurse::Yes
+            } else {
+                self.unloaded.insert(reference.clone());
+                Recurse::No
+            };
+            children.push(tree::Tree::singleton(NixQueryEntry(
+                reference.clone(),
+                recurse,
+            )));
+        }
+
+        let other_paths: Vec<tree::Path> = self
+            .map
+            .lookup_all(nix_query_drv)
+            .iter()
+            .filter(|&other_path| other_path != path)
+            .cloned()
+            .collect();
+        for other_path in &other_paths {
+            if let Some(other_tree) =
+                self.tree.0.lookup_tree_mut(other_path.clone())
+            {
+                other_tree.item.1 = Recurse::Yes;
+            }
+        }
+        for (i, child) in children.iter().enumerate() {
+            let mut child_path = path.clone();
+            child_path.push_back(i);
+            self.map.insert(child.item.0.clone(), child_path);
         }
+        if let Some(tree) = self.tree.0.lookup_tree_mut(path.clone()) {
+            tree.children = children;
+        }
+
+        self.raw.push_str(&nix_store_references.raw);
+        self.graph
+            .insert_references(nix_query_drv, &nix_store_references.references);
+        self.dominator_tree = None;
+        self.retained_sizes = vec![];
+
+        Some(other_paths)
+    }
+
+    /// Find the instance of `nix_query_drv` that has its references as
+    /// children.  See `NixQueryTree::lookup_expanded`.
+    pub fn lookup_expanded(
+        &self,
+        nix_query_drv: &NixQueryDrv,
+    ) -> Option<&tree::Path> {
+        self.tree.lookup_expanded(&self.map, nix_query_drv)
     }
 
     pub fn lookup_first_query_entry(
         &self,
         nix_query_entry: &NixQueryEntry,
     ) -> Option<&tree::Path> {
-        self.map.lookup_first(&nix_query_entry.0)
+        self.lookup_expanded(&nix_query_entry.0)
+    }
+
+    /// Compute summary statistics for the closure.
+    pub fn stats(&self) -> NixQueryTreeStats {
+        NixQueryTreeStats::new(&self.tree, &self.graph)
+    }
+
+    /// Find the packages with more than one distinct store path in the
+    /// closure.  See `duplicates::find_duplicates`.
+    pub fn duplicates(&self) -> Vec<DuplicateGroup> {
+        duplicates::find_duplicates(&self.tree)
+    }
+
+    /// Every `NixQueryDrv` that directly references the given `NixQueryDrv`.
+    ///
+    /// This returns an empty `Vec` for any `NixQueryDrv` that isn't in the
+    /// tree.
+    pub fn referrers(&self, nix_query_drv: &NixQueryDrv) -> Vec<&NixQueryDrv> {
+        match self.graph.index(nix_query_drv) {
+            None => vec![],
+            Some(index) => self
+                .graph
+                .referrers(index)
+                .iter()
+                .filter_map(|&referrer| self.graph.node(referrer))
+                .collect(),
+        }
+    }
+
+    /// Find a shortest chain of references from the root to `target`.  See
+    /// `NixQueryGraph::shortest_chain`.
+    pub fn shortest_chain(
+        &self,
+        target: &NixQueryDrv,
+    ) -> Option<Vec<&NixQueryDrv>> {
+        let target_index = self.graph.index(target)?;
+        self.graph
+            .shortest_chain(target_index)
+            .map(|chain| self.chain_drvs(&chain))
+    }
+
+    /// Find up to `limit` distinct chains of references from the root to
+    /// `target`.  See `NixQueryGraph::all_chains`.
+    pub fn all_chains(
+        &self,
+        target: &NixQueryDrv,
+        limit: usize,
+    ) -> Vec<Vec<&NixQueryDrv>> {
+        match self.graph.index(target) {
+            None => vec![],
+            Some(target_index) => self
+                .graph
+                .all_chains(target_index, limit)
+                .iter()
+                .map(|chain| self.chain_drvs(chain))
+                .collect(),
+        }
+    }
+
+    fn chain_drvs(&self, chain: &[NodeIndex]) -> Vec<&NixQueryDrv> {
+        chain
+            .iter()
+            .filter_map(|&index| self.graph.node(index))
+            .collect()
+    }
+
+    /// Turn a chain of references starting at the root into the `tree::Path`s
+    /// that make up that chain in the tree.
+    ///
+    /// Whenever the chain goes through a `[...]` entry, it continues from the
+    /// expanded instance of that entry, since that is the only instance that
+    /// has children in the tree.
+    pub fn chain_paths(&self, chain: &[&NixQueryDrv]) -> Vec<tree::Path> {
+        let mut paths: Vec<tree::Path> = vec![];
+        if let Some(first) = chain.first() {
+            if self.tree.0.item.0 == **first {
+                paths.push(tree::Path::new());
+            }
+        }
+        for pair in chain.windows(2) {
+            let (referrer, reference) = (pair[0], pair[1]);
+            let option_path = self.lookup_expanded(referrer).and_then(|path| {
+                let referrer_tree = self.tree.0.lookup_tree(path.clone())?;
+                let child_index = referrer_tree
+                    .children
+                    .iter()
+                    .position(|child| child.item.0 == *reference)?;
+                let mut child_path = path.clone();
+                child_path.push_back(child_index);
+                Some(child_path)
+            });
+            match option_path {
+                None => break,
+                Some(path) => paths.push(path),
+            }
+        }
+        paths
     }
 }
 
@@ -68,28 +543,592 @@ impl ExecNixStoreRes {
     }
 }
 
-fn nix_store_res(nix_store_path: &Path) -> Result<NixStoreRes, NixStoreErr> {
+/// Files with the flat reference listings to build a tree from, instead of
+/// running `nix-store --query --tree`.  See `NixStoreRes::from_requisites`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReferencesFiles {
+    /// The output of `nix-store --query --requisites`.
+    pub requisites: PathBuf,
+    /// The output of `nix-store --dump-db`, or anything else in the same
+    /// format.
+    pub references: PathBuf,
+}
+
+/// The result of successfully running `nix-store --query --references`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NixStoreReferences {
+    pub raw: String,
+    pub references: Vec<NixQueryDrv>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExecNixStoreReferencesRes {
+    pub nix_query_drv: NixQueryDrv,
+    pub res: Result<NixStoreReferences, NixStoreErr>,
+}
+
+/// The result of running `nix-store --query --deriver` or `nix-store --query
+/// --outputs` for `nix_query_drv`, to find the path to switch to.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExecNixStoreSwitchRes {
+    pub query_flag: &'static str,
+    pub nix_query_drv: NixQueryDrv,
+    pub res: Result<NixQueryDrv, NixStoreErr>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExecNixPathInfoRes {
+    pub nix_query_drv: NixQueryDrv,
+    pub res: Result<Vec<PathInfo>, NixStoreErr>,
+}
+
+/// The result of running `nix-store --check-validity --print-invalid` for
+/// paths in the tree rooted at `nix_query_drv`.  `res` holds the paths that
+/// are missing or invalid.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExecNixStoreValidityRes {
+    pub nix_query_drv: NixQueryDrv,
+    pub res: Result<Vec<NixQueryDrv>, NixStoreErr>,
+}
+
+/// The maximum number of paths passed to a single run of
+/// `nix-store --check-validity` or `nix path-info`, to stay well under the
+/// limit on the length of a command line.
+const PATHS_CHUNK_SIZE: usize = 1000;
+
+/// What `nix-store --query --deriver` outputs for a path without a known
+/// deriver, like a path added with `nix-store --add`.
+const UNKNOWN_DERIVER: &str = "unknown-deriver";
+
+/// Run `nix-store --query` with the given arguments, returning its stdout.
+///
+/// `store_dir` is passed to `nix-store` as `NIX_STORE_DIR`.
+fn nix_store_query(
+    store_dir: &Path,
+    args: &[&str],
+) -> Result<String, NixStoreErr> {
     let nix_store_output: Output = Command::new("nix-store")
-        .args(&["--query", "--tree", &nix_store_path.to_string_lossy()])
+        .env("NIX_STORE_DIR", store_dir)
+        .arg("--query")
+        .args(args)
         .output()
         .map_err(|io_err| NixStoreErr::CommandErr(io_err.to_string()))?;
 
     if nix_store_output.status.success() {
-        let stdout = from_utf8(nix_store_output.stdout)?;
-        parsing::nix_query_tree_parser(&stdout)
-            .map(|nix_query_tree| NixStoreRes::new(&stdout, nix_query_tree))
-            .map_err(|nom_err| NixStoreErr::ParseErr(nom_err.to_string()))
+        from_utf8(nix_store_output.stdout)
     } else {
         let stderr = from_utf8(nix_store_output.stderr)?;
         Err(NixStoreErr::NixStoreErr(stderr))
     }
 }
 
-/// Run `nix-store --query --tree` for the given nix store path.
-pub fn run(nix_store_path: &Path) -> ExecNixStoreRes {
+/// Run `nix path-info --json` with the given flags for `nix_query_drvs`.
+///
+/// `nix path-info` is part of the experimental `nix` command.  Nix 2.3 runs
+/// it as is but rejects `--extra-experimental-features`, while newer
+/// versions only run it with the `nix-command` feature enabled.  So it is
+/// first run without the flag, and only run again with it if Nix complains
+/// that `nix-command` is disabled.  The flag adds to any `NIX_CONFIG` the
+/// user has set instead of replacing it.
+fn nix_path_info(
+    store_dir: &Path,
+    flags: &[&str],
+    nix_query_drvs: &[NixQueryDrv],
+) -> Result<Vec<PathInfo>, NixStoreErr> {
+    let stdout = nix_path_info_output(store_dir, &[], flags, nix_query_drvs)
+        .or_else(|nix_store_err| match nix_store_err {
+            NixStoreErr::NixStoreErr(ref stderr)
+                if stderr.contains("nix-command") =>
+            {
+                nix_path_info_output(
+                    store_dir,
+                    &["--extra-experimental-features", "nix-command"],
+                    flags,
+                    nix_query_drvs,
+                )
+            }
+            _ => Err(nix_store_err),
+        })?;
+    path_info::path_info_parser(&stdout)
+        .map_err(|json_err| NixStoreErr::json_parse_err(&stdout, &json_err))
+}
+
+/// Run `nix path-info --json`, returning its stdout.  `nix_flags` are
+/// passed to `nix` itself, before the `path-info` subcommand.
+fn nix_path_info_output(
+    store_dir: &Path,
+    nix_flags: &[&str],
+    flags: &[&str],
+    nix_query_drvs: &[NixQueryDrv],
+) -> Result<String, NixStoreErr> {
+    let nix_output: Output = Command::new("nix")
+        .env("NIX_STORE_DIR", store_dir)
+        .args(nix_flags)
+        .args(["path-info", "--json"])
+        .args(flags)
+        .args(
+            nix_query_drvs
+                .iter()
+                .map(|nix_query_drv| nix_query_drv.as_os_str()),
+        )
+        .output()
+        .map_err(|io_err| NixStoreErr::CommandErr(io_err.to_string()))?;
+
+    if nix_output.status.success() {
+        from_utf8(nix_output.stdout)
+    } else {
+        let stderr = from_utf8(nix_output.stderr)?;
+        Err(NixStoreErr::NixStoreErr(stderr))
+    }
+}
+
+/// Get just the size of each of `nix_query_drvs` with
+/// `nix-store --query --size`.  This is the fallback for when `nix path-info`
+/// can't be run at all, so the rest of the metadata is left out.
+fn nix_store_sizes(
+    store_dir: &Path,
+    nix_query_drvs: &[NixQueryDrv],
+) -> Result<Vec<PathInfo>, NixStoreErr> {
+    let mut path_infos: Vec<PathInfo> = vec![];
+    for chunk in nix_query_drvs.chunks(PATHS_CHUNK_SIZE) {
+        let paths: Vec<String> = chunk
+            .iter()
+            .map(|nix_query_drv| nix_query_drv.to_string_lossy().into_owned())
+            .collect();
+        let mut args: Vec<&str> = vec!["--size"];
+        args.extend(paths.iter().map(String::as_str));
+        let stdout = nix_store_query(store_dir, &args)?;
+        path_infos.extend(nix_store_sizes_parser(chunk, &stdout)?);
+    }
+    Ok(path_infos)
+}
+
+/// Parse the output of `nix-store --query --size` for `nix_query_drvs`,
+/// which is the size of each path on its own line, in the same order.
+fn nix_store_sizes_parser(
+    nix_query_drvs: &[NixQueryDrv],
+    stdout: &str,
+) -> Result<Vec<PathInfo>, NixStoreErr> {
+    let lines: Vec<&str> = stdout.lines().collect();
+    if lines.len() != nix_query_drvs.len() {
+        return Err(NixStoreErr::NixStoreErr(format!(
+            "`nix-store --query --size` output {} sizes for {} paths",
+            lines.len(),
+            nix_query_drvs.len()
+        )));
+    }
+    nix_query_drvs
+        .iter()
+        .zip(lines)
+        .map(|(nix_query_drv, line)| {
+            let nar_size = line.trim().parse::<u64>().map_err(|_| {
+                NixStoreErr::NixStoreErr(format!(
+                    "`nix-store --query --size` gave {} an invalid size: {}",
+                    nix_query_drv, line
+                ))
+            })?;
+            Ok(PathInfo {
+                path: nix_query_drv.clone(),
+                nar_hash: None,
+                nar_size: Some(nar_size),
+                closure_size: None,
+                registration_time: None,
+                deriver: None,
+                signatures: vec![],
+                references: vec![],
+            })
+        })
+        .collect()
+}
+
+/// Run `nix path-info --json` for each of `nix_query_drvs`, but not for the
+/// rest of their closures.
+///
+/// `--closure-size` is not passed, since it would make `nix path-info` walk
+/// the whole closure of each path anyway.
+fn nix_path_info_of(
+    store_dir: &Path,
+    nix_query_drvs: &[NixQueryDrv],
+) -> Result<Vec<PathInfo>, NixStoreErr> {
+    let mut path_infos: Vec<PathInfo> = vec![];
+    for chunk in nix_query_drvs.chunks(PATHS_CHUNK_SIZE) {
+        path_infos.extend(nix_path_info(store_dir, &[], chunk)?);
+    }
+    Ok(path_infos)
+}
+
+/// Run `nix path-info --json --recursive --closure-size` for the closure of
+/// `nix_query_drv`.
+fn nix_path_info_recursive(
+    store_dir: &Path,
+    nix_query_drv: &NixQueryDrv,
+) -> Result<Vec<PathInfo>, NixStoreErr> {
+    nix_path_info(
+        store_dir,
+        &["--recursive", "--closure-size"],
+        std::slice::from_ref(nix_query_drv),
+    )
+}
+
+/// Run `nix-store --check-validity --print-invalid` for `nix_query_drvs`,
+/// returning the paths that are missing or invalid.
+fn nix_store_check_validity(
+    store_dir: &Path,
+    nix_query_drvs: &[NixQueryDrv],
+) -> Result<Vec<NixQueryDrv>, NixStoreErr> {
+    let mut invalid_paths: Vec<NixQueryDrv> = vec![];
+    for chunk in nix_query_drvs.chunks(PATHS_CHUNK_SIZE) {
+        let nix_store_output: Output = Command::new("nix-store")
+            .env("NIX_STORE_DIR", store_dir)
+            .args(["--check-validity", "--print-invalid"])
+            .args(chunk.iter().map(|nix_query_drv| nix_query_drv.as_os_str()))
+            .output()
+            .map_err(|io_err| NixStoreErr::CommandErr(io_err.to_string()))?;
+
+        if !nix_store_output.status.success() {
+            let stderr = from_utf8(nix_store_output.stderr)?;
+            return Err(NixStoreErr::NixStoreErr(stderr));
+        }
+        let stdout = from_utf8(nix_store_output.stdout)?;
+        invalid_paths.extend(
+            parsing::nix_query_drv_list_parser(&stdout).map_err(|nom_err| {
+                NixStoreErr::parse_err(
+                    &stdout,
+                    parsing::nix_query_drv_list_parse_err(&stdout, &nom_err),
+                )
+            })?,
+        );
+    }
+    Ok(invalid_paths)
+}
+
+fn nix_store_res(
+    store_dir: &Path,
+    nix_store_path: &Path,
+) -> Result<NixStoreRes, NixStoreErr> {
+    let stdout = nix_store_query(
+        store_dir,
+        &["--tree", &nix_store_path.to_string_lossy()],
+    )?;
+    parsing::nix_query_tree_parser_strict(&stdout)
+        .map(|nix_query_tree| NixStoreRes::new(&stdout, nix_query_tree))
+        .map_err(|parse_err| NixStoreErr::parse_err(&stdout, parse_err))
+}
+
+fn read_file(path: &Path) -> Result<String, NixStoreErr> {
+    let bytes = std::fs::read(path).map_err(|io_err| {
+        NixStoreErr::CommandErr(format!("{}: {}", path.display(), io_err))
+    })?;
+    from_utf8(bytes)
+}
+
+fn nix_store_res_from_files(
+    references_files: &ReferencesFiles,
+    nix_store_path: &Path,
+) -> Result<NixStoreRes, NixStoreErr> {
+    let raw_requisites = read_file(&references_files.requisites)?;
+    let requisites = parsing::nix_query_drv_list_parser(&raw_requisites)
+        .map_err(|nom_err| {
+            NixStoreErr::parse_err(
+                &raw_requisites,
+                parsing::nix_query_drv_list_parse_err(
+                    &raw_requisites,
+                    &nom_err,
+                ),
+            )
+        })?;
+    let raw_references = read_file(&references_files.references)?;
+    let path_infos = dump_db::nix_store_dump_db_parser(&raw_references)
+        .map_err(|nom_err| {
+            NixStoreErr::nom_parse_err(&raw_references, &nom_err)
+        })?;
+    NixStoreRes::from_requisites(
+        &raw_requisites,
+        &NixQueryDrv::from(nix_store_path),
+        &requisites,
+        path_infos,
+    )
+}
+
+fn nix_store_references(
+    store_dir: &Path,
+    nix_store_path: &Path,
+) -> Result<NixStoreReferences, NixStoreErr> {
+    let stdout = nix_store_query(
+        store_dir,
+        &["--references", &nix_store_path.to_string_lossy()],
+    )?;
+    parsing::nix_query_drv_list_parser(&stdout)
+        .map(|references| NixStoreReferences {
+            raw: stdout.clone(),
+            references,
+        })
+        .map_err(|nom_err| {
+            NixStoreErr::parse_err(
+                &stdout,
+                parsing::nix_query_drv_list_parse_err(&stdout, &nom_err),
+            )
+        })
+}
+
+fn nix_store_query_drvs(
+    store_dir: &Path,
+    query_flag: &str,
+    nix_query_drv: &NixQueryDrv,
+) -> Result<Vec<NixQueryDrv>, NixStoreErr> {
+    let stdout = nix_store_query(
+        store_dir,
+        &[query_flag, &nix_query_drv.to_string_lossy()],
+    )?;
+    parsing::nix_query_drv_list_parser(&stdout).map_err(|nom_err| {
+        NixStoreErr::parse_err(
+            &stdout,
+            parsing::nix_query_drv_list_parse_err(&stdout, &nom_err),
+        )
+    })
+}
+
+/// Pick the deriver out of the output of `nix-store --query --deriver`.
+fn pick_deriver(
+    nix_query_drv: &NixQueryDrv,
+    derivers: Vec<NixQueryDrv>,
+) -> Result<NixQueryDrv, NixStoreErr> {
+    match derivers.into_iter().next() {
+        Some(deriver) if deriver.as_os_str() != UNKNOWN_DERIVER => Ok(deriver),
+        _ => Err(NixStoreErr::NixStoreErr(format!(
+            "{} has no known deriver",
+            nix_query_drv
+        ))),
+    }
+}
+
+/// Pick one output out of the output of `nix-store --query --outputs`.
+///
+/// This is the default `out` output if there is one, and otherwise the first
+/// output listed.
+fn pick_output(
+    nix_query_drv: &NixQueryDrv,
+    outputs: Vec<NixQueryDrv>,
+) -> Result<NixQueryDrv, NixStoreErr> {
+    let default_output_index = outputs
+        .iter()
+        .position(|output| output.output().is_none())
+        .unwrap_or(0);
+    outputs
+        .into_iter()
+        .nth(default_output_index)
+        .ok_or_else(|| {
+            NixStoreErr::NixStoreErr(format!(
+                "{} has no outputs",
+                nix_query_drv
+            ))
+        })
+}
+
+/// Figure out the store path in `store_dir` that `nix_store_path` is in,
+/// following symlinks like the `./result` link created by `nix-build`.
+fn store_path_root(
+    store_dir: &Path,
+    nix_store_path: &Path,
+) -> Result<NixQueryDrv, NixStoreErr> {
+    if nix_store_path.parent() == Some(store_dir) {
+        return Ok(NixQueryDrv::from(nix_store_path));
+    }
+
+    let canonicalize = |path: &Path| {
+        path.canonicalize()
+            .map_err(|io_err| NixStoreErr::CommandErr(io_err.to_string()))
+    };
+    let canonical_path = canonicalize(nix_store_path)?;
+    let canonical_store_dir = canonicalize(store_dir)?;
+    match canonical_path
+        .strip_prefix(&canonical_store_dir)
+        .ok()
+        .and_then(|rel_path| rel_path.components().next())
+    {
+        Some(store_path_component) => {
+            Ok(NixQueryDrv::from(&store_dir.join(store_path_component)))
+        }
+        None => Err(NixStoreErr::CommandErr(format!(
+            "{} is not in the nix store {}",
+            canonical_path.display(),
+            store_dir.display()
+        ))),
+    }
+}
+
+fn nix_store_res_lazy(
+    store_dir: &Path,
+    nix_store_path: &Path,
+) -> Result<NixStoreRes, NixStoreErr> {
+    let root = store_path_root(store_dir, nix_store_path)?;
+    let references = nix_store_references(store_dir, &root)?;
+    let mut nix_store_res = NixStoreRes::new_lazy(root.clone());
+    nix_store_res.insert_references(&tree::Path::new(), &root, &references);
+    Ok(nix_store_res)
+}
+
+/// Run `nix-store --query --tree` for the given nix store path, in the nix
+/// store at `store_dir`.
+pub fn run(store_dir: &Path, nix_store_path: &Path) -> ExecNixStoreRes {
+    ExecNixStoreRes {
+        nix_store_path: nix_store_path.to_path_buf(),
+        res: nix_store_res(store_dir, nix_store_path),
+    }
+}
+
+/// Load only the root of the tree for the given nix store path, along with its
+/// direct references from `nix-store --query --references`.  The rest of the
+/// tree can be loaded later with `run_references`.
+pub fn run_lazy(store_dir: &Path, nix_store_path: &Path) -> ExecNixStoreRes {
+    ExecNixStoreRes {
+        nix_store_path: nix_store_path.to_path_buf(),
+        res: nix_store_res_lazy(store_dir, nix_store_path),
+    }
+}
+
+/// Build the tree for the given nix store path from the listings in
+/// `references_files`, without running `nix-store`.
+pub fn run_from_files(
+    references_files: &ReferencesFiles,
+    nix_store_path: &Path,
+) -> ExecNixStoreRes {
     ExecNixStoreRes {
         nix_store_path: nix_store_path.to_path_buf(),
-        res: nix_store_res(nix_store_path),
+        res: nix_store_res_from_files(references_files, nix_store_path),
+    }
+}
+
+fn nix_store_res_from_why_depends(
+    why_depends_file: &Path,
+) -> Result<NixStoreRes, NixStoreErr> {
+    let raw = read_file(why_depends_file)?;
+    parsing::nix_why_depends_parser(&raw)
+        .map(|why_depends| NixStoreRes::from_why_depends(&raw, why_depends))
+        .map_err(|nom_err| NixStoreErr::nom_parse_err(&raw, &nom_err))
+}
+
+/// Load the tree from a file with the output of `nix why-depends`, with or
+/// without `--precise`.
+pub fn run_from_why_depends(why_depends_file: &Path) -> ExecNixStoreRes {
+    ExecNixStoreRes {
+        nix_store_path: why_depends_file.to_path_buf(),
+        res: nix_store_res_from_why_depends(why_depends_file),
+    }
+}
+
+fn nix_store_res_from_path_info(
+    path_info_file: &Path,
+) -> Result<NixStoreRes, NixStoreErr> {
+    let raw = read_file(path_info_file)?;
+    NixStoreRes::from_path_info(&raw)
+}
+
+/// Load the tree and the metadata of every path in it from a file with the
+/// output of `nix path-info --json --recursive`.
+pub fn run_from_path_info(path_info_file: &Path) -> ExecNixStoreRes {
+    ExecNixStoreRes {
+        nix_store_path: path_info_file.to_path_buf(),
+        res: nix_store_res_from_path_info(path_info_file),
+    }
+}
+
+/// Run `nix-store --query --references` for the given `NixQueryDrv`.
+pub fn run_references(
+    store_dir: &Path,
+    nix_query_drv: &NixQueryDrv,
+) -> ExecNixStoreReferencesRes {
+    ExecNixStoreReferencesRes {
+        nix_query_drv: nix_query_drv.clone(),
+        res: nix_store_references(store_dir, nix_query_drv),
+    }
+}
+
+/// Run `nix-store --query --deriver` for the given `NixQueryDrv`, to find the
+/// `.drv` file that it was built from.
+pub fn run_deriver(
+    store_dir: &Path,
+    nix_query_drv: &NixQueryDrv,
+) -> ExecNixStoreSwitchRes {
+    let query_flag = "--deriver";
+    ExecNixStoreSwitchRes {
+        query_flag,
+        nix_query_drv: nix_query_drv.clone(),
+        res: nix_store_query_drvs(store_dir, query_flag, nix_query_drv)
+            .and_then(|derivers| pick_deriver(nix_query_drv, derivers)),
+    }
+}
+
+/// Run `nix-store --query --outputs` for the given `.drv` file, to find the
+/// output whose runtime closure should be shown.  See `pick_output`.
+pub fn run_outputs(
+    store_dir: &Path,
+    nix_query_drv: &NixQueryDrv,
+) -> ExecNixStoreSwitchRes {
+    let query_flag = "--outputs";
+    ExecNixStoreSwitchRes {
+        query_flag,
+        nix_query_drv: nix_query_drv.clone(),
+        res: nix_store_query_drvs(store_dir, query_flag, nix_query_drv)
+            .and_then(|outputs| pick_output(nix_query_drv, outputs)),
+    }
+}
+
+/// Load the metadata for every path in the closure of the given
+/// `NixQueryDrv` with `nix path-info`.
+///
+/// If `nix path-info` can't be run, this falls back to just the size of each
+/// path, from `nix-store --query --requisites` and `nix-store --query --size`.
+/// If that fails too, the error from `nix path-info` is returned.
+pub fn run_path_info(
+    store_dir: &Path,
+    nix_query_drv: &NixQueryDrv,
+) -> ExecNixPathInfoRes {
+    ExecNixPathInfoRes {
+        nix_query_drv: nix_query_drv.clone(),
+        res: nix_path_info_recursive(store_dir, nix_query_drv).or_else(
+            |nix_store_err| {
+                nix_store_query_drvs(store_dir, "--requisites", nix_query_drv)
+                    .and_then(|requisites| {
+                        nix_store_sizes(store_dir, &requisites)
+                    })
+                    .map_err(|_| nix_store_err)
+            },
+        ),
+    }
+}
+
+/// Load the metadata for just `nix_query_drvs`, all from the tree rooted at
+/// `nix_query_drv`, with `nix path-info`.  This is used in lazy mode, where
+/// only the paths that have been loaded so far are queried.  It falls back to
+/// `nix-store --query --size` like `run_path_info`.
+pub fn run_path_info_of(
+    store_dir: &Path,
+    nix_query_drv: &NixQueryDrv,
+    nix_query_drvs: &[NixQueryDrv],
+) -> ExecNixPathInfoRes {
+    ExecNixPathInfoRes {
+        nix_query_drv: nix_query_drv.clone(),
+        res: nix_path_info_of(store_dir, nix_query_drvs).or_else(
+            |nix_store_err| {
+                nix_store_sizes(store_dir, nix_query_drvs)
+                    .map_err(|_| nix_store_err)
+            },
+        ),
+    }
+}
+
+/// Check which of `nix_query_drvs`, all from the tree rooted at
+/// `nix_query_drv`, are missing or invalid in the local store, with
+/// `nix-store --check-validity`.
+pub fn run_check_validity(
+    store_dir: &Path,
+    nix_query_drv: &NixQueryDrv,
+    nix_query_drvs: &[NixQueryDrv],
+) -> ExecNixStoreValidityRes {
+    ExecNixStoreValidityRes {
+        nix_query_drv: nix_query_drv.clone(),
+        res: nix_store_check_validity(store_dir, nix_query_drvs),
     }
 }
 
@@ -98,3 +1137,353 @@ fn from_utf8(i: Vec<u8>) -> Result<String, NixStoreErr> {
     String::from_utf8(i)
         .map_err(|utf8_err| NixStoreErr::Utf8Err(utf8_err.to_string()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+
+    #[test]
+    fn test_chain_paths_follow_recurse_entries() {
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            |   +---/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43
+            +---/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh
+                +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            "
+        );
+        let nix_query_tree = parsing::nix_query_tree_parser(raw_input).unwrap();
+        let nix_store_res = NixStoreRes::new(raw_input, nix_query_tree);
+        let hello_drv: NixQueryDrv =
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10".into();
+        let glibc_drv: NixQueryDrv =
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27".into();
+        let pcre_drv: NixQueryDrv =
+            "/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43".into();
+        let multiple_outputs_drv: NixQueryDrv =
+            "/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh"
+                .into();
+
+        let chain =
+            vec![&hello_drv, &multiple_outputs_drv, &glibc_drv, &pcre_drv];
+        let actual_paths: Vec<tree::Path> = vec![
+            tree::Path::new(),
+            vec![1].into(),
+            vec![1, 0].into(),
+            vec![0, 0].into(),
+        ];
+
+        assert_eq!(nix_store_res.chain_paths(&chain), actual_paths);
+        assert_eq!(
+            nix_store_res.shortest_chain(&pcre_drv),
+            Some(vec![&hello_drv, &glibc_drv, &pcre_drv])
+        );
+    }
+
+    #[test]
+    fn test_insert_references() {
+        let hello_drv: NixQueryDrv =
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10".into();
+        let glibc_drv: NixQueryDrv =
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27".into();
+        let multiple_outputs_drv: NixQueryDrv =
+            "/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh"
+                .into();
+        let references = |drvs: &[&NixQueryDrv]| NixStoreReferences {
+            raw: drvs.iter().map(|drv| drv.to_string() + "\n").collect(),
+            references: drvs.iter().map(|&drv| drv.clone()).collect(),
+        };
+
+        let mut nix_store_res = NixStoreRes::new_lazy(hello_drv.clone());
+        assert_eq!(
+            nix_store_res.insert_references(
+                &tree::Path::new(),
+                &hello_drv,
+                &references(&[&glibc_drv, &multiple_outputs_drv, &hello_drv]),
+            ),
+            Some(vec![])
+        );
+        assert_eq!(
+            nix_store_res.insert_references(
+                &vec![1].into(),
+                &multiple_outputs_drv,
+                &references(&[&glibc_drv]),
+            ),
+            Some(vec![])
+        );
+        assert_eq!(
+            nix_store_res.insert_references(
+                &vec![1, 0].into(),
+                &glibc_drv,
+                &references(&[&glibc_drv]),
+            ),
+            Some(vec![vec![0].into()])
+        );
+        assert_eq!(
+            nix_store_res.insert_references(
+                &vec![1, 0].into(),
+                &glibc_drv,
+                &references(&[&glibc_drv]),
+            ),
+            None
+        );
+
+        let raw_tree = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            +---/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh
+            |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            |       +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+            "
+        );
+        let expected_tree = parsing::nix_query_tree_parser(raw_tree).unwrap();
+
+        assert_eq!(nix_store_res.tree, expected_tree);
+        assert_eq!(nix_store_res.map, expected_tree.path_map());
+        assert_eq!(nix_store_res.graph.edge_count(), 5);
+        assert_eq!(
+            nix_store_res.referrers(&glibc_drv),
+            vec![&hello_drv, &multiple_outputs_drv, &glibc_drv]
+        );
+        assert!(nix_store_res.unloaded.is_empty());
+        assert_eq!(
+            nix_store_res.lookup_expanded(&glibc_drv),
+            Some(&vec![1, 0].into())
+        );
+        assert_eq!(
+            nix_store_res.shortest_chain(&glibc_drv),
+            Some(vec![&hello_drv, &glibc_drv])
+        );
+
+        assert_eq!(nix_store_res.dominator_tree, None);
+        nix_store_res.update_dominators();
+        let expected_graph = expected_tree.graph();
+        let dominator_tree = nix_store_res.dominator_tree.as_ref().unwrap();
+        let glibc = nix_store_res.graph.index(&glibc_drv).unwrap();
+        assert_eq!(
+            dominator_tree.immediate_dominator(glibc),
+            Some(nix_store_res.graph.root())
+        );
+        assert_eq!(
+            dominator_tree.children(nix_store_res.graph.root()).len(),
+            DominatorTree::new(&expected_graph)
+                .children(expected_graph.root())
+                .len()
+        );
+    }
+
+    #[test]
+    fn test_retained_size() {
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            |   +---/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43
+            +---/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh
+                +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            "
+        );
+        let nix_query_tree = parsing::nix_query_tree_parser(raw_input).unwrap();
+        let mut nix_store_res = NixStoreRes::new(raw_input, nix_query_tree);
+        let hello_drv: NixQueryDrv =
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10".into();
+        let glibc_drv: NixQueryDrv =
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27".into();
+        let multiple_outputs_drv: NixQueryDrv =
+            "/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh"
+                .into();
+
+        assert_eq!(nix_store_res.retained_size(&glibc_drv), None);
+
+        let raw_path_info = indoc!(
+            r#"{
+              "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10": {"narSize":100},
+              "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27": {"narSize":20},
+              "/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43": {"narSize":3},
+              "/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh": {"narSize":4}
+            }"#
+        );
+        nix_store_res.insert_path_infos(
+            path_info::path_info_parser(raw_path_info).unwrap(),
+        );
+
+        assert_eq!(nix_store_res.retained_size(&hello_drv), Some(127));
+        assert_eq!(nix_store_res.retained_size(&glibc_drv), Some(23));
+        assert_eq!(nix_store_res.retained_size(&multiple_outputs_drv), Some(4));
+    }
+
+    #[test]
+    fn test_from_path_info() {
+        let raw_input = indoc!(
+            r#"{
+              "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10": {"narSize":100,"references":["/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27"]},
+              "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27": {"narSize":20,"narHash":"sha256:abc","references":[]}
+            }"#
+        );
+        let nix_store_res = NixStoreRes::from_path_info(raw_input).unwrap();
+        let hello_drv: NixQueryDrv =
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10".into();
+        let glibc_drv: NixQueryDrv =
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27".into();
+
+        assert_eq!(nix_store_res.tree.root(), &hello_drv);
+        assert_eq!(nix_store_res.raw, raw_input);
+        assert_eq!(
+            nix_store_res
+                .path_info(&glibc_drv)
+                .and_then(|info| info.nar_hash.as_deref()),
+            Some("sha256:abc")
+        );
+        assert_eq!(nix_store_res.retained_size(&hello_drv), Some(120));
+        assert!(NixStoreRes::from_path_info("[").is_err());
+    }
+
+    #[test]
+    fn test_from_requisites() {
+        let raw_requisites = indoc!(
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            /nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23
+            /nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            "
+        );
+        // The dump has every path in the store, not just the requisites.
+        let raw_dump = indoc!(
+            "/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43
+            0a1b
+            300
+
+            0
+            /nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            2c3d
+            100
+
+            3
+            /nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23
+            /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            /nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            /nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23
+            4e5f
+            10
+
+            2
+            /nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23
+            /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            6a7b
+            20
+
+            0
+            "
+        );
+        let raw_tree = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            +---/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23
+            |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            |   +---/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23 [...]
+            +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+            "
+        );
+        let requisites =
+            parsing::nix_query_drv_list_parser(raw_requisites).unwrap();
+        let path_infos = dump_db::nix_store_dump_db_parser(raw_dump).unwrap();
+        let hello_drv: NixQueryDrv =
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10".into();
+        let bash_drv: NixQueryDrv =
+            "/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23".into();
+        let pcre_drv: NixQueryDrv =
+            "/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43".into();
+
+        let nix_store_res = NixStoreRes::from_requisites(
+            raw_requisites,
+            &hello_drv,
+            &requisites,
+            path_infos.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            nix_store_res.tree,
+            parsing::nix_query_tree_parser(raw_tree).unwrap()
+        );
+        assert_eq!(
+            nix_store_res.lookup_expanded(&bash_drv),
+            Some(&vec![1].into())
+        );
+        assert_eq!(nix_store_res.retained_size(&hello_drv), Some(130));
+        assert!(nix_store_res.path_info(&pcre_drv).is_none());
+
+        assert!(NixStoreRes::from_requisites(
+            raw_requisites,
+            &pcre_drv,
+            &requisites,
+            path_infos.clone(),
+        )
+        .is_err());
+        // pcre isn't in the dump.
+        let mut requisites_with_pcre = requisites.clone();
+        requisites_with_pcre.push(pcre_drv.clone());
+        let path_infos_without_pcre: Vec<PathInfo> = path_infos
+            .into_iter()
+            .filter(|path_info| path_info.path != pcre_drv)
+            .collect();
+        assert!(NixStoreRes::from_requisites(
+            raw_requisites,
+            &hello_drv,
+            &requisites_with_pcre,
+            path_infos_without_pcre,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_pick_deriver_and_output() {
+        let hello_drv: NixQueryDrv =
+            "/nix/store/jymg0kanmlgbcv35wxd8d660rw0fawhv-hello-2.10.drv".into();
+        let hello: NixQueryDrv =
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10".into();
+        let gcc_drv: NixQueryDrv =
+            "/nix/store/2wbrzilwaxd5qhnlqvzrn5gk8rbmhlsb-gcc-7.4.0.drv".into();
+        let gcc_lib: NixQueryDrv =
+            "/nix/store/hlnxw4k6931bachvg5sv0cyaissimswb-gcc-7.4.0-lib".into();
+        let gcc: NixQueryDrv =
+            "/nix/store/ghzg4kg0sjif58smj2lfm2bdvjwim85y-gcc-7.4.0".into();
+
+        assert_eq!(
+            pick_deriver(&hello, vec![hello_drv.clone()]),
+            Ok(hello_drv.clone())
+        );
+        assert!(pick_deriver(&hello, vec![UNKNOWN_DERIVER.into()]).is_err());
+
+        assert_eq!(
+            pick_output(&gcc_drv, vec![gcc_lib.clone(), gcc.clone()]),
+            Ok(gcc)
+        );
+        assert_eq!(pick_output(&gcc_drv, vec![gcc_lib.clone()]), Ok(gcc_lib));
+        assert!(pick_output(&hello_drv, vec![]).is_err());
+    }
+    #[test]
+    fn test_nix_store_sizes_parser() {
+        let hello: NixQueryDrv =
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10".into();
+        let glibc: NixQueryDrv =
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27".into();
+        let nix_query_drvs = vec![hello.clone(), glibc.clone()];
+
+        let path_infos =
+            nix_store_sizes_parser(&nix_query_drvs, "206120\n29118424\n")
+                .unwrap();
+        assert_eq!(path_infos.len(), 2);
+        assert_eq!(path_infos[0].path, hello);
+        assert_eq!(path_infos[0].nar_size, Some(206_120));
+        assert_eq!(path_infos[0].closure_size, None);
+        assert_eq!(path_infos[1].path, glibc);
+        assert_eq!(path_infos[1].nar_size, Some(29_118_424));
+
+        assert!(nix_store_sizes_parser(&nix_query_drvs, "206120\n").is_err());
+        assert!(
+            nix_store_sizes_parser(&nix_query_drvs, "206120\nerror\n").is_err()
+        );
+    }
+}
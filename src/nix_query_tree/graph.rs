@@ -0,0 +1,381 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+use super::super::tree::Tree;
+use super::{NixQueryDrv, NixQueryEntry, NixQueryPathMap, NixQueryTree};
+
+/// The index of a single node in a `NixQueryGraph`.
+pub type NodeIndex = usize;
+
+/// A deduplicated dependency graph built from a `NixQueryTree`.
+///
+/// `nix-store --query --tree` only prints the references of a store path the
+/// first time it sees it.  Every later occurrence is printed with a `[...]`
+/// marker (`Recurse::Yes`) and no children.  A `NixQueryGraph` has exactly one
+/// node for every unique `NixQueryDrv` in the tree, and an explicit edge from
+/// each node to every one of its references.
+///
+/// Nodes are numbered in the order they are first seen in a pre-order walk of
+/// the tree, so the root of the tree is always node `0`.  Nodes added later
+/// with `insert_references` are numbered after all the others.
+///
+/// ```
+/// use indoc::indoc;
+/// use nix_query_tree_viewer::nix_query_tree::{NixQueryDrv, NixQueryTree};
+/// use std::str::FromStr;
+///
+/// let raw_tree = indoc!(
+///         "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+///         +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+///         |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+///         +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+///         "
+///     );
+/// let graph = NixQueryTree::from_str(raw_tree).unwrap().graph();
+/// let glibc_drv =
+///     NixQueryDrv::from("/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27");
+///
+/// assert_eq!(graph.len(), 2);
+/// assert_eq!(graph.edge_count(), 3);
+/// assert_eq!(graph.index(&glibc_drv), Some(1));
+/// assert_eq!(graph.references(graph.root()), &[1, 0]);
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NixQueryGraph {
+    nodes: Vec<NixQueryDrv>,
+    indices: HashMap<NixQueryDrv, NodeIndex>,
+    references: Vec<Vec<NodeIndex>>,
+    referrers: Vec<Vec<NodeIndex>>,
+}
+
+impl NixQueryGraph {
+    /// Build a `NixQueryGraph` from a `NixQueryTree` and its `NixQueryPathMap`.
+    ///
+    /// The references of a node are the children of the instance of that node
+    /// that isn't a `[...]` entry (see `NixQueryTree::lookup_expanded`), which
+    /// is the only instance `nix-store` expands.
+    pub fn new(
+        nix_query_tree: &NixQueryTree,
+        path_map: &NixQueryPathMap,
+    ) -> Self {
+        let tree: &Tree<NixQueryEntry> = &nix_query_tree.0;
+
+        let mut nodes: Vec<NixQueryDrv> = vec![];
+        let mut indices: HashMap<NixQueryDrv, NodeIndex> = HashMap::new();
+        for (_, nix_query_entry) in tree.iter_pre_order() {
+            let drv: &NixQueryDrv = &nix_query_entry.0;
+            if !indices.contains_key(drv) {
+                indices.insert(drv.clone(), nodes.len());
+                nodes.push(drv.clone());
+            }
+        }
+
+        let references: Vec<Vec<NodeIndex>> = nodes
+            .iter()
+            .map(|drv| {
+                nix_query_tree
+                    .lookup_expanded(path_map, drv)
+                    .and_then(|path| tree.lookup_tree(path.clone()))
+                    .map(|first_tree| {
+                        first_tree
+                            .children
+                            .iter()
+                            .map(|child| indices[&child.item.0])
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        let mut referrers: Vec<Vec<NodeIndex>> = vec![vec![]; nodes.len()];
+        for (referrer, refs) in references.iter().enumerate() {
+            for &reference in refs {
+                referrers[reference].push(referrer);
+            }
+        }
+
+        NixQueryGraph {
+            nodes,
+            indices,
+            references,
+            referrers,
+        }
+    }
+
+    /// Add the references of the node for `drv`, which doesn't have any
+    /// references yet, adding nodes for any of them that aren't in the graph.
+    ///
+    /// This is the same as building a new `NixQueryGraph` after the
+    /// references of `drv` have been added to the `NixQueryTree`, except for
+    /// the numbering of the new nodes, but without walking the whole tree.
+    ///
+    /// * Panics
+    ///
+    /// This panics if `drv` is not a node in this graph.
+    pub fn insert_references(
+        &mut self,
+        drv: &NixQueryDrv,
+        references: &[NixQueryDrv],
+    ) {
+        let index = self.indices[drv];
+        for reference in references {
+            let reference_index =
+                if let Some(&reference_index) = self.indices.get(reference) {
+                    reference_index
+                } else {
+                    let reference_index = self.nodes.len();
+                    self.indices.insert(reference.clone(), reference_index);
+                    self.nodes.push(reference.clone());
+                    self.references.push(vec![]);
+                    self.referrers.push(vec![]);
+                    reference_index
+                };
+            self.references[index].push(reference_index);
+            self.referrers[reference_index].push(index);
+        }
+    }
+
+    /// The node for the root of the original `NixQueryTree`.
+    pub fn root(&self) -> NodeIndex {
+        0
+    }
+
+    /// The number of unique nodes in the graph.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The total number of reference edges in the graph.
+    pub fn edge_count(&self) -> usize {
+        self.references.iter().map(Vec::len).sum()
+    }
+
+    /// Get the `NixQueryDrv` for a node.
+    pub fn node(&self, index: NodeIndex) -> Option<&NixQueryDrv> {
+        self.nodes.get(index)
+    }
+
+    /// Find the node for a `NixQueryDrv`.
+    pub fn index(&self, drv: &NixQueryDrv) -> Option<NodeIndex> {
+        self.indices.get(drv).copied()
+    }
+
+    /// The direct references of a node, in the order `nix-store` output them.
+    ///
+    /// * Panics
+    ///
+    /// This panics if `index` is not a node in this graph.
+    pub fn references(&self, index: NodeIndex) -> &[NodeIndex] {
+        &self.references[index]
+    }
+
+    /// The nodes that directly reference a node.  This is the reverse of
+    /// `references`.
+    ///
+    /// * Panics
+    ///
+    /// This panics if `index` is not a node in this graph.
+    pub fn referrers(&self, index: NodeIndex) -> &[NodeIndex] {
+        &self.referrers[index]
+    }
+
+    /// Find a shortest chain of references from the root to `target`.  The
+    /// chain starts with the root and ends with `target`.
+    ///
+    /// This returns `None` if `target` can't be reached from the root.
+    pub fn shortest_chain(&self, target: NodeIndex) -> Option<Vec<NodeIndex>> {
+        if target >= self.len() {
+            return None;
+        }
+
+        // Breadth-first search from the root, remembering how each node was
+        // first reached.
+        let mut reached_from: Vec<Option<NodeIndex>> = vec![None; self.len()];
+        let mut queue: VecDeque<NodeIndex> = VecDeque::new();
+        let root = self.root();
+        queue.push_back(root);
+        while let Some(node) = queue.pop_front() {
+            if node == target {
+                let mut chain = vec![target];
+                let mut curr = target;
+                while let Some(prev) = reached_from[curr] {
+                    chain.push(prev);
+                    curr = prev;
+                }
+                chain.reverse();
+                return Some(chain);
+            }
+            for &reference in self.references(node) {
+                if reference != root && reached_from[reference].is_none() {
+                    reached_from[reference] = Some(node);
+                    queue.push_back(reference);
+                }
+            }
+        }
+        None
+    }
+
+    /// Find every distinct chain of references from the root to `target`,
+    /// shortest first.  At most `limit` chains are returned, since a widely
+    /// shared dependency can easily be reachable in millions of ways.  When
+    /// there are more than `limit` chains, the ones returned are the shortest.
+    pub fn all_chains(
+        &self,
+        target: NodeIndex,
+        limit: usize,
+    ) -> Vec<Vec<NodeIndex>> {
+        if target >= self.len() {
+            return vec![];
+        }
+
+        // The length of the shortest chain from each node to the target.
+        // Nodes that can't reach the target aren't worth walking through.
+        let mut dist_to_target: HashMap<NodeIndex, usize> = HashMap::new();
+        let mut queue: VecDeque<NodeIndex> = VecDeque::new();
+        dist_to_target.insert(target, 0);
+        queue.push_back(target);
+        while let Some(node) = queue.pop_front() {
+            let dist = dist_to_target[&node];
+            for &referrer in self.referrers(node) {
+                if let Entry::Vacant(entry) = dist_to_target.entry(referrer) {
+                    entry.insert(dist + 1);
+                    queue.push_back(referrer);
+                }
+            }
+        }
+
+        // Extend partial chains in order of the shortest complete chain each
+        // of them could still become.  That estimate never overshoots, so
+        // complete chains come out shortest first, and the search can stop
+        // as soon as it has `limit` of them.  The sequence number keeps
+        // chains of the same length in the order `nix-store` output them.
+        let mut chains: Vec<Vec<NodeIndex>> = vec![];
+        let mut partial_chains = BinaryHeap::new();
+        let mut seq: usize = 0;
+        if let Some(&dist) = dist_to_target.get(&self.root()) {
+            partial_chains.push(Reverse((dist, seq, vec![self.root()])));
+        }
+        while let Some(Reverse((_, _, chain))) = partial_chains.pop() {
+            if chains.len() >= limit {
+                break;
+            }
+            let node = *chain.last().expect("chain is never empty");
+            if node == target {
+                chains.push(chain);
+                continue;
+            }
+            for &reference in self.references(node) {
+                if let Some(&dist) = dist_to_target.get(&reference) {
+                    if !chain.contains(&reference) {
+                        let mut longer_chain = chain.clone();
+                        longer_chain.push(reference);
+                        seq += 1;
+                        partial_chains.push(Reverse((
+                            chain.len() + dist,
+                            seq,
+                            longer_chain,
+                        )));
+                    }
+                }
+            }
+        }
+        chains
+    }
+
+    /// Iterate over all nodes in the graph in index order.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeIndex, &NixQueryDrv)> {
+        self.nodes.iter().enumerate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_graph_follows_recurse_entries() {
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            |   +---/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43
+            +---/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh
+                +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            "
+        );
+        let graph = NixQueryTree::from_str(raw_input).unwrap().graph();
+
+        let glibc_drv: NixQueryDrv =
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27".into();
+        let pcre_drv: NixQueryDrv =
+            "/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43".into();
+        let multiple_outputs_drv: NixQueryDrv =
+            "/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh"
+                .into();
+
+        let hello = graph.root();
+        let glibc = graph.index(&glibc_drv).unwrap();
+        let pcre = graph.index(&pcre_drv).unwrap();
+        let multiple_outputs = graph.index(&multiple_outputs_drv).unwrap();
+
+        assert_eq!(graph.len(), 4);
+        assert_eq!(graph.edge_count(), 4);
+        assert_eq!(graph.references(hello), &[glibc, multiple_outputs]);
+        assert_eq!(graph.references(glibc), &[pcre]);
+        assert_eq!(graph.references(pcre), &[] as &[NodeIndex]);
+        assert_eq!(graph.references(multiple_outputs), &[glibc]);
+
+        assert_eq!(graph.referrers(hello), &[] as &[NodeIndex]);
+        assert_eq!(graph.referrers(glibc), &[hello, multiple_outputs]);
+        assert_eq!(graph.referrers(pcre), &[glibc]);
+    }
+
+    #[test]
+    fn test_graph_chains() {
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh
+            |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            |       +---/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43
+            +---/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43 [...]
+            +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+            "
+        );
+        let graph = NixQueryTree::from_str(raw_input).unwrap().graph();
+        let glibc_drv: NixQueryDrv =
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27".into();
+        let pcre_drv: NixQueryDrv =
+            "/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43".into();
+        let multiple_outputs_drv: NixQueryDrv =
+            "/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh"
+                .into();
+
+        let hello = graph.root();
+        let glibc = graph.index(&glibc_drv).unwrap();
+        let pcre = graph.index(&pcre_drv).unwrap();
+        let multiple_outputs = graph.index(&multiple_outputs_drv).unwrap();
+
+        assert_eq!(graph.shortest_chain(hello), Some(vec![hello]));
+        assert_eq!(graph.shortest_chain(pcre), Some(vec![hello, pcre]));
+        assert_eq!(
+            graph.shortest_chain(glibc),
+            Some(vec![hello, multiple_outputs, glibc])
+        );
+        assert_eq!(
+            graph.all_chains(pcre, 10),
+            vec![
+                vec![hello, pcre],
+                vec![hello, multiple_outputs, glibc, pcre],
+            ]
+        );
+        assert_eq!(graph.all_chains(pcre, 1), vec![vec![hello, pcre]]);
+        assert_eq!(graph.all_chains(pcre, 0), vec![] as Vec<Vec<NodeIndex>>);
+    }
+}
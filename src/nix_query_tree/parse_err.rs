@@ -0,0 +1,221 @@
+use nom::error::ErrorKind;
+
+/// What a parser was expecting at the point where it failed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Expected {
+    /// The `+---`, `├───` or `└───` at the start of a branch.
+    BranchGlyph,
+    /// At most this many levels of indentation (`|   `, `│   ` or `    `)
+    /// before the branch glyph.  A branch can be at most one level deeper
+    /// than the line above it.
+    Indent(usize),
+    /// A store path.
+    Path,
+    /// A `[...]` after the path, or the end of the line.
+    Recurse,
+    /// The end of the line.
+    EndOfLine,
+    /// Whatever the nom parser that failed was looking for.  This is used
+    /// for formats that don't have a more specific diagnosis.
+    Other(ErrorKind),
+    /// Valid JSON, with the message `serde_json` gave for why it wasn't.
+    Json(String),
+}
+
+impl std::fmt::Display for Expected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expected::BranchGlyph => {
+                write!(f, "a branch glyph (`+---`, `├───` or `└───`)")
+            }
+            Expected::Indent(0) => write!(f, "no indentation"),
+            Expected::Indent(1) => write!(f, "at most 1 level of indentation"),
+            Expected::Indent(levels) => {
+                write!(f, "at most {} levels of indentation", levels)
+            }
+            Expected::Path => write!(f, "a store path"),
+            Expected::Recurse => write!(f, "`[...]` or the end of the line"),
+            Expected::EndOfLine => write!(f, "the end of the line"),
+            Expected::Other(error_kind) => {
+                write!(
+                    f,
+                    "valid input (nom error: {})",
+                    error_kind.description()
+                )
+            }
+            Expected::Json(message) => write!(f, "valid JSON ({})", message),
+        }
+    }
+}
+
+/// An error from parsing the output of `nix-store` (or one of the other
+/// formats that can be read), pointing at where in the input it happened.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::parse_err::{Expected, ParseErr};
+///
+/// let input = "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10\n+--/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27\n";
+/// let parse_err = ParseErr::at(input, 55, Expected::BranchGlyph);
+///
+/// assert_eq!(parse_err.line, 2);
+/// assert_eq!(parse_err.column, 1);
+/// assert_eq!(
+///     parse_err.line_text,
+///     "+--/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27"
+/// );
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseErr {
+    /// The line the error is on, starting from 1.
+    pub line: usize,
+    /// The column the error is at, in characters, starting from 1.
+    pub column: usize,
+    /// The whole line the error is on, without the newline.
+    pub line_text: String,
+    pub expected: Expected,
+}
+
+impl ParseErr {
+    /// Create a `ParseErr` for the byte `offset` into `input`.
+    pub fn at(input: &str, offset: usize, expected: Expected) -> Self {
+        let before = &input[..offset];
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        let line_text =
+            input[line_start..].split('\n').next().unwrap_or_default();
+        ParseErr {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            line_text: String::from(line_text),
+            expected,
+        }
+    }
+
+    /// Create a `ParseErr` from the error a nom parser returned for `input`,
+    /// at the point where the parser stopped.
+    pub fn new(input: &str, nom_err: &nom::Err<(&str, ErrorKind)>) -> Self {
+        match nom_err {
+            nom::Err::Error((_, error_kind))
+            | nom::Err::Failure((_, error_kind)) => ParseErr::at(
+                input,
+                error_offset(input, nom_err),
+                Expected::Other(*error_kind),
+            ),
+            nom::Err::Incomplete(_) => ParseErr::at(
+                input,
+                input.len(),
+                Expected::Other(ErrorKind::Complete),
+            ),
+        }
+    }
+
+    /// Create a `ParseErr` from the error `serde_json` returned for `input`.
+    /// Errors that aren't about the syntax of the JSON, like a missing field,
+    /// don't have a position, so they point at the start of the input.
+    pub fn from_json(input: &str, json_err: &serde_json::Error) -> Self {
+        let message = json_err.to_string();
+        let position = format!(
+            " at line {} column {}",
+            json_err.line(),
+            json_err.column()
+        );
+        let message = message.strip_suffix(&position).unwrap_or(&message);
+
+        let mut offset = match json_err.line() {
+            0 => 0,
+            line => {
+                // Lines start from 1, and `serde_json` counts columns in
+                // bytes, also starting from 1.
+                let line_start = if line == 1 {
+                    0
+                } else {
+                    input
+                        .match_indices('\n')
+                        .nth(line - 2)
+                        .map_or(input.len(), |(index, _)| index + 1)
+                };
+                let column_offset = json_err.column().saturating_sub(1);
+                (line_start + column_offset).min(input.len())
+            }
+        };
+        while !input.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        ParseErr::at(input, offset, Expected::Json(String::from(message)))
+    }
+}
+
+impl std::fmt::Display for ParseErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Parse error on line {}, column {}: expected {}\n\n{}",
+            self.line, self.column, self.expected, self.line_text
+        )
+    }
+}
+
+/// The byte offset into `input` where the parser that returned `nom_err`
+/// stopped.  This relies on the remaining input in the error being a suffix
+/// of `input`, which it is for every parser in this crate.
+pub fn error_offset(
+    input: &str,
+    nom_err: &nom::Err<(&str, ErrorKind)>,
+) -> usize {
+    match nom_err {
+        nom::Err::Error((rest, _)) | nom::Err::Failure((rest, _)) => {
+            input.len().saturating_sub(rest.len())
+        }
+        nom::Err::Incomplete(_) => input.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_err_at() {
+        let input = "first\n│   second\n";
+
+        // The column counts characters, not bytes.
+        let parse_err = ParseErr::at(input, 6 + "│   ".len(), Expected::Path);
+        assert_eq!(parse_err.line, 2);
+        assert_eq!(parse_err.column, 5);
+        assert_eq!(parse_err.line_text, "│   second");
+
+        let parse_err = ParseErr::at(input, input.len(), Expected::Path);
+        assert_eq!(parse_err.line, 3);
+        assert_eq!(parse_err.column, 1);
+        assert_eq!(parse_err.line_text, "");
+
+        assert_eq!(
+            ParseErr::new(
+                input,
+                &nom::Err::Error(("second\n", ErrorKind::Eof))
+            )
+            .line,
+            2
+        );
+    }
+
+    #[test]
+    fn test_parse_err_from_json() {
+        let input = "[\n  {\"narSize\": 1},\n  nulx\n]";
+        let json_err =
+            serde_json::from_str::<serde_json::Value>(input).unwrap_err();
+        let parse_err = ParseErr::from_json(input, &json_err);
+        assert_eq!(parse_err.line, 3);
+        assert_eq!(parse_err.line_text, "  nulx");
+        assert_eq!(parse_err.column, 6);
+        assert_eq!(
+            parse_err.expected,
+            Expected::Json(String::from("expected ident"))
+        );
+
+        // Errors without a position point at the start of the input.
+        let json_err =
+            serde_json::from_value::<u64>(serde_json::json!("1")).unwrap_err();
+        let parse_err = ParseErr::from_json(input, &json_err);
+        assert_eq!((parse_err.line, parse_err.column), (1, 1));
+    }
+}
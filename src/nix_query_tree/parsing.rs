@@ -1,9 +1,15 @@
+use nom::bytes::complete::take_till1;
 use nom::character::complete::{newline, space1};
-use nom::combinator::complete;
+use nom::combinator::{complete, verify};
 use nom::multi::{many0, many_m_n};
-use nom::{alt, do_parse, eof, map, named, opt, tag, take_till, IResult};
+use nom::{
+    alt, complete, do_parse, eof, many0, map, named, opt, tag, take_till,
+    IResult,
+};
 
 use super::super::tree::Tree;
+use super::parse_err::{error_offset, Expected, ParseErr};
+use super::why_depends::WhyDepends;
 use super::{NixQueryDrv, NixQueryEntry, NixQueryTree, Recurse};
 
 named!(parse_nix_query_drv<&str, NixQueryDrv>,
@@ -100,6 +106,237 @@ pub fn nix_query_tree_parser(
     parse_nix_query_tree(input).map(|(_, nix_query_tree)| nix_query_tree)
 }
 
+/// Parse a single line with nothing but a nix store path on it.
+fn parse_nix_query_drv_line(input: &str) -> IResult<&str, NixQueryDrv> {
+    let (input, drv) = take_till1(char::is_whitespace)(input)?;
+    let (input, _) = newline(input)?;
+    Ok((input, NixQueryDrv::from(drv)))
+}
+
+named!(parse_nix_query_drv_list<&str, Vec<NixQueryDrv>>,
+    do_parse!(
+        drvs: many0!(complete!(parse_nix_query_drv_line)) >>
+        eof!() >>
+        (drvs)));
+
+/// Parse output from `nix-store --query --references` (or any other
+/// `nix-store --query` command that outputs one store path per line).
+pub fn nix_query_drv_list_parser(
+    input: &str,
+) -> Result<Vec<NixQueryDrv>, nom::Err<(&str, nom::error::ErrorKind)>> {
+    parse_nix_query_drv_list(input).map(|(_, drvs)| drvs)
+}
+
+/// The byte offset of the start of the line that `nom_err` points at, and
+/// that line without its newline.
+fn failing_line<'a>(
+    input: &'a str,
+    nom_err: &nom::Err<(&str, nom::error::ErrorKind)>,
+) -> (usize, &'a str) {
+    let offset = error_offset(input, nom_err);
+    let line_start = input[..offset].rfind('\n').map_or(0, |index| index + 1);
+    let line = input[line_start..].split('\n').next().unwrap_or_default();
+    (line_start, line)
+}
+
+/// The level of a line of `nix-store --query --tree` output, or `None` if
+/// it isn't a branch (like the line with the root on it).
+fn branch_level(line: &str) -> Option<usize> {
+    let (rest, levels) = many0(complete(parse_extra_level))(line).ok()?;
+    complete(parse_branch_start)(rest).ok()?;
+    Some(levels.len())
+}
+
+/// Find what is wrong with the entry at the end of `line`, which starts at
+/// `rest`.  Returns the byte offset into `line` of the problem and what
+/// should have been there.
+fn diagnose_entry(
+    line: &str,
+    rest: &str,
+    recurse_allowed: bool,
+) -> (usize, Expected) {
+    let path_len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let (path, after_path) = rest.split_at(path_len);
+    if path.is_empty() {
+        return (line.len() - rest.len(), Expected::Path);
+    }
+    let is_recurse = after_path.starts_with(char::is_whitespace)
+        && after_path.trim_start() == "[...]";
+    if after_path.is_empty() || (recurse_allowed && is_recurse) {
+        // The line itself is fine, so it must be missing its newline.
+        (line.len(), Expected::EndOfLine)
+    } else if recurse_allowed {
+        (line.len() - after_path.len(), Expected::Recurse)
+    } else {
+        (line.len() - after_path.len(), Expected::EndOfLine)
+    }
+}
+
+/// Find what is wrong with a branch `line` that can be at most `max_levels`
+/// deep.
+fn diagnose_branch(line: &str, max_levels: usize) -> (usize, Expected) {
+    let (rest, levels) = many0(complete(parse_extra_level))(line)
+        .unwrap_or((line, vec![]));
+    if levels.len() > max_levels {
+        let indent_len = levels[..max_levels].iter().map(|l| l.len()).sum();
+        return (indent_len, Expected::Indent(max_levels));
+    }
+    match complete(parse_branch_start)(rest) {
+        Ok((rest, _)) => diagnose_entry(line, rest, true),
+        Err(_) => (line.len() - rest.len(), Expected::BranchGlyph),
+    }
+}
+
+/// Parse all output from `nix-store --query --tree`, like
+/// `nix_query_tree_parser`, but fail if there is anything after the tree
+/// that isn't part of it.  The error says where and why parsing failed.
+///
+/// The parser itself only knows that it couldn't get any further, so this
+/// looks at the line it stopped on, and the line before that, to tell
+/// whether the branch glyph, the indentation, the path or the `[...]` is
+/// wrong.
+///
+/// ```
+/// use indoc::indoc;
+/// use nix_query_tree_viewer::nix_query_tree::parse_err::Expected;
+/// use nix_query_tree_viewer::nix_query_tree::parsing::nix_query_tree_parser_strict;
+///
+/// let raw_tree = indoc!(
+///         "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+///         +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+///         |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 (...)
+///         "
+///     );
+/// let parse_err = nix_query_tree_parser_strict(raw_tree).unwrap_err();
+///
+/// assert_eq!(parse_err.line, 3);
+/// assert_eq!(parse_err.column, 63);
+/// assert_eq!(parse_err.expected, Expected::Recurse);
+/// ```
+pub fn nix_query_tree_parser_strict(
+    input: &str,
+) -> Result<NixQueryTree, ParseErr> {
+    parse_nix_query_tree_final(input)
+        .map(|(_, nix_query_tree)| nix_query_tree)
+        .map_err(|nom_err| nix_query_tree_parse_err(input, &nom_err))
+}
+
+/// Work out where and why `parse_nix_query_tree_final` failed on `input`.
+fn nix_query_tree_parse_err(
+    input: &str,
+    nom_err: &nom::Err<(&str, nom::error::ErrorKind)>,
+) -> ParseErr {
+    let (line_start, line) = failing_line(input, nom_err);
+    let (offset, expected) = if line_start == 0 {
+        diagnose_entry(line, line, false)
+    } else {
+        let prev_line = input[..line_start - 1]
+            .rsplit('\n')
+            .next()
+            .unwrap_or_default();
+        let max_levels = branch_level(prev_line).map_or(0, |level| level + 1);
+        diagnose_branch(line, max_levels)
+    };
+    ParseErr::at(input, line_start + offset, expected)
+}
+
+/// Work out where and why `nix_query_drv_list_parser` failed on `input`.
+pub fn nix_query_drv_list_parse_err(
+    input: &str,
+    nom_err: &nom::Err<(&str, nom::error::ErrorKind)>,
+) -> ParseErr {
+    let (line_start, line) = failing_line(input, nom_err);
+    let path_len = line.find(char::is_whitespace).unwrap_or(line.len());
+    let (offset, expected) = if path_len == 0 {
+        (0, Expected::Path)
+    } else {
+        (path_len, Expected::EndOfLine)
+    };
+    ParseErr::at(input, line_start + offset, expected)
+}
+
+named!(parse_arrow<&str, &str>,
+    tag!("→ "));
+
+/// Parse the rest of a line of `nix why-depends --precise` output that shows
+/// where a file refers to the next path, like
+/// `bin/hello: …/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27/lib…`.
+fn parse_excerpt(input: &str) -> IResult<&str, String> {
+    let (input, excerpt) = verify(take_till1(|c| c == '\n'), |s: &str| {
+        !s.starts_with("→ ")
+    })(input)?;
+    let (input, _) = newline(input)?;
+    Ok((input, String::from(excerpt)))
+}
+
+/// Parse a single reference in `nix why-depends --precise` output, with all
+/// of the references under it.
+///
+/// A reference starts with the excerpts of the files that refer to it, and
+/// then has the path itself on a line starting with `→`:
+///
+/// ```text
+/// └───bin/hello: …/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27/lib…
+///     → /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+/// ```
+///
+/// The references under it are indented to the same level as the `→` line.
+/// With `--all`, every file after the first is on its own line, also indented
+/// to that level.
+fn parse_precise_branch(
+    level: usize,
+) -> impl Fn(&str) -> IResult<&str, Tree<(NixQueryDrv, Vec<String>)>> {
+    move |input| {
+        let (input, _) = parse_extra_levels(level)(input)?;
+        let (input, _) = parse_branch_start(input)?;
+        let (input, first_excerpt) = parse_excerpt(input)?;
+        let (input, mut excerpts) = many0(complete(|input| {
+            let (input, _) = parse_extra_levels(level + 1)(input)?;
+            parse_excerpt(input)
+        }))(input)?;
+        excerpts.insert(0, first_excerpt);
+        let (input, _) = parse_extra_levels(level + 1)(input)?;
+        let (input, _) = parse_arrow(input)?;
+        let (input, drv) = parse_nix_query_drv(input)?;
+        let (input, _) = newline(input)?;
+        let (input, children) =
+            many0(complete(parse_precise_branch(level + 1)))(input)?;
+        Ok((input, Tree::new((drv, excerpts), children)))
+    }
+}
+
+fn parse_why_depends_precise(
+    input: &str,
+) -> IResult<&str, Tree<(NixQueryDrv, Vec<String>)>> {
+    let (input, top_drv) = parse_nix_query_drv(input)?;
+    let (input, _) = newline(input)?;
+    let (input, children) = many0(complete(parse_precise_branch(0)))(input)?;
+    let (input, _) = eof!(input,)?;
+    Ok((input, Tree::new((top_drv, vec![]), children)))
+}
+
+/// The output of `nix why-depends` without `--precise` is a tree just like
+/// the output of `nix-store --query --tree`, without any `[...]` entries.
+fn parse_why_depends_plain(
+    input: &str,
+) -> IResult<&str, Tree<(NixQueryDrv, Vec<String>)>> {
+    let (input, nix_query_tree) = parse_nix_query_tree_final(input)?;
+    let tree = nix_query_tree
+        .0
+        .map(&|nix_query_entry| (nix_query_entry.0.clone(), vec![]));
+    Ok((input, tree))
+}
+
+/// Parse all output from `nix why-depends`, with or without `--precise`.
+pub fn nix_why_depends_parser(
+    input: &str,
+) -> Result<WhyDepends, nom::Err<(&str, nom::error::ErrorKind)>> {
+    nom::branch::alt((parse_why_depends_plain, parse_why_depends_precise))(
+        input,
+    )
+    .map(|(_, tree)| WhyDepends::new(&tree))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -352,4 +589,166 @@ mod tests {
         let r = parse_nix_query_tree(raw_input);
         assert_eq!(r, Ok(("", NixQueryTree(actual_tree))));
     }
+
+    #[test]
+    fn test_parse_nix_query_drv_list() {
+        let raw_input = indoc!(
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            /nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            "
+        );
+        let hello_drv: NixQueryDrv =
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10".into();
+        let glibc_drv: NixQueryDrv =
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27".into();
+
+        assert_eq!(
+            nix_query_drv_list_parser(raw_input),
+            Ok(vec![glibc_drv, hello_drv])
+        );
+        assert_eq!(nix_query_drv_list_parser(""), Ok(vec![]));
+        assert!(nix_query_drv_list_parser("\n").is_err());
+    }
+
+    #[test]
+    fn test_nix_why_depends_parser_plain() {
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            └───/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23
+                └───/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            "
+        );
+        let raw_tree = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23
+                +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            "
+        );
+        let why_depends = nix_why_depends_parser(raw_input).unwrap();
+
+        assert_eq!(why_depends.tree, nix_query_tree_parser(raw_tree).unwrap());
+        assert!(why_depends.excerpts.is_empty());
+    }
+
+    #[test]
+    fn test_nix_why_depends_parser_precise() {
+        // This is `--precise --all` output, where bin/hello refers to glibc
+        // directly and through bash, and two files in bash refer to glibc.
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            ├───bin/hello: …ld:/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23/bin/sh…
+            │   → /nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23
+            │   └───bin/bash: …/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27/lib/ld-linux…
+            │       lib/bash/sleep: …/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27/lib…
+            │       → /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            └───bin/hello: …/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27/lib/ld-linux…
+                → /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            "
+        );
+        let raw_tree = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23
+            |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            "
+        );
+        let hello_drv: NixQueryDrv =
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10".into();
+        let bash_drv: NixQueryDrv =
+            "/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23".into();
+        let glibc_drv: NixQueryDrv =
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27".into();
+        let why_depends = nix_why_depends_parser(raw_input).unwrap();
+
+        assert_eq!(why_depends.tree, nix_query_tree_parser(raw_tree).unwrap());
+        assert_eq!(
+            why_depends.excerpts(&hello_drv, &bash_drv),
+            &[String::from(
+                "bin/hello: …ld:/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23/bin/sh…"
+            )]
+        );
+        assert_eq!(
+            why_depends.excerpts(&bash_drv, &glibc_drv),
+            &[
+                String::from("bin/bash: …/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27/lib/ld-linux…"),
+                String::from("lib/bash/sleep: …/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27/lib…"),
+            ]
+        );
+        assert_eq!(why_depends.excerpts(&hello_drv, &glibc_drv).len(), 1);
+
+        // An excerpt without the path it refers to.
+        assert!(nix_why_depends_parser(indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            └───bin/hello: …/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27…
+            "
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_nix_query_tree_parse_err() {
+        let parse_err = |raw_input: &str| {
+            nix_query_tree_parser_strict(raw_input).unwrap_err()
+        };
+
+        // Indented two levels below a branch at level 0.
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            |   |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            "
+        );
+        let err = parse_err(raw_input);
+        assert_eq!((err.line, err.column), (3, 5));
+        assert_eq!(err.expected, Expected::Indent(1));
+        assert_eq!(
+            err.line_text,
+            "|   |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27"
+        );
+
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            │   *---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            "
+        );
+        let err = parse_err(raw_input);
+        assert_eq!((err.line, err.column), (3, 5));
+        assert_eq!(err.expected, Expected::BranchGlyph);
+
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +--- /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            "
+        );
+        let err = parse_err(raw_input);
+        assert_eq!((err.line, err.column), (2, 5));
+        assert_eq!(err.expected, Expected::Path);
+
+        let err = parse_err(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]\n",
+        );
+        assert_eq!((err.line, err.column), (1, 55));
+        assert_eq!(err.expected, Expected::EndOfLine);
+
+        // The last line is missing its newline.
+        let err = parse_err(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10\n+---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27",
+        );
+        assert_eq!((err.line, err.column), (2, 59));
+        assert_eq!(err.expected, Expected::EndOfLine);
+    }
+
+    #[test]
+    fn test_nix_query_drv_list_parse_err() {
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 extra
+            "
+        );
+        let nom_err = nix_query_drv_list_parser(raw_input).unwrap_err();
+        let err = nix_query_drv_list_parse_err(raw_input, &nom_err);
+        assert_eq!((err.line, err.column), (2, 55));
+        assert_eq!(err.expected, Expected::EndOfLine);
+    }
 }
@@ -0,0 +1,378 @@
+use serde::de::Error as _;
+use serde::Deserialize;
+
+use super::closure::Closure;
+use super::{NixQueryDrv, NixQueryTree};
+
+/// The metadata for a single store path, from `nix path-info --json`.
+///
+/// Fields that `nix path-info` didn't output are `None` (or empty).  In
+/// particular, `closure_size` is only output when `nix path-info` is passed
+/// `--closure-size`.
+///
+/// `NixStoreRes` keeps a single `PathInfo` for each path in its tree, rather
+/// than one on each `NixQueryEntry`, so every `[...]` instance of a path
+/// shares it.  Look it up with `NixStoreRes::path_info`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PathInfo {
+    pub path: NixQueryDrv,
+    pub nar_hash: Option<String>,
+    pub nar_size: Option<u64>,
+    pub closure_size: Option<u64>,
+    /// The time the path was added to the store, in seconds since the epoch.
+    pub registration_time: Option<u64>,
+    pub deriver: Option<NixQueryDrv>,
+    pub signatures: Vec<String>,
+    pub references: Vec<NixQueryDrv>,
+}
+
+impl PathInfo {
+    /// The names of the keys this path has been signed with, like
+    /// `cache.nixos.org-1`.
+    pub fn signature_key_names(&self) -> Vec<&str> {
+        self.signatures
+            .iter()
+            .map(|signature| {
+                signature.split(':').next().unwrap_or(signature.as_str())
+            })
+            .collect()
+    }
+}
+
+/// The metadata for a single path, the way `nix path-info --json` writes
+/// it.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonPathInfo {
+    /// Only output by older versions of Nix.  Newer versions use the path as
+    /// the key of the object holding the metadata instead.
+    path: Option<String>,
+    valid: Option<bool>,
+    nar_hash: Option<String>,
+    nar_size: Option<u64>,
+    closure_size: Option<u64>,
+    registration_time: Option<u64>,
+    deriver: Option<String>,
+    #[serde(default)]
+    signatures: Vec<String>,
+    #[serde(default)]
+    references: Vec<String>,
+}
+
+impl JsonPathInfo {
+    fn into_path_info(self, path: &str) -> PathInfo {
+        PathInfo {
+            path: NixQueryDrv::from(path),
+            nar_hash: self.nar_hash,
+            nar_size: self.nar_size,
+            closure_size: self.closure_size,
+            registration_time: self.registration_time,
+            deriver: self.deriver.as_deref().map(NixQueryDrv::from),
+            signatures: self.signatures,
+            references: self
+                .references
+                .iter()
+                .map(|reference| NixQueryDrv::from(reference.as_str()))
+                .collect(),
+        }
+    }
+}
+
+/// The whole output of `nix path-info --json`.
+///
+/// Older versions of Nix output an array of objects that each have a `path`
+/// member, while newer versions output an object with a member for each
+/// path.  Paths that aren't valid are `null`, or have `valid` set to
+/// `false`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonPathInfos {
+    Array(Vec<Option<JsonPathInfo>>),
+    Object(serde_json::Map<String, serde_json::Value>),
+}
+
+/// Parse the output of `nix path-info --json`.  Paths that aren't valid are
+/// skipped.  With the output of newer versions of Nix, the paths come out
+/// sorted.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::path_info::path_info_parser;
+///
+/// let raw_path_info = r#"[{"path":"/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10","narSize":206120,"references":[]}]"#;
+/// let path_infos = path_info_parser(raw_path_info).unwrap();
+///
+/// assert_eq!(path_infos.len(), 1);
+/// assert_eq!(path_infos[0].nar_size, Some(206120));
+/// assert_eq!(path_infos[0].closure_size, None);
+/// ```
+pub fn path_info_parser(
+    input: &str,
+) -> Result<Vec<PathInfo>, serde_json::Error> {
+    let is_valid = |info: &JsonPathInfo| info.valid != Some(false);
+
+    let mut path_infos = vec![];
+    match serde_json::from_str(input)? {
+        JsonPathInfos::Array(infos) => {
+            for info in infos.into_iter().flatten().filter(is_valid) {
+                let path = info
+                    .path
+                    .clone()
+                    .ok_or_else(|| serde_json::Error::missing_field("path"))?;
+                path_infos.push(info.into_path_info(&path));
+            }
+        }
+        JsonPathInfos::Object(members) => {
+            for (path, info) in members {
+                let option_info: Option<JsonPathInfo> =
+                    serde_json::from_value(info)?;
+                if let Some(info) = option_info.filter(is_valid) {
+                    path_infos.push(info.into_path_info(&path));
+                }
+            }
+        }
+    }
+    Ok(path_infos)
+}
+
+/// The `Closure` made up of the paths in `path_infos` and their references.
+pub fn path_infos_closure(path_infos: &[PathInfo]) -> Closure {
+    let mut closure = Closure::new();
+    for path_info in path_infos {
+        closure.insert_path(path_info.path.clone());
+        for reference in &path_info.references {
+            closure.insert_reference(path_info.path.clone(), reference.clone());
+        }
+    }
+    closure
+}
+
+/// A `NixQueryTree` along with the metadata for every path in it.
+pub type PathInfoTree = (NixQueryTree, Vec<PathInfo>);
+
+/// Parse the output of `nix path-info --json --recursive` into the
+/// `NixQueryTree` that `nix-store --query --tree` would print for the same
+/// path, along with the metadata for every path in it.
+///
+/// As with the `nix-store` output, each path is only expanded the first time
+/// it is seen, and is a `[...]` entry after that.  This fails if the closure
+/// doesn't have exactly one path that nothing else refers to, which is the
+/// case when `nix path-info` is passed more than one path.
+///
+/// ```
+/// use indoc::indoc;
+/// use nix_query_tree_viewer::nix_query_tree::path_info::path_info_tree_parser;
+/// use nix_query_tree_viewer::nix_query_tree::NixQueryTree;
+/// use std::str::FromStr;
+///
+/// let raw_path_info = indoc!(
+///         r#"[
+///         {"path":"/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10","narSize":206120,"references":["/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27","/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10"]},
+///         {"path":"/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27","narSize":28912304,"references":["/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27"]}
+///         ]"#
+///     );
+/// let raw_tree = indoc!(
+///         "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+///         +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+///         |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+///         +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+///         "
+///     );
+/// let (nix_query_tree, path_infos) =
+///     path_info_tree_parser(raw_path_info).unwrap();
+///
+/// assert_eq!(nix_query_tree, NixQueryTree::from_str(raw_tree).unwrap());
+/// assert_eq!(path_infos[1].nar_size, Some(28912304));
+/// ```
+pub fn path_info_tree_parser(
+    input: &str,
+) -> Result<PathInfoTree, serde_json::Error> {
+    let path_infos = path_info_parser(input)?;
+    let closure = path_infos_closure(&path_infos);
+    match closure.roots().as_slice() {
+        [root] => Ok((closure.tree(root), path_infos)),
+        _ => Err(serde_json::Error::custom(
+            "expected exactly one path that no other path refers to",
+        )),
+    }
+}
+
+/// Format a size in bytes with binary units, like `1.5 MiB`.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::path_info::format_size;
+///
+/// assert_eq!(format_size(512), "512 B");
+/// assert_eq!(format_size(1536), "1.5 KiB");
+/// assert_eq!(format_size(206_120), "201.3 KiB");
+/// ```
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next_unit in &UNITS[1..] {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next_unit;
+    }
+    format!("{:.1} {}", size, unit)
+}
+
+/// Format a time in seconds since the epoch as a UTC date and time, like
+/// `2020-01-25 12:34:56`.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::path_info::format_timestamp;
+///
+/// assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+/// assert_eq!(format_timestamp(1_579_955_696), "2020-01-25 12:34:56");
+/// ```
+pub fn format_timestamp(secs: u64) -> String {
+    let days = secs / 86400;
+    let secs_of_day = secs % 86400;
+
+    // This converts days since the epoch to a date in the proleptic
+    // Gregorian calendar, counting in 400-year eras that start on March 1st.
+    let days_from_era_start = days + 719_468;
+    let era = days_from_era_start / 146_097;
+    let day_of_era = days_from_era_start % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524
+        - day_of_era / 146_096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_path_info_parser() {
+        let hello: NixQueryDrv =
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10".into();
+        let glibc: NixQueryDrv =
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27".into();
+        let hello_drv: NixQueryDrv =
+            "/nix/store/jymg0kanmlgbcv35wxd8d660rw0fawhv-hello-2.10.drv".into();
+
+        let hello_path_info = PathInfo {
+            path: hello.clone(),
+            nar_hash: Some(String::from(
+                "sha256:1wlwxr4s3pdb6yjvmvbzmbkfysrg9bdsqpm1qnrw3bj8nmjc6z9d",
+            )),
+            nar_size: Some(206_120),
+            closure_size: Some(29_118_424),
+            registration_time: Some(1_579_955_696),
+            deriver: Some(hello_drv),
+            signatures: vec![String::from("cache.nixos.org-1:abc==")],
+            references: vec![glibc.clone(), hello.clone()],
+        };
+        let glibc_path_info = PathInfo {
+            path: glibc.clone(),
+            nar_hash: None,
+            nar_size: Some(28_912_304),
+            closure_size: None,
+            registration_time: None,
+            deriver: None,
+            signatures: vec![],
+            references: vec![glibc.clone()],
+        };
+
+        let old_format = indoc!(
+            r#"[{"path":"/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10","narHash":"sha256:1wlwxr4s3pdb6yjvmvbzmbkfysrg9bdsqpm1qnrw3bj8nmjc6z9d","narSize":206120,"closureSize":29118424,"references":["/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27","/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10"],"deriver":"/nix/store/jymg0kanmlgbcv35wxd8d660rw0fawhv-hello-2.10.drv","registrationTime":1579955696,"signatures":["cache.nixos.org-1:abc=="]},
+            {"path":"/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27","narSize":28912304,"references":["/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27"]},
+            {"path":"/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43","valid":false}]
+            "#
+        );
+        assert_eq!(
+            path_info_parser(old_format).unwrap(),
+            vec![hello_path_info.clone(), glibc_path_info.clone()]
+        );
+
+        let new_format = indoc!(
+            r#"{
+              "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10": {"narHash":"sha256:1wlwxr4s3pdb6yjvmvbzmbkfysrg9bdsqpm1qnrw3bj8nmjc6z9d","narSize":206120,"closureSize":29118424,"references":["/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27","/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10"],"deriver":"/nix/store/jymg0kanmlgbcv35wxd8d660rw0fawhv-hello-2.10.drv","registrationTime":1579955696,"signatures":["cache.nixos.org-1:abc=="]},
+              "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27": {"narSize":28912304,"references":["/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27"],"deriver":null},
+              "/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43": null
+            }
+            "#
+        );
+        // The members of the object come out sorted by path.
+        assert_eq!(
+            path_info_parser(new_format).unwrap(),
+            vec![glibc_path_info, hello_path_info.clone()]
+        );
+
+        assert_eq!(
+            hello_path_info.signature_key_names(),
+            vec!["cache.nixos.org-1"]
+        );
+        assert!(path_info_parser(r#"[{"narSize":1}]"#).is_err());
+    }
+
+    #[test]
+    fn test_path_info_tree_parser() {
+        // bash comes before glibc because neither refers to the other and
+        // bash has the smaller hash, so pcre is a `[...]` entry under glibc.
+        let raw_input = indoc!(
+            r#"{
+              "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10": {"narSize":206120,"references":["/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23","/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27"]},
+              "/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23": {"narSize":1000,"references":["/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43","/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23"]},
+              "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27": {"narSize":20000,"references":["/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43"]},
+              "/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43": {"narSize":300,"references":[]}
+            }"#
+        );
+        let raw_tree = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23
+            |   +---/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43
+            |   +---/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23 [...]
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+                +---/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43 [...]
+            "
+        );
+        let (nix_query_tree, path_infos) =
+            path_info_tree_parser(raw_input).unwrap();
+
+        assert_eq!(nix_query_tree, NixQueryTree::from_str(raw_tree).unwrap());
+        assert_eq!(path_infos.len(), 4);
+
+        // Two paths that nothing refers to.
+        let two_roots = indoc!(
+            r#"{
+              "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10": {"references":[]},
+              "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27": {"references":[]}
+            }"#
+        );
+        assert!(path_info_tree_parser(two_roots).is_err());
+        assert!(path_info_tree_parser("[]").is_err());
+    }
+}
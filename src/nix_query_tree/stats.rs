@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+
+use super::graph::NixQueryGraph;
+use super::{NixQueryDrv, NixQueryTree, Recurse};
+
+/// Summary statistics about the closure in a `NixQueryTree`.
+///
+/// ```
+/// use indoc::indoc;
+/// use nix_query_tree_viewer::nix_query_tree::stats::NixQueryTreeStats;
+/// use nix_query_tree_viewer::nix_query_tree::{NixQueryDrv, NixQueryTree};
+/// use std::str::FromStr;
+///
+/// let raw_tree = indoc!(
+///         "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+///         +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+///         |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+///         +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+///         "
+///     );
+/// let nix_query_tree = NixQueryTree::from_str(raw_tree).unwrap();
+/// let stats = NixQueryTreeStats::new(&nix_query_tree, &nix_query_tree.graph());
+///
+/// assert_eq!(stats.node_count, 4);
+/// assert_eq!(stats.unique_count, 2);
+/// assert_eq!(stats.recurse_count, 2);
+/// assert_eq!(stats.max_depth, 2);
+/// assert_eq!(stats.recurse_ratio(), 0.5);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct NixQueryTreeStats {
+    /// The total number of entries in the tree, including `[...]` entries.
+    pub node_count: usize,
+
+    /// The number of unique `NixQueryDrv`s in the tree.
+    pub unique_count: usize,
+
+    /// The number of `[...]` entries in the tree.
+    pub recurse_count: usize,
+
+    /// The depth of the deepest entry in the tree.  The root has depth 0.
+    pub max_depth: usize,
+
+    /// The average depth of all the entries in the tree.
+    pub avg_depth: f64,
+
+    /// A mapping from a number of direct references to the number of unique
+    /// `NixQueryDrv`s that have exactly that many references.
+    pub fan_out: BTreeMap<usize, usize>,
+
+    /// Every unique `NixQueryDrv` along with the number of other
+    /// `NixQueryDrv`s that directly reference it.
+    ///
+    /// This is sorted so that the most referenced `NixQueryDrv` comes first.
+    /// A `NixQueryDrv` referencing itself is not counted.
+    pub referrer_counts: Vec<(NixQueryDrv, usize)>,
+}
+
+impl NixQueryTreeStats {
+    /// Compute the statistics for a `NixQueryTree`.  `graph` must be the
+    /// `NixQueryGraph` built from `nix_query_tree`.
+    pub fn new(nix_query_tree: &NixQueryTree, graph: &NixQueryGraph) -> Self {
+        let mut node_count = 0;
+        let mut recurse_count = 0;
+        let mut max_depth = 0;
+        let mut total_depth = 0;
+
+        for (path, nix_query_entry) in nix_query_tree.0.iter_pre_order() {
+            let depth = path.0.len();
+            node_count += 1;
+            total_depth += depth;
+            max_depth = max_depth.max(depth);
+            if nix_query_entry.1 == Recurse::Yes {
+                recurse_count += 1;
+            }
+        }
+
+        let mut fan_out: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut referrer_counts: Vec<(NixQueryDrv, usize)> = vec![];
+
+        for (index, nix_query_drv) in graph.iter() {
+            *fan_out.entry(graph.references(index).len()).or_insert(0) += 1;
+
+            let referrer_count = graph
+                .referrers(index)
+                .iter()
+                .filter(|&&referrer| referrer != index)
+                .count();
+            referrer_counts.push((nix_query_drv.clone(), referrer_count));
+        }
+
+        referrer_counts.sort_by(|(drv_a, count_a), (drv_b, count_b)| {
+            count_b.cmp(count_a).then_with(|| drv_a.cmp_drv_name(drv_b))
+        });
+
+        NixQueryTreeStats {
+            node_count,
+            unique_count: graph.len(),
+            recurse_count,
+            max_depth,
+            avg_depth: total_depth as f64 / node_count as f64,
+            fan_out,
+            referrer_counts,
+        }
+    }
+
+    /// The fraction of entries in the tree that are `[...]` entries.
+    pub fn recurse_ratio(&self) -> f64 {
+        self.recurse_count as f64 / self.node_count as f64
+    }
+
+    /// The `n` most referenced `NixQueryDrv`s, along with how many other
+    /// `NixQueryDrv`s reference them.
+    pub fn most_referenced(&self, n: usize) -> &[(NixQueryDrv, usize)] {
+        &self.referrer_counts[..n.min(self.referrer_counts.len())]
+    }
+}
+
+/// A one-line summary of the statistics.
+impl std::fmt::Display for NixQueryTreeStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} entries, {} unique paths, {:.1}% [...], max depth {}, average depth {:.2}",
+            self.node_count,
+            self.unique_count,
+            self.recurse_ratio() * 100.0,
+            self.max_depth,
+            self.avg_depth
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_stats() {
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            |   +---/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43
+            +---/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh
+                +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            "
+        );
+        let nix_query_tree = NixQueryTree::from_str(raw_input).unwrap();
+        let stats =
+            NixQueryTreeStats::new(&nix_query_tree, &nix_query_tree.graph());
+
+        let glibc_drv: NixQueryDrv =
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27".into();
+        let multiple_outputs_drv: NixQueryDrv =
+            "/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh"
+                .into();
+
+        let mut fan_out = BTreeMap::new();
+        fan_out.insert(0, 1);
+        fan_out.insert(1, 2);
+        fan_out.insert(2, 1);
+
+        assert_eq!(stats.node_count, 5);
+        assert_eq!(stats.unique_count, 4);
+        assert_eq!(stats.recurse_count, 1);
+        assert_eq!(stats.max_depth, 2);
+        assert!((stats.avg_depth - 6.0 / 5.0).abs() < f64::EPSILON);
+        assert_eq!(stats.fan_out, fan_out);
+        assert_eq!(
+            stats.most_referenced(2),
+            &[(glibc_drv, 2), (multiple_outputs_drv, 1)][..]
+        );
+        assert_eq!(stats.most_referenced(10).len(), 4);
+    }
+}
@@ -0,0 +1,334 @@
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+
+/// The store directory used when `NIX_STORE_DIR` isn't set.
+pub const DEFAULT_STORE_DIR: &str = "/nix/store";
+
+/// The length of the hash part of a nix store path.
+pub const HASH_LEN: usize = 32;
+
+/// The characters that can appear in the hash part of a nix store path.  This
+/// is nix's base-32 alphabet, which leaves out `e`, `o`, `u` and `t`.
+pub const NIX32_CHARS: &str = "0123456789abcdfghijklmnpqrsvwxyz";
+
+/// Output names that nix appends to the name of a store path for every output
+/// of a derivation other than `out`.
+pub const OUTPUT_NAMES: [&str; 9] = [
+    "lib", "dev", "bin", "out", "man", "doc", "info", "debug", "static",
+];
+
+/// The reasons a path can fail to parse as a `StorePath`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StorePathErr {
+    /// The path isn't directly inside the store directory.
+    NotInStoreDir(String),
+    /// The file name doesn't start with a valid nix32 hash followed by a `-`.
+    InvalidHash(String),
+    /// There is nothing after the hash.
+    MissingName(String),
+}
+
+impl std::fmt::Display for StorePathErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorePathErr::NotInStoreDir(path) => {
+                write!(f, "{} is not in a nix store directory", path)
+            }
+            StorePathErr::InvalidHash(path) => write!(
+                f,
+                "{} does not start with a {} character nix32 hash",
+                path, HASH_LEN
+            ),
+            StorePathErr::MissingName(path) => {
+                write!(f, "{} has no name after the hash", path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StorePathErr {}
+
+/// A nix store path, split into its parts.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::store_path::StorePath;
+/// use std::path::Path;
+///
+/// let store_path: StorePath =
+///     "/nix/store/hlnxw4k6931bachvg5sv0cyaissimswb-gcc-7.4.0-lib".parse().unwrap();
+///
+/// assert_eq!(store_path.store_dir, Path::new("/nix/store"));
+/// assert_eq!(store_path.hash, "hlnxw4k6931bachvg5sv0cyaissimswb");
+/// assert_eq!(store_path.name, "gcc-7.4.0-lib");
+/// assert_eq!(store_path.pname, "gcc");
+/// assert_eq!(store_path.version, "7.4.0");
+/// assert_eq!(store_path.output.as_deref(), Some("lib"));
+/// assert!(!store_path.is_drv);
+///
+/// assert!("/nix/store/not-a-hash-gcc-7.4.0".parse::<StorePath>().is_err());
+/// ```
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct StorePath {
+    /// The directory the path is in, normally `/nix/store`.
+    pub store_dir: PathBuf,
+
+    /// The nix32 hash at the start of the file name.
+    pub hash: String,
+
+    /// Everything in the file name after the hash and the `-`.
+    pub name: String,
+
+    /// The package name, as returned by `builtins.parseDrvName` on `name`
+    /// without the output suffix or `.drv` extension.
+    pub pname: String,
+
+    /// The version, as returned by `builtins.parseDrvName`.  This is empty if
+    /// there is no version.
+    pub version: String,
+
+    /// The output suffix of `name`, like `lib` in `gcc-7.4.0-lib`.  This is
+    /// `None` for the default output.
+    pub output: Option<String>,
+
+    /// Whether this is the path of a `.drv` file.
+    pub is_drv: bool,
+}
+
+impl StorePath {
+    /// Parse a path into a `StorePath`.  The store directory is taken to be
+    /// the parent directory of `path`, so this works for paths in any store
+    /// directory.
+    pub fn new(path: &Path) -> Result<StorePath, StorePathErr> {
+        let path_str = || path.to_string_lossy().into_owned();
+
+        let (store_dir, file_name) = match (path.parent(), path.file_name()) {
+            (Some(store_dir), Some(file_name))
+                if !store_dir.as_os_str().is_empty() =>
+            {
+                (store_dir, file_name.to_string_lossy())
+            }
+            _ => return Err(StorePathErr::NotInStoreDir(path_str())),
+        };
+
+        let hash = file_name
+            .get(..HASH_LEN)
+            .filter(|hash| hash.chars().all(|c| NIX32_CHARS.contains(c)))
+            .filter(|_| file_name[HASH_LEN..].starts_with('-'))
+            .ok_or_else(|| StorePathErr::InvalidHash(path_str()))?;
+
+        let name = &file_name[HASH_LEN + 1..];
+        if name.is_empty() {
+            return Err(StorePathErr::MissingName(path_str()));
+        }
+
+        let is_drv = name.ends_with(".drv");
+        let name_no_drv = name.trim_end_matches(".drv");
+        let (name_no_output, output) = split_output(name_no_drv);
+        let (pname, version) = parse_drv_name(name_no_output);
+
+        Ok(StorePath {
+            store_dir: store_dir.to_path_buf(),
+            hash: hash.to_string(),
+            name: name.to_string(),
+            pname,
+            version,
+            output: output.map(String::from),
+            is_drv,
+        })
+    }
+}
+
+impl std::str::FromStr for StorePath {
+    type Err = StorePathErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        StorePath::new(Path::new(s))
+    }
+}
+
+/// Split a known output suffix off of the name of a store path.
+fn split_output(name: &str) -> (&str, Option<&str>) {
+    match name.rfind('-') {
+        Some(i) if i > 0 && OUTPUT_NAMES.contains(&&name[i + 1..]) => {
+            (&name[..i], Some(&name[i + 1..]))
+        }
+        _ => (name, None),
+    }
+}
+
+/// Split a derivation name into a package name and a version, the same way
+/// `builtins.parseDrvName` does.  The package name ends at the first `-` that
+/// is not followed by a letter.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::store_path::parse_drv_name;
+///
+/// assert_eq!(
+///     parse_drv_name("bash-interactive-4.4-p23"),
+///     (String::from("bash-interactive"), String::from("4.4-p23"))
+/// );
+/// assert_eq!(
+///     parse_drv_name("multiple-outputs.sh"),
+///     (String::from("multiple-outputs.sh"), String::new())
+/// );
+/// ```
+pub fn parse_drv_name(drv_name: &str) -> (String, String) {
+    let option_version_dash = drv_name
+        .char_indices()
+        .zip(drv_name.chars().skip(1))
+        .find(|((_, c), next_c)| *c == '-' && !next_c.is_alphabetic())
+        .map(|((i, _), _)| i);
+    match option_version_dash {
+        None => (drv_name.to_string(), String::new()),
+        Some(i) => (drv_name[..i].to_string(), drv_name[i + 1..].to_string()),
+    }
+}
+
+/// Split the next component off of a version string, skipping any leading
+/// `.` and `-` separators.  A component is either a run of digits, or a run of
+/// anything other than digits and separators.
+fn next_version_component(version: &str) -> (&str, &str) {
+    let version = version.trim_start_matches(&['.', '-'][..]);
+    let is_digit = |c: char| c.is_ascii_digit();
+    let end = if version.starts_with(is_digit) {
+        version.find(|c: char| !is_digit(c))
+    } else {
+        version.find(|c: char| is_digit(c) || c == '.' || c == '-')
+    }
+    .unwrap_or(version.len());
+    (&version[..end], &version[end..])
+}
+
+/// Whether version component `c1` is lower than `c2`.
+fn version_component_lt(c1: &str, c2: &str) -> bool {
+    let n1 = c1.parse::<u64>().ok();
+    let n2 = c2.parse::<u64>().ok();
+    match (n1, n2) {
+        (Some(n1), Some(n2)) => n1 < n2,
+        (_, Some(_)) if c1.is_empty() => true,
+        _ if c1 == "pre" && c2 != "pre" => true,
+        _ if c2 == "pre" => false,
+        // `2.3a` is lower than `2.3.1`.
+        (_, Some(_)) => true,
+        (Some(_), _) => false,
+        _ => c1 < c2,
+    }
+}
+
+/// Compare two versions with the same rules as `builtins.compareVersions`.
+///
+/// Versions are compared component by component.  Numbers are compared
+/// numerically, a missing component is lower than a number, `pre` is lower
+/// than anything else, and any other string is lower than a number.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::store_path::compare_versions;
+/// use std::cmp::Ordering;
+///
+/// assert_eq!(compare_versions("9.3", "10.2"), Ordering::Less);
+/// assert_eq!(compare_versions("2.3pre1", "2.3"), Ordering::Less);
+/// assert_eq!(compare_versions("2.3a", "2.3.1"), Ordering::Less);
+/// assert_eq!(compare_versions("1.0", "1.0"), Ordering::Equal);
+/// ```
+pub fn compare_versions(v1: &str, v2: &str) -> Ordering {
+    let (mut rest1, mut rest2) = (v1, v2);
+    while !rest1.is_empty() || !rest2.is_empty() {
+        let (c1, next1) = next_version_component(rest1);
+        let (c2, next2) = next_version_component(rest2);
+        if version_component_lt(c1, c2) {
+            return Ordering::Less;
+        } else if version_component_lt(c2, c1) {
+            return Ordering::Greater;
+        }
+        rest1 = next1;
+        rest2 = next2;
+    }
+    Ordering::Equal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_path_drv() {
+        let store_path: StorePath =
+            "/nix/store/dyxdjxyszmlz29mb0jr9qkncj5l41dai-gcc-wrapper-7.4.0.drv"
+                .parse()
+                .unwrap();
+
+        assert_eq!(store_path.name, "gcc-wrapper-7.4.0.drv");
+        assert_eq!(store_path.pname, "gcc-wrapper");
+        assert_eq!(store_path.version, "7.4.0");
+        assert_eq!(store_path.output, None);
+        assert!(store_path.is_drv);
+    }
+
+    #[test]
+    fn test_store_path_no_version() {
+        let store_path: StorePath =
+            "/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh"
+                .parse()
+                .unwrap();
+
+        assert_eq!(store_path.pname, "multiple-outputs.sh");
+        assert_eq!(store_path.version, "");
+        assert_eq!(store_path.output, None);
+    }
+
+    #[test]
+    fn test_store_path_errors() {
+        let invalid_hash =
+            "/nix/store/eeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee-hello-2.10";
+        let short_hash = "/nix/store/qy93dp4a-hello-2.10";
+        let missing_name = "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-";
+
+        assert_eq!(
+            invalid_hash.parse::<StorePath>(),
+            Err(StorePathErr::InvalidHash(String::from(invalid_hash)))
+        );
+        assert_eq!(
+            short_hash.parse::<StorePath>(),
+            Err(StorePathErr::InvalidHash(String::from(short_hash)))
+        );
+        assert_eq!(
+            missing_name.parse::<StorePath>(),
+            Err(StorePathErr::MissingName(String::from(missing_name)))
+        );
+        assert_eq!(
+            "hello".parse::<StorePath>(),
+            Err(StorePathErr::NotInStoreDir(String::from("hello")))
+        );
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        let cases = [
+            ("1.0", "2.3", Ordering::Less),
+            ("2.1", "2.3", Ordering::Less),
+            ("2.3", "2.3", Ordering::Equal),
+            ("2.5", "2.3", Ordering::Greater),
+            ("3.1", "2.3", Ordering::Greater),
+            ("2.3.1", "2.3", Ordering::Greater),
+            ("2.3.1", "2.3a", Ordering::Greater),
+            ("2.3pre1", "2.3", Ordering::Less),
+            ("2.3pre3", "2.3pre12", Ordering::Less),
+            ("2.3a", "2.3c", Ordering::Less),
+            ("2.3pre1", "2.3c", Ordering::Less),
+            ("2.3pre1", "2.3q", Ordering::Less),
+            ("10.2", "9.3", Ordering::Greater),
+            ("4.4-p23", "4.4-p3", Ordering::Greater),
+            ("", "1", Ordering::Less),
+        ];
+        for (v1, v2, expected) in &cases {
+            assert_eq!(compare_versions(v1, v2), *expected, "{} {}", v1, v2);
+            assert_eq!(
+                compare_versions(v2, v1),
+                expected.reverse(),
+                "{} {}",
+                v2,
+                v1
+            );
+        }
+    }
+}
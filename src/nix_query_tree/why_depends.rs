@@ -0,0 +1,156 @@
+use std::collections::{HashMap, HashSet};
+
+use super::super::tree::Tree;
+use super::{NixQueryDrv, NixQueryEntry, NixQueryTree, Recurse};
+
+/// The excerpts of the files in a path that refer to another path, keyed by
+/// the referring path and the path it refers to.
+pub type Excerpts = HashMap<(NixQueryDrv, NixQueryDrv), Vec<String>>;
+
+/// The output of `nix why-depends`, which shows the chains of references
+/// from one path to another.
+///
+/// With `--precise`, `nix why-depends` also shows an excerpt of each file
+/// that refers to the next path in the chain, like
+/// `bin/hello: …/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27/lib…`.
+/// These are kept in `excerpts` for each reference, since every instance of
+/// a reference in the tree has the same excerpts.
+///
+/// `tree` has the same `[...]` semantics as the output of `nix-store --query
+/// --tree`: only the first instance of a path has children.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WhyDepends {
+    pub tree: NixQueryTree,
+    pub excerpts: Excerpts,
+}
+
+impl WhyDepends {
+    /// Build a `WhyDepends` from the tree printed by `nix why-depends`, where
+    /// each node has the excerpts shown for the reference to it from its
+    /// parent.
+    pub fn new(tree: &Tree<(NixQueryDrv, Vec<String>)>) -> Self {
+        let mut excerpts = Excerpts::new();
+        let tree = build_tree(tree, None, &mut HashSet::new(), &mut excerpts);
+        WhyDepends {
+            tree: NixQueryTree(tree),
+            excerpts,
+        }
+    }
+
+    /// The excerpts shown for the reference from `referrer` to `reference`.
+    /// This is empty for output without `--precise`.
+    pub fn excerpts(
+        &self,
+        referrer: &NixQueryDrv,
+        reference: &NixQueryDrv,
+    ) -> &[String] {
+        lookup_excerpts(&self.excerpts, referrer, reference)
+    }
+}
+
+/// Look up the excerpts for the reference from `referrer` to `reference`.
+pub fn lookup_excerpts<'a>(
+    excerpts: &'a Excerpts,
+    referrer: &NixQueryDrv,
+    reference: &NixQueryDrv,
+) -> &'a [String] {
+    excerpts
+        .get(&(referrer.clone(), reference.clone()))
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+/// Turn every instance of a path after the first into a `[...]` entry, and
+/// collect the excerpts of every reference into `excerpts`.
+///
+/// `nix why-depends --all` prints a path again each time another chain goes
+/// through it, with or without its children, so this makes it look like
+/// `nix-store --query --tree` output.
+fn build_tree(
+    tree: &Tree<(NixQueryDrv, Vec<String>)>,
+    parent: Option<&NixQueryDrv>,
+    expanded: &mut HashSet<NixQueryDrv>,
+    excerpts: &mut Excerpts,
+) -> Tree<NixQueryEntry> {
+    let (drv, node_excerpts) = &tree.item;
+    if let Some(parent) = parent {
+        if !node_excerpts.is_empty() {
+            let edge_excerpts =
+                excerpts.entry((parent.clone(), drv.clone())).or_default();
+            for excerpt in node_excerpts {
+                if !edge_excerpts.contains(excerpt) {
+                    edge_excerpts.push(excerpt.clone());
+                }
+            }
+        }
+    }
+
+    let first_instance = expanded.insert(drv.clone());
+    let children: Vec<Tree<NixQueryEntry>> = tree
+        .children
+        .iter()
+        .map(|child| build_tree(child, Some(drv), expanded, excerpts))
+        .collect();
+    if first_instance {
+        Tree::new(NixQueryEntry(drv.clone(), Recurse::No), children)
+    } else {
+        Tree::singleton(NixQueryEntry(drv.clone(), Recurse::Yes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_why_depends_new() {
+        let hello: NixQueryDrv =
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10".into();
+        let glibc: NixQueryDrv =
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27".into();
+        let bash: NixQueryDrv =
+            "/nix/store/cinw572b38aln37glr0zb8lxwrgaffl4-bash-4.4-p23".into();
+        let excerpt = |s: &str| vec![String::from(s)];
+
+        let raw_tree = Tree::new(
+            (hello.clone(), vec![]),
+            vec![
+                Tree::new(
+                    (bash.clone(), excerpt("bin/hello: …bash…")),
+                    vec![Tree::singleton((
+                        glibc.clone(),
+                        excerpt("bin/bash: …glibc…"),
+                    ))],
+                ),
+                Tree::singleton((glibc.clone(), excerpt("bin/hello: …glibc…"))),
+            ],
+        );
+        let why_depends = WhyDepends::new(&raw_tree);
+
+        assert_eq!(
+            why_depends.tree.0,
+            Tree::new(
+                NixQueryEntry(hello.clone(), Recurse::No),
+                vec![
+                    Tree::new(
+                        NixQueryEntry(bash.clone(), Recurse::No),
+                        vec![Tree::singleton(NixQueryEntry(
+                            glibc.clone(),
+                            Recurse::No
+                        ))],
+                    ),
+                    Tree::singleton(NixQueryEntry(glibc.clone(), Recurse::Yes)),
+                ],
+            )
+        );
+        assert_eq!(
+            why_depends.excerpts(&hello, &glibc),
+            excerpt("bin/hello: …glibc…").as_slice()
+        );
+        assert_eq!(
+            why_depends.excerpts(&bash, &glibc),
+            excerpt("bin/bash: …glibc…").as_slice()
+        );
+        assert!(why_depends.excerpts(&glibc, &hello).is_empty());
+    }
+}
@@ -1,16 +1,95 @@
 use std::path::PathBuf;
 use structopt::StructOpt;
 
+use super::nix_query_tree::exec_nix_store::ReferencesFiles;
+use super::tree;
+
 #[derive(Debug, StructOpt)]
 #[structopt(about = "GUI viewer for `nix store --query --tree` output.")]
 pub struct Opts {
     /// PATH in /nix/store to view references of
-    #[structopt(name = "PATH", parse(from_os_str))]
-    pub nix_store_path: PathBuf,
+    #[structopt(
+        name = "PATH",
+        required_unless_one = &["WHY_DEPENDS_FILE", "PATH_INFO_FILE"],
+        parse(from_os_str)
+    )]
+    pub nix_store_path: Option<PathBuf>,
+
+    /// Location of a node in the tree to go to after loading, like `0.2.1`
+    #[structopt(long = "goto", name = "LOCATION")]
+    pub goto: Option<tree::Path>,
+
+    /// Only load the references of a path when it is expanded, using
+    /// `nix-store --query --references`.  This is useful for huge closures.
+    #[structopt(long = "lazy")]
+    pub lazy: bool,
+
+    /// Build the tree from the output of `nix-store --query --requisites` in
+    /// FILE instead of running `nix-store`.  This needs `--references`.
+    #[structopt(
+        long = "requisites",
+        name = "REQUISITES_FILE",
+        requires = "REFERENCES_FILE",
+        parse(from_os_str)
+    )]
+    pub requisites: Option<PathBuf>,
+
+    /// The references of every path for `--requisites`, in the format output
+    /// by `nix-store --dump-db`
+    #[structopt(
+        long = "references",
+        name = "REFERENCES_FILE",
+        requires = "REQUISITES_FILE",
+        parse(from_os_str)
+    )]
+    pub references: Option<PathBuf>,
+
+    /// Show the output of `nix why-depends` in FILE instead of running
+    /// `nix-store`.  Excerpts from `nix why-depends --precise` are shown as
+    /// tooltips.
+    #[structopt(
+        long = "why-depends",
+        name = "WHY_DEPENDS_FILE",
+        conflicts_with_all = &["PATH", "REQUISITES_FILE"],
+        parse(from_os_str)
+    )]
+    pub why_depends: Option<PathBuf>,
+
+    /// Build the tree from the output of `nix path-info --json --recursive`
+    /// in FILE instead of running `nix-store`.  The metadata of every path is
+    /// taken from FILE as well.
+    #[structopt(
+        long = "path-info",
+        name = "PATH_INFO_FILE",
+        conflicts_with_all = &["PATH", "REQUISITES_FILE", "WHY_DEPENDS_FILE"],
+        parse(from_os_str)
+    )]
+    pub path_info: Option<PathBuf>,
+
+    /// Directory of the nix store, if it isn't the default `/nix/store`
+    #[structopt(
+        long = "store-dir",
+        name = "DIR",
+        env = "NIX_STORE_DIR",
+        default_value = "/nix/store",
+        parse(from_os_str)
+    )]
+    pub store_dir: PathBuf,
 }
 
 impl Opts {
     pub fn parse_from_args() -> Self {
         Opts::from_args()
     }
+
+    /// The files given with `--requisites` and `--references`, if any.
+    pub fn references_files(&self) -> Option<ReferencesFiles> {
+        match (&self.requisites, &self.references) {
+            (Some(requisites), Some(references)) => Some(ReferencesFiles {
+                requisites: requisites.clone(),
+                references: references.clone(),
+            }),
+            _ => None,
+        }
+    }
 }
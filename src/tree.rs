@@ -1,7 +1,17 @@
 use std::collections::{HashMap, VecDeque};
 use std::hash::Hash;
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+use serde::{Deserialize, Serialize};
+
+/// A rose tree.
+///
+/// This is serialized as an object with two fields: `item` and `children`.
+/// For example, in JSON:
+///
+/// ```json
+/// { "item": 1, "children": [ { "item": 2, "children": [] } ] }
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Tree<T> {
     pub item: T,
     pub children: Vec<Tree<T>>,
@@ -18,15 +28,46 @@ impl<T> Tree<T> {
 
     /// Lookup the item in the `Tree` that corresponds to the given `Path`.
     pub fn lookup(&self, path: Path) -> Option<&T> {
+        self.lookup_tree(path).map(|tree| &tree.item)
+    }
+
+    /// Lookup the sub-`Tree` rooted at the given `Path`.
+    pub fn lookup_tree(&self, path: Path) -> Option<&Tree<T>> {
         match path.split_front() {
-            None => Some(&self.item),
+            None => Some(self),
             Some((index, child_path)) => match self.children.get(index) {
                 None => None,
-                Some(child_tree) => child_tree.lookup(child_path),
+                Some(child_tree) => child_tree.lookup_tree(child_path),
             },
         }
     }
 
+    /// Lookup the sub-`Tree` rooted at the given `Path`, allowing it to be
+    /// modified.
+    pub fn lookup_tree_mut(&mut self, path: Path) -> Option<&mut Tree<T>> {
+        match path.split_front() {
+            None => Some(self),
+            Some((index, child_path)) => match self.children.get_mut(index) {
+                None => None,
+                Some(child_tree) => child_tree.lookup_tree_mut(child_path),
+            },
+        }
+    }
+
+    /// Lookup the item at the given `Path`, along with all of its ancestors.
+    ///
+    /// The returned `Vec` starts with the root of the `Tree` and ends with the
+    /// item at `path`.
+    pub fn lookup_with_ancestors(&self, path: Path) -> Option<Vec<&T>> {
+        let mut tree = self;
+        let mut items = vec![&tree.item];
+        for index in path.0 {
+            tree = tree.children.get(index)?;
+            items.push(&tree.item);
+        }
+        Some(items)
+    }
+
     /// Similar to `path_map`, but take a function for mapping an item in the tree to an
     /// alternative type to use to construct the `TreePathMap`.
     ///
@@ -37,11 +78,110 @@ impl<T> Tree<T> {
         U: Eq + Hash,
     {
         let mut map = TreePathMap::new();
-        let root_path = Path::new();
-        map.insert(f(&self.item), root_path.clone());
-        map.insert_children_map(&self.children, &root_path, f);
+        for (path, item) in self.iter_pre_order() {
+            map.insert(f(item), path);
+        }
         map
     }
+
+    /// Iterate over every item in the `Tree` along with its `Path`.  Each item
+    /// comes before all of its children.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::tree::{Path, Tree};
+    ///
+    /// let tree = Tree::new(1, vec![Tree::new(2, vec![Tree::singleton(3)])]);
+    /// let items: Vec<(Path, &i32)> = tree.iter_pre_order().collect();
+    ///
+    /// assert_eq!(
+    ///     items,
+    ///     vec![
+    ///         (Path::new(), &1),
+    ///         (Path::from(vec![0]), &2),
+    ///         (Path::from(vec![0, 0]), &3),
+    ///     ]
+    /// );
+    /// ```
+    pub fn iter_pre_order(&self) -> PreOrderIter<'_, T> {
+        PreOrderIter {
+            stack: vec![(Path::new(), self)],
+        }
+    }
+
+    /// Iterate over every item in the `Tree` along with its `Path`.  Each item
+    /// comes after all of its children.
+    pub fn iter_post_order(&self) -> PostOrderIter<'_, T> {
+        PostOrderIter {
+            stack: vec![(Path::new(), self, 0)],
+        }
+    }
+
+    /// Collapse the `Tree` depth-first.  `f` is called on each item together
+    /// with the results of folding each of its children.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::tree::Tree;
+    ///
+    /// let tree = Tree::new(1, vec![Tree::new(2, vec![Tree::singleton(3)])]);
+    /// let sum = tree.fold(&|item, children: Vec<i32>| {
+    ///     item + children.iter().sum::<i32>()
+    /// });
+    ///
+    /// assert_eq!(sum, 6);
+    /// ```
+    pub fn fold<B>(&self, f: &dyn Fn(&T, Vec<B>) -> B) -> B {
+        let children_res: Vec<B> =
+            self.children.iter().map(|child| child.fold(f)).collect();
+        f(&self.item, children_res)
+    }
+
+    /// Create a new `Tree` with the same shape by applying `f` to every item.
+    pub fn map<U>(&self, f: &dyn Fn(&T) -> U) -> Tree<U> {
+        Tree::new(
+            f(&self.item),
+            self.children.iter().map(|child| child.map(f)).collect(),
+        )
+    }
+}
+
+impl<T> Tree<T>
+where
+    T: Clone,
+{
+    /// Create a new `Tree` only containing items that match the predicate,
+    /// along with all of their ancestors.
+    ///
+    /// This returns `None` if nothing in the `Tree` matches.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::tree::Tree;
+    ///
+    /// let tree = Tree::new(
+    ///     1,
+    ///     vec![
+    ///         Tree::new(2, vec![Tree::singleton(3), Tree::singleton(4)]),
+    ///         Tree::singleton(5),
+    ///     ],
+    /// );
+    ///
+    /// assert_eq!(
+    ///     tree.filter(&|&i| i == 4),
+    ///     Some(Tree::new(1, vec![Tree::new(2, vec![Tree::singleton(4)])]))
+    /// );
+    /// assert_eq!(tree.filter(&|&i| i == 6), None);
+    /// ```
+    pub fn filter(&self, predicate: &dyn Fn(&T) -> bool) -> Option<Tree<T>> {
+        let children: Vec<Tree<T>> = self
+            .children
+            .iter()
+            .filter_map(|child| child.filter(predicate))
+            .collect();
+        if children.is_empty() && !predicate(&self.item) {
+            None
+        } else {
+            Some(Tree::new(self.item.clone(), children))
+        }
+    }
 }
 
 impl<T> Tree<T>
@@ -55,7 +195,39 @@ where
 }
 
 /// This represents the path through a `Tree<T>` to a given node.
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+///
+/// `Path`s are ordered the same way as a pre-order walk of a `Tree`, so a
+/// `Path` always comes before the `Path`s of its descendants.
+///
+/// A `Path` can be converted to and from a dotted textual form, where each
+/// number is the index of a child, starting from the root.  The root itself
+/// is the empty string.
+///
+/// ```
+/// use nix_query_tree_viewer::tree::Path;
+///
+/// let path: Path = "0.2.1".parse().unwrap();
+///
+/// assert_eq!(path, Path::from(vec![0, 2, 1]));
+/// assert_eq!(path.to_string(), "0.2.1");
+/// assert_eq!("".parse(), Ok(Path::new()));
+/// ```
+///
+/// This is serialized as an array of child indices, starting from the root.
+/// For example, `[2, 0, 1]`.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Eq,
+    Hash,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(transparent)]
 pub struct Path(pub VecDeque<usize>);
 
 impl Path {
@@ -74,6 +246,70 @@ impl Path {
     pub fn new() -> Self {
         Path(VecDeque::new())
     }
+
+    /// The number of steps from the root to this `Path`.  The root has depth
+    /// 0.
+    pub fn depth(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The `Path` of the parent of this `Path`.  This returns `None` for the
+    /// root.
+    pub fn parent(&self) -> Option<Path> {
+        let mut parent = self.clone();
+        parent.0.pop_back().map(|_| parent)
+    }
+
+    /// Whether or not this `Path` is a strict ancestor of `other`.  A `Path`
+    /// is not an ancestor of itself.
+    pub fn is_ancestor_of(&self, other: &Path) -> bool {
+        self.depth() < other.depth()
+            && other.0.iter().zip(&self.0).all(|(a, b)| a == b)
+    }
+
+    /// The deepest `Path` that is either equal to or an ancestor of both
+    /// `self` and `other`.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::tree::Path;
+    ///
+    /// let path_a = Path::from(vec![0, 2, 1]);
+    /// let path_b = Path::from(vec![0, 2, 3, 4]);
+    ///
+    /// assert_eq!(path_a.common_ancestor(&path_b), Path::from(vec![0, 2]));
+    /// ```
+    pub fn common_ancestor(&self, other: &Path) -> Path {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| *a)
+            .collect::<VecDeque<usize>>()
+            .into()
+    }
+}
+
+impl std::fmt::Display for Path {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let strings: Vec<String> =
+            self.0.iter().map(ToString::to_string).collect();
+        write!(f, "{}", strings.join("."))
+    }
+}
+
+impl std::str::FromStr for Path {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            Ok(Path::new())
+        } else {
+            s.split('.')
+                .map(str::parse)
+                .collect::<Result<VecDeque<usize>, _>>()
+                .map(Path)
+        }
+    }
 }
 
 impl<T> From<T> for Path
@@ -108,31 +344,81 @@ where
             .or_insert_with(|| vec![path]);
     }
 
+    /// Insert a mapping from `U` to `Path`, keeping the `Path`s for `k` in
+    /// the order of a pre-order walk of the `Tree`.  This is for adding the
+    /// `Path`s of new nodes to an existing `TreePathMap`.
+    pub fn insert_ordered(&mut self, k: U, path: Path) {
+        let paths: &mut Vec<Path> = self.0.entry(k).or_default();
+        let pos = paths.binary_search(&path).unwrap_or_else(|pos| pos);
+        paths.insert(pos, path);
+    }
+
     /// Lookup the first `Path` for a given item.
     pub fn lookup_first(&self, k: &U) -> Option<&Path> {
         let option_paths: Option<&Vec<Path>> = self.0.get(k);
         option_paths.and_then(|vec: &Vec<Path>| vec.first())
     }
-}
 
-impl<U> TreePathMap<U>
-where
-    U: Eq + Hash,
-{
-    /// Insert child `Tree`s starting at `Path`.
+    /// Lookup all the `Path`s for a given item, in the order they were
+    /// inserted.
     ///
-    /// The function `f` will map the items in the `T` to an alternative type.
-    fn insert_children_map<T>(
-        &mut self,
-        children: &[Tree<T>],
-        path: &Path,
-        f: &dyn Fn(&T) -> U,
-    ) {
-        for (i, child) in children.iter().enumerate() {
+    /// This returns an empty slice if the item is not in the map.
+    pub fn lookup_all(&self, k: &U) -> &[Path] {
+        self.0.get(k).map_or(&[], Vec::as_slice)
+    }
+
+    /// Lookup the `n`th `Path` for a given item.  `n` starts from 0, so
+    /// `lookup_nth(k, 0)` is the same as `lookup_first(k)`.
+    pub fn lookup_nth(&self, k: &U, n: usize) -> Option<&Path> {
+        self.lookup_all(k).get(n)
+    }
+}
+
+/// Pre-order iterator over a `Tree`.  Created by `Tree::iter_pre_order`.
+pub struct PreOrderIter<'a, T> {
+    stack: Vec<(Path, &'a Tree<T>)>,
+}
+
+impl<'a, T> Iterator for PreOrderIter<'a, T> {
+    type Item = (Path, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, tree) = self.stack.pop()?;
+        for (i, child) in tree.children.iter().enumerate().rev() {
             let mut child_path = path.clone();
             child_path.push_back(i);
-            self.insert(f(&child.item), child_path.clone());
-            self.insert_children_map(&child.children, &child_path, f);
+            self.stack.push((child_path, child));
+        }
+        Some((path, &tree.item))
+    }
+}
+
+/// Post-order iterator over a `Tree`.  Created by `Tree::iter_post_order`.
+pub struct PostOrderIter<'a, T> {
+    /// Each entry holds the index of the next child that still needs to be
+    /// visited.
+    stack: Vec<(Path, &'a Tree<T>, usize)>,
+}
+
+impl<'a, T> Iterator for PostOrderIter<'a, T> {
+    type Item = (Path, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (path, tree, next_child) = self.stack.last_mut()?;
+            let tree: &'a Tree<T> = tree;
+            match tree.children.get(*next_child) {
+                None => {
+                    let (path, tree, _) = self.stack.pop()?;
+                    return Some((path, &tree.item));
+                }
+                Some(child) => {
+                    let mut child_path = path.clone();
+                    child_path.push_back(*next_child);
+                    *next_child += 1;
+                    self.stack.push((child_path, child, 0));
+                }
+            }
         }
     }
 }
@@ -182,6 +468,54 @@ mod tests {
         assert_eq!(res, actual);
     }
 
+    #[test]
+    fn test_path_parent_and_depth() {
+        let path: Path = vec![0, 2, 1].into();
+
+        assert_eq!(path.depth(), 3);
+        assert_eq!(path.parent(), Some(vec![0, 2].into()));
+        assert_eq!(Path::new().depth(), 0);
+        assert_eq!(Path::new().parent(), None);
+    }
+
+    #[test]
+    fn test_path_is_ancestor_of() {
+        let path: Path = vec![0, 2].into();
+
+        assert!(Path::new().is_ancestor_of(&path));
+        assert!(path.is_ancestor_of(&vec![0, 2, 1].into()));
+        assert!(!path.is_ancestor_of(&path));
+        assert!(!path.is_ancestor_of(&vec![0, 3, 1].into()));
+        assert!(!path.is_ancestor_of(&vec![0].into()));
+    }
+
+    #[test]
+    fn test_path_ord_is_pre_order() {
+        let tree = Tree::new(
+            "root",
+            vec![
+                Tree::new("0", vec![Tree::singleton("0-0")]),
+                Tree::singleton("1"),
+            ],
+        );
+
+        let paths: Vec<Path> =
+            tree.iter_pre_order().map(|(path, _)| path).collect();
+        let mut sorted_paths = paths.clone();
+        sorted_paths.sort();
+
+        assert_eq!(paths, sorted_paths);
+    }
+
+    #[test]
+    fn test_path_from_str() {
+        assert_eq!("3".parse(), Ok(Path::from(vec![3])));
+        assert_eq!("10.0.22".parse(), Ok(Path::from(vec![10, 0, 22])));
+        assert!("0..1".parse::<Path>().is_err());
+        assert!("0.a".parse::<Path>().is_err());
+        assert!("-1".parse::<Path>().is_err());
+    }
+
     #[test]
     fn test_lookup_no_item() {
         let tree = Tree::new(
@@ -253,6 +587,116 @@ mod tests {
         assert_eq!(tree.lookup(path2_1_1).map(String::deref), Some("2-1-1"));
     }
 
+    #[test]
+    fn test_lookup_with_ancestors() {
+        let tree = Tree::new(
+            "root",
+            vec![
+                Tree::singleton("0"),
+                Tree::new("1", vec![Tree::singleton("1-0")]),
+            ],
+        );
+
+        assert_eq!(
+            tree.lookup_with_ancestors(Path::new()),
+            Some(vec![&"root"])
+        );
+        assert_eq!(
+            tree.lookup_with_ancestors(vec![1, 0].into()),
+            Some(vec![&"root", &"1", &"1-0"])
+        );
+        assert_eq!(tree.lookup_with_ancestors(vec![0, 0].into()), None);
+    }
+
+    #[test]
+    fn test_iter_post_order() {
+        let tree = Tree::new(
+            "root",
+            vec![
+                Tree::new("0", vec![Tree::singleton("0-0")]),
+                Tree::new(
+                    "1",
+                    vec![Tree::singleton("1-0"), Tree::singleton("1-1")],
+                ),
+            ],
+        );
+
+        let res: Vec<(Path, &str)> = tree
+            .iter_post_order()
+            .map(|(path, item)| (path, *item))
+            .collect();
+
+        let actual = vec![
+            (vec![0, 0].into(), "0-0"),
+            (vec![0].into(), "0"),
+            (vec![1, 0].into(), "1-0"),
+            (vec![1, 1].into(), "1-1"),
+            (vec![1].into(), "1"),
+            (Path::new(), "root"),
+        ];
+
+        assert_eq!(res, actual);
+    }
+
+    #[test]
+    fn test_fold_depth() {
+        let tree = Tree::new(
+            "root",
+            vec![
+                Tree::singleton("0"),
+                Tree::new(
+                    "1",
+                    vec![Tree::new("1-0", vec![Tree::singleton("1-0-0")])],
+                ),
+            ],
+        );
+
+        let depth = tree.fold(&|_, children: Vec<usize>| {
+            children.into_iter().max().map_or(0, |max| max + 1)
+        });
+
+        assert_eq!(depth, 3);
+    }
+
+    #[test]
+    fn test_map() {
+        let tree = Tree::new("root", vec![Tree::singleton("0")]);
+
+        let res: Tree<usize> = tree.map(&|item| item.len());
+
+        assert_eq!(res, Tree::new(4, vec![Tree::singleton(1)]));
+    }
+
+    #[test]
+    fn test_filter_keeps_ancestors() {
+        let tree: Tree<String> = Tree::new(
+            "root".into(),
+            vec![
+                Tree::singleton("0".into()),
+                Tree::new(
+                    "1".into(),
+                    vec![
+                        Tree::singleton("match".into()),
+                        Tree::singleton("1-1".into()),
+                    ],
+                ),
+                Tree::new("match".into(), vec![Tree::singleton("2-0".into())]),
+            ],
+        );
+
+        let res = tree.filter(&|item| item == "match");
+
+        let actual = Tree::new(
+            "root".into(),
+            vec![
+                Tree::new("1".into(), vec![Tree::singleton("match".into())]),
+                Tree::singleton("match".into()),
+            ],
+        );
+
+        assert_eq!(res, Some(actual));
+    }
+
     #[test]
     fn test_tree_path_map_from_tree_all_unique() {
         let tree: Tree<String> = Tree::new(
@@ -333,4 +777,26 @@ mod tests {
 
         assert_eq!(res_tree_path_map, TreePathMap(actual_tree_path_map));
     }
+
+    #[test]
+    fn test_tree_path_map_lookup_all_and_nth() {
+        let tree = Tree::new(
+            "cat",
+            vec![
+                Tree::singleton("dog"),
+                Tree::new("cat", vec![Tree::singleton("dog")]),
+            ],
+        );
+
+        let map: TreePathMap<&str> = tree.path_map();
+
+        assert_eq!(
+            map.lookup_all(&"dog"),
+            &[vec![0].into(), vec![1, 0].into()][..]
+        );
+        assert_eq!(map.lookup_all(&"mouse"), &[][..]);
+        assert_eq!(map.lookup_nth(&"cat", 0), Some(&Path::new()));
+        assert_eq!(map.lookup_nth(&"cat", 1), Some(&vec![1].into()));
+        assert_eq!(map.lookup_nth(&"cat", 2), None);
+    }
 }
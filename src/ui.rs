@@ -1,4 +1,5 @@
 mod builder;
+mod closure_summary;
 mod css;
 mod menu;
 mod stack;
@@ -14,32 +15,90 @@ use glib::clone;
 use std::path::Path;
 use std::thread;
 
-use super::nix_query_tree::exec_nix_store::NixStoreErr;
+use super::nix_query_tree::diff::NixQueryTreeDiff;
+use super::nix_query_tree::exec_nix_store::{
+    ExecNixPathInfoRes, ExecNixStoreReferencesRes, ExecNixStoreSwitchRes,
+    ExecNixStoreValidityRes, NixStoreErr, NixStoreRes,
+};
+use super::nix_query_tree::NixQueryDrv;
+use super::tree;
 
 use prelude::*;
 
+/// Show an error from `action`, like "running `nix-store --query --tree
+/// <path>`", in the statusbar and the error dialog.
+fn render_err(state: &State, action: &str, nix_store_err: &NixStoreErr) {
+    statusbar::show_msg(state, &format!("Error {}", action));
+
+    let error_dialog: gtk::MessageDialog = state.get_error_dialog();
+    let error_msg = &format!("Error {}:\n\n{}", action, nix_store_err);
+    error_dialog.set_property_secondary_text(Some(error_msg));
+    error_dialog.run();
+    error_dialog.hide();
+}
+
+/// Show an error from running `nix-store --query <query_flag> <nix_store_path>`.
 fn render_nix_store_err(
     state: &State,
+    query_flag: &str,
     nix_store_path: &Path,
     nix_store_err: &NixStoreErr,
 ) {
-    statusbar::show_msg(
+    render_err(
         state,
         &format!(
-            "Error running `nix-store --query --tree {}`",
+            "running `nix-store --query {} {}`",
+            query_flag,
             nix_store_path.to_string_lossy()
         ),
+        nix_store_err,
     );
+}
 
-    let error_dialog: gtk::MessageDialog = state.get_error_dialog();
-    let error_msg = &format!(
-        "Error running `nix-store --query --tree {}`:\n\n{}",
-        nix_store_path.to_string_lossy(),
-        nix_store_err
-    );
-    error_dialog.set_property_secondary_text(Some(error_msg));
-    error_dialog.run();
-    error_dialog.hide();
+/// Show an error from loading the whole tree for `nix_store_path`.  When the
+/// tree is loaded from files, the error says which files couldn't be read
+/// or parsed, instead of naming a `nix-store` command that was never run.
+fn render_load_err(
+    state: &State,
+    nix_store_path: &Path,
+    nix_store_err: &NixStoreErr,
+) {
+    let option_file_action = if let Some(why_depends_file) =
+        state.read_why_depends_file().as_ref()
+    {
+        Some(format!(
+            "reading and parsing the `nix why-depends` output in {}",
+            why_depends_file.display()
+        ))
+    } else if let Some(path_info_file) = state.read_path_info_file().as_ref() {
+        Some(format!(
+            "reading and parsing the `nix path-info` output in {}",
+            path_info_file.display()
+        ))
+    } else {
+        state
+            .read_references_files()
+            .as_ref()
+            .map(|references_files| {
+                format!(
+                    "reading and parsing the listings for {} in {} and {}",
+                    nix_store_path.display(),
+                    references_files.requisites.display(),
+                    references_files.references.display()
+                )
+            })
+    };
+    match option_file_action {
+        Some(file_action) => render_err(state, &file_action, nix_store_err),
+        None => {
+            render_nix_store_err(
+                state,
+                "--tree",
+                nix_store_path,
+                nix_store_err,
+            );
+        }
+    }
 }
 
 fn search_for(state: &State, nix_store_path: &Path) {
@@ -47,6 +106,10 @@ fn search_for(state: &State, nix_store_path: &Path) {
     // nix-store --query --tree /nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
 
     disable(state);
+    // A search replaces any tree loaded from `nix why-depends` or
+    // `nix path-info` output.
+    state.write_why_depends_file(None);
+    state.write_path_info_file(None);
 
     statusbar::show_msg(
         state,
@@ -54,9 +117,38 @@ fn search_for(state: &State, nix_store_path: &Path) {
     );
 
     let nix_store_path_buf = nix_store_path.to_path_buf();
+    let store_dir = state.read_store_dir().clone();
+    let lazy = state.read_lazy();
+    let option_references_files = state.read_references_files().clone();
+    thread::spawn(clone!(@strong state.sender as sender => move || {
+        let exec_nix_store_res = if let Some(references_files) = option_references_files {
+            super::nix_query_tree::exec_nix_store::run_from_files(&references_files, &nix_store_path_buf)
+        } else if lazy {
+            super::nix_query_tree::exec_nix_store::run_lazy(&store_dir, &nix_store_path_buf)
+        } else {
+            super::nix_query_tree::exec_nix_store::run(&store_dir, &nix_store_path_buf)
+        };
+
+        sender
+            .send(Message::Display(exec_nix_store_res))
+            .expect("sender is already closed.  This should never happen");
+    }));
+}
+
+/// Load the output of `nix why-depends` from `why_depends_file`.
+fn load_why_depends(state: &State, why_depends_file: &Path) {
+    disable(state);
+    state.write_why_depends_file(Some(why_depends_file.to_path_buf()));
+
+    statusbar::show_msg(
+        state,
+        &format!("Loading {}...", why_depends_file.display()),
+    );
+
+    let why_depends_file_buf = why_depends_file.to_path_buf();
     thread::spawn(clone!(@strong state.sender as sender => move || {
         let exec_nix_store_res =
-            super::nix_query_tree::exec_nix_store::run(&nix_store_path_buf);
+            super::nix_query_tree::exec_nix_store::run_from_why_depends(&why_depends_file_buf);
 
         sender
             .send(Message::Display(exec_nix_store_res))
@@ -64,12 +156,433 @@ fn search_for(state: &State, nix_store_path: &Path) {
     }));
 }
 
+/// Load the tree from the output of `nix path-info --json --recursive` in
+/// `path_info_file`.
+fn load_path_info_file(state: &State, path_info_file: &Path) {
+    disable(state);
+    state.write_path_info_file(Some(path_info_file.to_path_buf()));
+
+    statusbar::show_msg(
+        state,
+        &format!("Loading {}...", path_info_file.display()),
+    );
+
+    let path_info_file_buf = path_info_file.to_path_buf();
+    thread::spawn(clone!(@strong state.sender as sender => move || {
+        let exec_nix_store_res =
+            super::nix_query_tree::exec_nix_store::run_from_path_info(&path_info_file_buf);
+
+        sender
+            .send(Message::Display(exec_nix_store_res))
+            .expect("sender is already closed.  This should never happen");
+    }));
+}
+
+/// Whether the tree is loaded from files instead of from the local nix
+/// store.  The paths in such a tree may not be in the local store at all.
+fn is_from_files(state: &State) -> bool {
+    state.read_references_files().is_some()
+        || state.read_why_depends_file().is_some()
+        || state.read_path_info_file().is_some()
+}
+
+/// Show the build-time derivation graph of `nix_query_drv`, by finding its
+/// deriver with `nix-store --query --deriver` and searching for that.
+fn show_build_dependencies(state: &State, nix_query_drv: &NixQueryDrv) {
+    disable(state);
+
+    statusbar::show_msg(
+        state,
+        &format!("Finding the deriver of {}...", nix_query_drv),
+    );
+
+    let nix_query_drv = nix_query_drv.clone();
+    let store_dir = state.read_store_dir().clone();
+    thread::spawn(clone!(@strong state.sender as sender => move || {
+        let exec_nix_store_switch_res =
+            super::nix_query_tree::exec_nix_store::run_deriver(&store_dir, &nix_query_drv);
+
+        sender
+            .send(Message::Switch(exec_nix_store_switch_res))
+            .expect("sender is already closed.  This should never happen");
+    }));
+}
+
+/// Show the runtime closure of the `.drv` file `nix_query_drv`, by finding
+/// its outputs with `nix-store --query --outputs` and searching for one of
+/// them.
+fn show_runtime_closure(state: &State, nix_query_drv: &NixQueryDrv) {
+    disable(state);
+
+    statusbar::show_msg(
+        state,
+        &format!("Finding the outputs of {}...", nix_query_drv),
+    );
+
+    let nix_query_drv = nix_query_drv.clone();
+    let store_dir = state.read_store_dir().clone();
+    thread::spawn(clone!(@strong state.sender as sender => move || {
+        let exec_nix_store_switch_res =
+            super::nix_query_tree::exec_nix_store::run_outputs(&store_dir, &nix_query_drv);
+
+        sender
+            .send(Message::Switch(exec_nix_store_switch_res))
+            .expect("sender is already closed.  This should never happen");
+    }));
+}
+
+fn switch_to(state: &State, exec_nix_store_switch_res: ExecNixStoreSwitchRes) {
+    match exec_nix_store_switch_res.res {
+        Err(nix_store_err) => {
+            enable(state);
+            render_nix_store_err(
+                state,
+                exec_nix_store_switch_res.query_flag,
+                &exec_nix_store_switch_res.nix_query_drv,
+                &nix_store_err,
+            );
+        }
+        Ok(nix_query_drv) => search_for(state, &nix_query_drv),
+    }
+}
+
+/// The root of the tree that is currently displayed.
+fn read_root(state: &State) -> Option<NixQueryDrv> {
+    state
+        .read_nix_store_res()
+        .as_ref()
+        .map(|nix_store_res| nix_store_res.tree.root().clone())
+}
+
+/// Show the root in the window title, marking `.drv` roots, since their tree
+/// is the build-time derivation graph instead of a runtime closure.
+fn show_root_in_title(state: &State) {
+    let title = match read_root(state) {
+        None => String::from("nix-query-tree-viewer"),
+        Some(root) if root.is_drv() => format!(
+            "{} [build dependencies] - nix-query-tree-viewer",
+            root.drv_name()
+        ),
+        Some(root) => format!("{} - nix-query-tree-viewer", root.drv_name()),
+    };
+    state.get_app_win().set_title(&title);
+}
+
+/// Run `nix-store --query --tree` for an older path, and diff it against the
+/// currently displayed tree.
+fn compare_with(state: &State, old_nix_store_path: &Path) {
+    disable(state);
+
+    statusbar::show_msg(
+        state,
+        &format!("Comparing with {}...", old_nix_store_path.display()),
+    );
+
+    let old_nix_store_path_buf = old_nix_store_path.to_path_buf();
+    let store_dir = state.read_store_dir().clone();
+    thread::spawn(clone!(@strong state.sender as sender => move || {
+        let exec_nix_store_res =
+            super::nix_query_tree::exec_nix_store::run(&store_dir, &old_nix_store_path_buf);
+
+        sender
+            .send(Message::DisplayDiff(exec_nix_store_res))
+            .expect("sender is already closed.  This should never happen");
+    }));
+}
+
+/// Run `nix-store --query --references` for the path at `path` in the tree,
+/// so that its references can be shown when it is expanded.
+fn load_references(
+    state: &State,
+    path: tree::Path,
+    nix_query_drv: NixQueryDrv,
+) {
+    statusbar::show_msg(
+        state,
+        &format!("Loading references of {}...", nix_query_drv),
+    );
+
+    let store_dir = state.read_store_dir().clone();
+    thread::spawn(clone!(@strong state.sender as sender => move || {
+        let exec_nix_store_references_res =
+            super::nix_query_tree::exec_nix_store::run_references(&store_dir, &nix_query_drv);
+
+        sender
+            .send(Message::DisplayReferences(path, exec_nix_store_references_res))
+            .expect("sender is already closed.  This should never happen");
+    }));
+}
+
+fn display_references(
+    state: &State,
+    path: &tree::Path,
+    exec_nix_store_references_res: ExecNixStoreReferencesRes,
+) {
+    let nix_query_drv = exec_nix_store_references_res.nix_query_drv;
+    match exec_nix_store_references_res.res {
+        Err(nix_store_err) => {
+            render_nix_store_err(
+                state,
+                "--references",
+                &nix_query_drv,
+                &nix_store_err,
+            );
+        }
+        Ok(nix_store_references) => {
+            let had_retained_sizes = match &*state.read_nix_store_res() {
+                Some(nix_store_res) => !nix_store_res.retained_sizes.is_empty(),
+                None => false,
+            };
+            let option_recursed_paths =
+                state.modify_nix_store_res(|nix_store_res| {
+                    nix_store_res.insert_references(
+                        path,
+                        &nix_query_drv,
+                        &nix_store_references,
+                    )
+                });
+            if let Some(recursed_paths) = option_recursed_paths {
+                stack::insert_references(state, path, &recursed_paths);
+                // Loading more references can change the retained size of
+                // any path.  They are only recomputed right away if the tree
+                // is sorted by them, and are cleared otherwise.
+                if *state.read_sort_order() == SortOrder::RetainedSize {
+                    stack::update_dominators(state);
+                } else if had_retained_sizes {
+                    stack::update_path_infos(state);
+                }
+                closure_summary::show_in_statusbar(state);
+                if let Some(root) = read_root(state) {
+                    load_path_info_of(
+                        state,
+                        root.clone(),
+                        nix_store_references.references.clone(),
+                    );
+                    load_validity(state, root, nix_store_references.references);
+                }
+            }
+        }
+    }
+}
+
+/// Run `nix path-info` for the closure of `root`, so that the metadata
+/// columns can be filled in.
+fn load_path_info(state: &State, root: NixQueryDrv) {
+    let store_dir = state.read_store_dir().clone();
+    thread::spawn(clone!(@strong state.sender as sender => move || {
+        let exec_nix_path_info_res =
+            super::nix_query_tree::exec_nix_store::run_path_info(&store_dir, &root);
+
+        sender
+            .send(Message::DisplayPathInfo(exec_nix_path_info_res))
+            .expect("sender is already closed.  This should never happen");
+    }));
+}
+
+/// Run `nix path-info` for just `nix_query_drvs`, all from the tree rooted at
+/// `root`.  In lazy mode this is run for each batch of paths as it is
+/// loaded, instead of for the whole closure.
+fn load_path_info_of(
+    state: &State,
+    root: NixQueryDrv,
+    nix_query_drvs: Vec<NixQueryDrv>,
+) {
+    let store_dir = state.read_store_dir().clone();
+    thread::spawn(clone!(@strong state.sender as sender => move || {
+        let exec_nix_path_info_res =
+            super::nix_query_tree::exec_nix_store::run_path_info_of(&store_dir, &root, &nix_query_drvs);
+
+        sender
+            .send(Message::DisplayPathInfo(exec_nix_path_info_res))
+            .expect("sender is already closed.  This should never happen");
+    }));
+}
+
+/// Show the metadata from `nix path-info`.
+///
+/// The metadata is only extra information, so if `nix path-info` fails
+/// (for instance because it is too old to support `--json`), the error is
+/// just shown in the statusbar.
+fn display_path_info(
+    state: &State,
+    exec_nix_path_info_res: ExecNixPathInfoRes,
+) {
+    // The user may have searched for something else in the meantime.
+    if read_root(state).as_ref() != Some(&exec_nix_path_info_res.nix_query_drv)
+    {
+        return;
+    }
+
+    match exec_nix_path_info_res.res {
+        Err(nix_store_err) => {
+            statusbar::show_msg(
+                state,
+                &format!(
+                    "Error running `nix path-info` for {}: {}",
+                    exec_nix_path_info_res.nix_query_drv, nix_store_err
+                ),
+            );
+        }
+        Ok(path_infos) => {
+            state.modify_nix_store_res(|nix_store_res| {
+                nix_store_res.insert_path_infos(path_infos);
+                Some(())
+            });
+            stack::update_path_infos(state);
+        }
+    }
+}
+
+/// Run `nix-store --check-validity` for `nix_query_drvs`, so that paths
+/// missing from the local store can be marked in the tree.
+fn load_validity(
+    state: &State,
+    root: NixQueryDrv,
+    nix_query_drvs: Vec<NixQueryDrv>,
+) {
+    let store_dir = state.read_store_dir().clone();
+    thread::spawn(clone!(@strong state.sender as sender => move || {
+        let exec_nix_store_validity_res =
+            super::nix_query_tree::exec_nix_store::run_check_validity(&store_dir, &root, &nix_query_drvs);
+
+        sender
+            .send(Message::DisplayValidity(exec_nix_store_validity_res))
+            .expect("sender is already closed.  This should never happen");
+    }));
+}
+
+/// The root of the tree that is currently shown, along with every path that
+/// has been loaded into it.
+fn read_root_and_loaded_drvs(
+    state: &State,
+) -> Option<(NixQueryDrv, Vec<NixQueryDrv>)> {
+    state.read_nix_store_res().as_ref().map(|nix_store_res| {
+        let nix_query_drvs: Vec<NixQueryDrv> = nix_store_res
+            .graph
+            .iter()
+            .map(|(_, nix_query_drv)| nix_query_drv.clone())
+            .collect();
+        (nix_store_res.tree.root().clone(), nix_query_drvs)
+    })
+}
+
+/// Check the validity of every path in the tree that is currently shown.
+fn load_validity_of_all(state: &State) {
+    if let Some((root, nix_query_drvs)) = read_root_and_loaded_drvs(state) {
+        load_validity(state, root, nix_query_drvs);
+    }
+}
+
+/// Mark the paths that are missing from the local store.
+///
+/// Like the metadata from `nix path-info`, this is only extra information,
+/// so errors are just shown in the statusbar.
+fn display_validity(
+    state: &State,
+    exec_nix_store_validity_res: ExecNixStoreValidityRes,
+) {
+    // The user may have searched for something else in the meantime.
+    if read_root(state).as_ref()
+        != Some(&exec_nix_store_validity_res.nix_query_drv)
+    {
+        return;
+    }
+
+    match exec_nix_store_validity_res.res {
+        Err(nix_store_err) => {
+            statusbar::show_msg(
+                state,
+                &format!(
+                    "Error running `nix-store --check-validity` for {}: {}",
+                    exec_nix_store_validity_res.nix_query_drv, nix_store_err
+                ),
+            );
+        }
+        Ok(invalid_paths) => {
+            if !invalid_paths.is_empty() {
+                state.modify_nix_store_res(|nix_store_res| {
+                    nix_store_res.insert_invalid_paths(invalid_paths);
+                    Some(())
+                });
+                stack::update_validity(state);
+                closure_summary::show_in_statusbar(state);
+            }
+        }
+    }
+}
+
+fn display_diff(state: &State, old_nix_store_res: &NixStoreRes) {
+    let option_diff: Option<NixQueryTreeDiff> = state
+        .read_nix_store_res()
+        .as_ref()
+        .map(|new_nix_store_res| {
+            NixQueryTreeDiff::new(
+                &old_nix_store_res.tree,
+                &new_nix_store_res.tree,
+            )
+        });
+
+    if let Some(diff) = option_diff {
+        statusbar::show_msg(
+            state,
+            &format!(
+                "{} added, {} removed, {} version changed, {} rebuilt",
+                diff.added.len(),
+                diff.removed.len(),
+                diff.version_changes.len(),
+                diff.rebuilds.len()
+            ),
+        );
+        state.write_diff(Some(diff));
+        stack::redisplay_diff(state);
+        stack::show_diff_page(state);
+    }
+}
+
+/// Go to the node at `path` in the tree view, showing a message in the
+/// statusbar if there is no such node.
+fn goto_path(state: &State, path: &tree::Path) {
+    if !stack::goto(state, path) {
+        statusbar::show_msg(
+            state,
+            &format!("There is no node at location \"{}\"", path),
+        );
+    }
+}
+
+/// Go to a location in the tree view, given in the dotted textual form of a
+/// `tree::Path`.
+fn goto_location(state: &State, location: &str) {
+    match location.trim().parse::<tree::Path>() {
+        Err(_) => statusbar::show_msg(
+            state,
+            &format!("\"{}\" is not a valid location", location),
+        ),
+        Ok(path) => goto_path(state, &path),
+    }
+}
+
 fn set_sort_order(state: &State, new_sort_order: SortOrder) {
     state.write_sort_order(new_sort_order);
+    if new_sort_order == SortOrder::RetainedSize {
+        stack::update_dominators(state);
+    }
+
+    stack::change_sort_order(state);
+}
+
+pub fn set_group_outputs(state: &State, new_group_outputs: bool) {
+    state.write_group_outputs(new_group_outputs);
 
     stack::change_sort_order(state);
 }
 
+pub fn set_missing_only(state: &State, new_missing_only: bool) {
+    state.write_missing_only(new_missing_only);
+
+    stack::change_missing_only(state);
+}
+
 pub fn set_view_style(state: &State, new_view_style: ViewStyle) {
     state.write_view_style(new_view_style);
 
@@ -79,6 +592,8 @@ pub fn set_view_style(state: &State, new_view_style: ViewStyle) {
 fn redisplay_data(state: &State) {
     statusbar::clear(state);
     stack::redisplay_data(state);
+    closure_summary::show_in_statusbar(state);
+    show_root_in_title(state);
 }
 
 fn disable(state: &State) {
@@ -92,12 +607,18 @@ fn enable(state: &State) {
 }
 
 fn handle_msg_recv(state: &State, msg: Message) {
-    enable(state);
-
     match msg {
         Message::Display(exec_nix_store_res) => match exec_nix_store_res.res {
             Err(nix_store_err) => {
-                render_nix_store_err(
+                enable(state);
+                // Only a failure to load the whole tree replaces the raw
+                // view.  Errors from lazily expanding `--references` are
+                // shown in the statusbar and the error dialog alone.
+                if let NixStoreErr::ParseErr { raw, parse_err } = &nix_store_err
+                {
+                    stack::show_parse_err(state, raw, parse_err);
+                }
+                render_load_err(
                     state,
                     &exec_nix_store_res.nix_store_path,
                     &nix_store_err,
@@ -105,9 +626,58 @@ fn handle_msg_recv(state: &State, msg: Message) {
             }
             Ok(nix_store_res) => {
                 state.write_nix_store_res(nix_store_res);
+                enable(state);
+                state.write_diff(None);
                 redisplay_data(state);
+                if let Some(path) = state.take_goto_after_display() {
+                    goto_path(state, &path);
+                }
+                // A tree built from reference listings already has the
+                // metadata from the listings.
+                if !is_from_files(state) {
+                    // In lazy mode, only the paths loaded so far are queried,
+                    // and the rest as they are expanded.
+                    if state.read_lazy() {
+                        if let Some((root, nix_query_drvs)) =
+                            read_root_and_loaded_drvs(state)
+                        {
+                            load_path_info_of(state, root, nix_query_drvs);
+                        }
+                    } else if let Some(root) = read_root(state) {
+                        load_path_info(state, root);
+                    }
+                    load_validity_of_all(state);
+                }
             }
         },
+        Message::DisplayDiff(exec_nix_store_res) => {
+            enable(state);
+            match exec_nix_store_res.res {
+                Err(nix_store_err) => {
+                    render_nix_store_err(
+                        state,
+                        "--tree",
+                        &exec_nix_store_res.nix_store_path,
+                        &nix_store_err,
+                    );
+                }
+                Ok(old_nix_store_res) => {
+                    display_diff(state, &old_nix_store_res);
+                }
+            }
+        }
+        Message::DisplayReferences(path, exec_nix_store_references_res) => {
+            display_references(state, &path, exec_nix_store_references_res);
+        }
+        Message::Switch(exec_nix_store_switch_res) => {
+            switch_to(state, exec_nix_store_switch_res);
+        }
+        Message::DisplayPathInfo(exec_nix_path_info_res) => {
+            display_path_info(state, exec_nix_path_info_res);
+        }
+        Message::DisplayValidity(exec_nix_store_validity_res) => {
+            display_validity(state, exec_nix_store_validity_res);
+        }
     }
 }
 
@@ -122,6 +692,7 @@ fn app_activate(app: gtk::Application) {
 
     css::setup(window.upcast_ref());
     menu::setup(&state);
+    closure_summary::setup(&state);
     toolbar::setup(&state);
     stack::setup(&state);
 
@@ -137,7 +708,23 @@ fn app_activate(app: gtk::Application) {
 
     // Do the initial search and display the results.
     let opts = crate::opts::Opts::parse_from_args();
-    search_for(&state, &opts.nix_store_path);
+    state.write_references_files(opts.references_files());
+    state.write_goto_after_display(opts.goto);
+    state.write_lazy(opts.lazy);
+    state.write_store_dir(opts.store_dir);
+    match (opts.why_depends, opts.path_info, opts.nix_store_path) {
+        (Some(why_depends_file), _, _) => {
+            load_why_depends(&state, &why_depends_file);
+        }
+        (None, Some(path_info_file), _) => {
+            load_path_info_file(&state, &path_info_file);
+        }
+        (None, None, Some(nix_store_path)) => {
+            search_for(&state, &nix_store_path);
+        }
+        // structopt makes sure that one of these is given.
+        (None, None, None) => {}
+    }
 }
 
 pub fn run() {
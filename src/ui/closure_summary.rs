@@ -0,0 +1,91 @@
+use glib::clone;
+
+use super::super::nix_query_tree::stats::NixQueryTreeStats;
+use super::super::ui;
+use super::prelude::*;
+
+/// The number of paths to list in the "Most referenced" section of the
+/// closure summary.
+const MOST_REFERENCED_LEN: usize = 20;
+
+fn render_stats(
+    view_style: ui::ViewStyle,
+    stats: &NixQueryTreeStats,
+) -> String {
+    let mut lines: Vec<String> = vec![
+        format!("Total entries: {}", stats.node_count),
+        format!("Unique paths: {}", stats.unique_count),
+        format!(
+            "[...] entries: {} ({:.1}%)",
+            stats.recurse_count,
+            stats.recurse_ratio() * 100.0
+        ),
+        format!("Maximum depth: {}", stats.max_depth),
+        format!("Average depth: {:.2}", stats.avg_depth),
+        String::new(),
+        String::from("Fan-out (direct references: paths):"),
+    ];
+
+    for (references, count) in &stats.fan_out {
+        lines.push(format!("    {}: {}", references, count));
+    }
+
+    lines.push(String::new());
+    lines.push(String::from("Most referenced (referrers: path):"));
+
+    for (nix_query_drv, count) in stats.most_referenced(MOST_REFERENCED_LEN) {
+        lines.push(format!(
+            "    {}: {}",
+            count,
+            view_style.render(nix_query_drv)
+        ));
+    }
+
+    lines.join("\n")
+}
+
+/// Show the one-line summary of the closure in the statusbar.
+///
+/// When the root is a `.drv` file, this is marked as a build-time
+/// derivation graph.  The number of paths missing from the local store is
+/// added once it is known.
+pub fn show_in_statusbar(state: &ui::State) {
+    if let Some(nix_store_res) = &*state.read_nix_store_res() {
+        let closure_stats = nix_store_res.stats();
+        let mut msg = if nix_store_res.tree.root().is_drv() {
+            format!("Build-time derivation graph: {}", closure_stats)
+        } else {
+            closure_stats.to_string()
+        };
+        if !nix_store_res.invalid_paths.is_empty() {
+            msg = format!(
+                "{}, {} missing from the local store",
+                msg,
+                nix_store_res.invalid_paths.len()
+            );
+        }
+        ui::statusbar::show_msg(state, &msg);
+    }
+}
+
+fn show_dialog(state: &ui::State) {
+    if let Some(nix_store_res) = &*state.read_nix_store_res() {
+        let view_style = *state.read_view_style();
+        let closure_stats = nix_store_res.stats();
+        state
+            .get_closure_summary_label()
+            .set_text(&render_stats(view_style, &closure_stats));
+    }
+
+    let dialog = state.get_closure_summary_dialog();
+    dialog.run();
+    dialog.hide();
+}
+
+pub fn setup(state: &ui::State) {
+    state.get_closure_summary_menu_item().connect_activate(
+        clone!(@strong state => move |_| {
+            show_dialog(&state);
+        }),
+    );
+}
@@ -1,21 +1,36 @@
+mod diff;
+mod dominators;
+mod duplicates;
 mod raw;
 mod tree;
 
+use super::super::nix_query_tree::parse_err::ParseErr;
 use super::super::ui;
+use super::prelude::*;
+use crate::tree::Path;
 
 pub fn setup(state: &ui::State) {
     tree::setup(&state);
     raw::setup(&state);
+    diff::setup(&state);
+    duplicates::setup(&state);
+    dominators::setup(&state);
 }
 
 pub fn disable(state: &ui::State) {
     tree::disable(state);
     raw::disable(state);
+    diff::disable(state);
+    duplicates::disable(state);
+    dominators::disable(state);
 }
 
 pub fn enable(state: &ui::State) {
     tree::enable(state);
     raw::enable(state);
+    diff::enable(state);
+    duplicates::enable(state);
+    dominators::enable(state);
 }
 
 pub fn change_sort_order(state: &ui::State) {
@@ -24,9 +39,86 @@ pub fn change_sort_order(state: &ui::State) {
 
 pub fn change_view_style(state: &ui::State) {
     tree::change_view_style(state);
+    diff::change_view_style(state);
+    duplicates::change_view_style(state);
+    dominators::change_view_style(state);
 }
 
 pub fn redisplay_data(state: &ui::State) {
     tree::redisplay_data(&state);
     raw::redisplay_data(&state);
+    diff::redisplay_data(&state);
+    duplicates::redisplay_data(&state);
+    dominators::redisplay_data(&state);
+}
+
+/// Show the references that have just been loaded for the node at `path`.
+/// See `tree::insert_references`.
+pub fn insert_references(
+    state: &ui::State,
+    path: &Path,
+    recursed_paths: &[Path],
+) {
+    tree::insert_references(state, path, recursed_paths);
+    raw::redisplay_data(state);
+    duplicates::redisplay_data(state);
+    dominators::redisplay_data(state);
+}
+
+pub fn update_path_infos(state: &ui::State) {
+    tree::update_path_infos(state);
+    dominators::redisplay_data(state);
+}
+
+/// Compute the dominators and retained sizes if they are out of date, and
+/// show the retained sizes in the tree view.  This walks the whole closure,
+/// so it is only done for the views that need it.
+pub fn update_dominators(state: &ui::State) {
+    let updated = state
+        .modify_nix_store_res(|nix_store_res| {
+            if nix_store_res.dominator_tree.is_some() {
+                None
+            } else {
+                nix_store_res.update_dominators();
+                Some(())
+            }
+        })
+        .is_some();
+    if updated {
+        tree::update_path_infos(state);
+    }
+}
+
+pub fn update_validity(state: &ui::State) {
+    tree::update_validity(state);
+}
+
+pub fn change_missing_only(state: &ui::State) {
+    tree::change_missing_only(state);
+}
+
+pub fn redisplay_diff(state: &ui::State) {
+    diff::redisplay_data(state);
+}
+
+/// Show the raw text that couldn't be parsed on the raw page, with the
+/// failing line highlighted.  See `raw::show_parse_err`.
+pub fn show_parse_err(state: &ui::State, raw: &str, parse_err: &ParseErr) {
+    raw::show_parse_err(state, raw, parse_err);
+    state.get_stack().set_visible_child_name("page1");
+}
+
+pub fn show_diff_page(state: &ui::State) {
+    state.get_stack().set_visible_child_name("page2");
+}
+
+/// Show the tree view and go to `path` in it.
+///
+/// Returns `false` if there is no node at `path`.
+pub fn goto(state: &ui::State, path: &Path) -> bool {
+    let found = tree::goto(state, path);
+    if found {
+        state.get_stack().set_visible_child_name("page0");
+    }
+    found
 }
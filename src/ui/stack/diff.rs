@@ -0,0 +1,138 @@
+use glib::clone;
+
+use super::super::super::ui;
+use super::super::prelude::*;
+use crate::nix_query_tree::diff::{DiffEntry, DiffKind};
+use crate::tree::Tree;
+
+/// These correspond to the columns in the `diffTreeStore`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(i32)]
+enum Column {
+    Item = 0,
+    Change,
+    Foreground,
+    ForegroundSet,
+}
+
+impl Column {
+    const INDICIES: [u32; 4] = [
+        Column::Item as u32,
+        Column::Change as u32,
+        Column::Foreground as u32,
+        Column::ForegroundSet as u32,
+    ];
+}
+
+fn change_str(kind: DiffKind) -> &'static str {
+    match kind {
+        DiffKind::Unchanged => "",
+        DiffKind::Added => "added",
+        DiffKind::Removed => "removed",
+        DiffKind::VersionChanged => "version changed",
+        DiffKind::Rebuilt => "rebuilt",
+    }
+}
+
+fn foreground(kind: DiffKind) -> Option<&'static str> {
+    match kind {
+        DiffKind::Unchanged => None,
+        DiffKind::Added => Some("green"),
+        DiffKind::Removed => Some("red"),
+        DiffKind::VersionChanged => Some("orange"),
+        DiffKind::Rebuilt => Some("blue"),
+    }
+}
+
+fn item_str(view_style: ui::ViewStyle, diff_entry: &DiffEntry) -> String {
+    let item = view_style.render(diff_entry.drv());
+    match (diff_entry.kind, &diff_entry.old) {
+        (DiffKind::VersionChanged, Some(old_drv)) => {
+            format!("{} (was {})", item, old_drv.package_name_and_version().1)
+        }
+        _ => item,
+    }
+}
+
+fn insert_child(
+    tree_store: &gtk::TreeStore,
+    parent: Option<&gtk::TreeIter>,
+    view_style: ui::ViewStyle,
+    child: &Tree<DiffEntry>,
+) {
+    let Tree { item, children }: &Tree<DiffEntry> = child;
+    let item_str = item_str(view_style, item);
+    let change_str = change_str(item.kind);
+    let option_foreground = foreground(item.kind);
+    let foreground_str = option_foreground.unwrap_or("");
+    let foreground_set = option_foreground.is_some();
+    let this_iter: gtk::TreeIter = tree_store.insert_with_values(
+        parent,
+        None,
+        &Column::INDICIES,
+        &[&item_str, &change_str, &foreground_str, &foreground_set],
+    );
+    for grandchild in children {
+        insert_child(tree_store, Some(&this_iter), view_style, grandchild);
+    }
+}
+
+fn clear(state: &ui::State) {
+    state.get_diff_tree_store().clear();
+}
+
+fn render_diff(state: &ui::State) {
+    if let Some(diff) = &*state.read_diff() {
+        let tree_store = state.get_diff_tree_store();
+        let view_style = *state.read_view_style();
+        insert_child(&tree_store, None, view_style, &diff.merged);
+    }
+}
+
+fn handle_compare(state: &ui::State) {
+    let search_text = state.get_diff_search_entry().get_buffer().get_text();
+
+    ui::compare_with(state, std::path::Path::new(&search_text));
+}
+
+pub fn setup(state: &ui::State) {
+    state.get_diff_search_entry().connect_activate(
+        clone!(@strong state => move |_| {
+            handle_compare(&state);
+        }),
+    );
+
+    state
+        .get_diff_button()
+        .connect_clicked(clone!(@strong state => move |_| {
+            handle_compare(&state);
+        }));
+}
+
+pub fn disable(state: &ui::State) {
+    state.get_diff_search_entry().set_sensitive(false);
+    state.get_diff_button().set_sensitive(false);
+    state.get_diff_tree_view().set_sensitive(false);
+}
+
+pub fn enable(state: &ui::State) {
+    state.get_diff_search_entry().set_sensitive(true);
+    state.get_diff_button().set_sensitive(true);
+    state.get_diff_tree_view().set_sensitive(true);
+}
+
+pub fn change_view_style(state: &ui::State) {
+    redisplay_data(state);
+}
+
+pub fn redisplay_data(state: &ui::State) {
+    clear(state);
+    enable(state);
+
+    render_diff(state);
+
+    // expand the first row of the tree view
+    state
+        .get_diff_tree_view()
+        .expand_row(&gtk::TreePath::new_first(), false);
+}
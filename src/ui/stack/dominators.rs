@@ -0,0 +1,178 @@
+use glib::clone;
+
+use super::super::super::ui;
+use super::super::prelude::*;
+use crate::nix_query_tree::dominators::DominatorTree;
+use crate::nix_query_tree::exec_nix_store::NixStoreRes;
+use crate::nix_query_tree::graph::NodeIndex;
+use crate::nix_query_tree::path_info::format_size;
+use crate::nix_query_tree::NixQueryDrv;
+use crate::tree;
+
+/// These correspond to the columns in the `dominatorsTreeStore`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(i32)]
+enum Column {
+    Item = 0,
+    RetainedSize,
+    RetainedSizeStr,
+    FullPath,
+}
+
+impl Column {
+    const INDICIES: [u32; 4] = [
+        Column::Item as u32,
+        Column::RetainedSize as u32,
+        Column::RetainedSizeStr as u32,
+        Column::FullPath as u32,
+    ];
+}
+
+/// The nodes immediately dominated by `node`, with the largest retained size
+/// first.  Nodes keep the order of the dominator tree if their retained
+/// sizes are the same, or haven't been loaded yet.
+fn sorted_children(
+    nix_store_res: &NixStoreRes,
+    dominator_tree: &DominatorTree,
+    node: NodeIndex,
+) -> Vec<NodeIndex> {
+    let mut children: Vec<NodeIndex> = dominator_tree.children(node).to_vec();
+    let retained_size =
+        |child: NodeIndex| nix_store_res.retained_sizes.get(child).copied();
+    children.sort_by(|&child_a, &child_b| {
+        retained_size(child_b).cmp(&retained_size(child_a))
+    });
+    children
+}
+
+fn insert_node(
+    tree_store: &gtk::TreeStore,
+    view_style: ui::ViewStyle,
+    nix_store_res: &NixStoreRes,
+    dominator_tree: &DominatorTree,
+    parent: Option<&gtk::TreeIter>,
+    node: NodeIndex,
+) {
+    let drv: &NixQueryDrv = match nix_store_res.graph.node(node) {
+        Some(drv) => drv,
+        None => return,
+    };
+    let option_retained_size = nix_store_res.retained_sizes.get(node).copied();
+    let this_iter: gtk::TreeIter = tree_store.insert_with_values(
+        parent,
+        None,
+        &Column::INDICIES,
+        &[
+            &view_style.render(drv),
+            &option_retained_size.unwrap_or(0),
+            &option_retained_size.map(format_size).unwrap_or_default(),
+            &drv.to_string(),
+        ],
+    );
+    for child in sorted_children(nix_store_res, dominator_tree, node) {
+        insert_node(
+            tree_store,
+            view_style,
+            nix_store_res,
+            dominator_tree,
+            Some(&this_iter),
+            child,
+        );
+    }
+}
+
+fn clear(state: &ui::State) {
+    state.get_dominators_tree_store().clear();
+}
+
+fn render_dominators(state: &ui::State) {
+    if let Some(nix_store_res) = &*state.read_nix_store_res() {
+        if let Some(dominator_tree) = &nix_store_res.dominator_tree {
+            if !nix_store_res.graph.is_empty() {
+                let tree_store = state.get_dominators_tree_store();
+                let view_style = *state.read_view_style();
+                insert_node(
+                    &tree_store,
+                    view_style,
+                    nix_store_res,
+                    dominator_tree,
+                    None,
+                    dominator_tree.root(),
+                );
+            }
+        }
+    }
+
+    // expand the first row of the tree view
+    state
+        .get_dominators_tree_view()
+        .expand_row(&gtk::TreePath::new_first(), false);
+}
+
+/// Jump to the tree instance of the path that was clicked on.
+fn handle_row_activated(state: &ui::State, tree_path: &gtk::TreePath) {
+    let tree_store = state.get_dominators_tree_store();
+    let option_full_path: Option<String> = tree_store
+        .get_iter(tree_path)
+        .map(|iter| tree_store.get_value(&iter, Column::FullPath as i32))
+        .and_then(|value| value.get::<String>().ok().flatten());
+
+    if let Some(full_path) = option_full_path {
+        let drv = NixQueryDrv::from(&full_path);
+        let option_path: Option<tree::Path> = state
+            .read_nix_store_res()
+            .as_ref()
+            .and_then(|nix_store_res| {
+                nix_store_res.lookup_expanded(&drv).cloned()
+            });
+        if let Some(path) = option_path {
+            super::goto(state, &path);
+        }
+    }
+}
+
+/// Whether the dominators page is the one being shown.
+fn is_shown(state: &ui::State) -> bool {
+    state.get_stack().get_visible_child_name().as_deref() == Some("page4")
+}
+
+pub fn setup(state: &ui::State) {
+    state.get_dominators_tree_view().connect_row_activated(
+        clone!(@strong state => move |_, tree_path, _| {
+            handle_row_activated(&state, tree_path);
+        }),
+    );
+    state
+        .get_stack()
+        .connect_property_visible_child_name_notify(
+            clone!(@strong state => move |_| {
+                if is_shown(&state) {
+                    redisplay_data(&state);
+                }
+            }),
+        );
+}
+
+pub fn disable(state: &ui::State) {
+    state.get_dominators_tree_view().set_sensitive(false);
+}
+
+pub fn enable(state: &ui::State) {
+    state.get_dominators_tree_view().set_sensitive(true);
+}
+
+pub fn change_view_style(state: &ui::State) {
+    redisplay_data(state);
+}
+
+/// Show the dominator tree, if the dominators page is being shown.  The
+/// dominators are only computed then, since that walks the whole closure.
+pub fn redisplay_data(state: &ui::State) {
+    clear(state);
+    enable(state);
+
+    if is_shown(state) {
+        super::update_dominators(state);
+        render_dominators(state);
+    }
+}
@@ -0,0 +1,129 @@
+use glib::clone;
+
+use super::super::super::ui;
+use super::super::prelude::*;
+use crate::nix_query_tree::duplicates::DuplicateGroup;
+use crate::nix_query_tree::NixQueryDrv;
+use crate::tree;
+
+/// These correspond to the columns in the `duplicatesTreeStore`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(i32)]
+enum Column {
+    Item = 0,
+    FullPath,
+}
+
+impl Column {
+    const INDICIES: [u32; 2] = [Column::Item as u32, Column::FullPath as u32];
+}
+
+fn group_str(duplicate_group: &DuplicateGroup) -> String {
+    let package_name = match &duplicate_group.output {
+        None => duplicate_group.package_name.clone(),
+        Some(output) => {
+            format!("{} ({})", duplicate_group.package_name, output)
+        }
+    };
+    format!(
+        "{}: {} variants",
+        package_name,
+        duplicate_group.variants.len()
+    )
+}
+
+fn insert_group(
+    tree_store: &gtk::TreeStore,
+    view_style: ui::ViewStyle,
+    duplicate_group: &DuplicateGroup,
+) {
+    let group_iter: gtk::TreeIter = tree_store.insert_with_values(
+        None,
+        None,
+        &Column::INDICIES,
+        &[&group_str(duplicate_group), &""],
+    );
+    for variant in &duplicate_group.variants {
+        tree_store.insert_with_values(
+            Some(&group_iter),
+            None,
+            &Column::INDICIES,
+            &[&view_style.render(variant), &variant.to_string()],
+        );
+    }
+}
+
+fn clear(state: &ui::State) {
+    state.get_duplicates_tree_store().clear();
+}
+
+fn render_duplicates(state: &ui::State) {
+    if let Some(nix_store_res) = &*state.read_nix_store_res() {
+        let tree_store = state.get_duplicates_tree_store();
+        let view_style = *state.read_view_style();
+        for duplicate_group in nix_store_res.duplicates() {
+            insert_group(&tree_store, view_style, &duplicate_group);
+        }
+    }
+}
+
+/// Jump to the tree instance of the variant that was clicked on, or toggle a
+/// group open or closed.
+fn handle_row_activated(state: &ui::State, tree_path: &gtk::TreePath) {
+    let tree_view = state.get_duplicates_tree_view();
+    let tree_store = state.get_duplicates_tree_store();
+    let option_full_path: Option<String> = tree_store
+        .get_iter(tree_path)
+        .map(|iter| tree_store.get_value(&iter, Column::FullPath as i32))
+        .and_then(|value| value.get::<String>().ok().flatten())
+        .filter(|full_path| !full_path.is_empty());
+
+    match option_full_path {
+        None => {
+            if tree_view.row_expanded(tree_path) {
+                tree_view.collapse_row(tree_path);
+            } else {
+                tree_view.expand_row(tree_path, false);
+            }
+        }
+        Some(full_path) => {
+            let variant = NixQueryDrv::from(&full_path);
+            let option_path: Option<tree::Path> = state
+                .read_nix_store_res()
+                .as_ref()
+                .and_then(|nix_store_res| {
+                    nix_store_res.lookup_expanded(&variant).cloned()
+                });
+            if let Some(path) = option_path {
+                super::goto(state, &path);
+            }
+        }
+    }
+}
+
+pub fn setup(state: &ui::State) {
+    state.get_duplicates_tree_view().connect_row_activated(
+        clone!(@strong state => move |_, tree_path, _| {
+            handle_row_activated(&state, tree_path);
+        }),
+    );
+}
+
+pub fn disable(state: &ui::State) {
+    state.get_duplicates_tree_view().set_sensitive(false);
+}
+
+pub fn enable(state: &ui::State) {
+    state.get_duplicates_tree_view().set_sensitive(true);
+}
+
+pub fn change_view_style(state: &ui::State) {
+    redisplay_data(state);
+}
+
+pub fn redisplay_data(state: &ui::State) {
+    clear(state);
+    enable(state);
+
+    render_duplicates(state);
+}
@@ -1,3 +1,4 @@
+use super::super::super::nix_query_tree::parse_err::ParseErr;
 use super::super::super::ui;
 use super::super::prelude::*;
 
@@ -15,3 +16,21 @@ pub fn redisplay_data(state: &ui::State) {
         text_buffer.set_text(&nix_store_res.raw);
     }
 }
+
+/// Show `raw`, the text that couldn't be parsed, with the line that
+/// `parse_err` is on highlighted and scrolled to.
+pub fn show_parse_err(state: &ui::State, raw: &str, parse_err: &ParseErr) {
+    let text_buffer: gtk::TextBuffer = state.get_raw_text_buffer();
+    text_buffer.set_text(raw);
+
+    let start = text_buffer.get_iter_at_line(parse_err.line as i32 - 1);
+    let mut end = start.clone();
+    end.forward_line();
+    text_buffer.apply_tag_by_name("parse-error", &start, &end);
+    text_buffer.place_cursor(&start);
+
+    if let Some(insert_mark) = text_buffer.get_insert() {
+        let text_view: gtk::TextView = state.get_raw_text_view();
+        text_view.scroll_to_mark(&insert_mark, 0.0, true, 0.0, 0.5);
+    }
+}
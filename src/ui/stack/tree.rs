@@ -1,7 +1,10 @@
 mod columns;
+mod instances;
 mod path;
+mod referrers;
 mod signals;
 mod store;
+mod why_depends;
 
 use core::cmp::Ordering;
 use glib::clone;
@@ -9,20 +12,26 @@ use glib::translate::ToGlibPtr;
 
 use super::super::super::ui;
 use super::super::prelude::*;
+use crate::nix_query_tree::exec_nix_store::NixStoreRes;
+use crate::nix_query_tree::NixQueryEntry;
+use crate::tree;
 
 fn clear(state: &ui::State) {
     let tree_store = state.get_tree_store();
     tree_store.clear();
+    referrers::clear(state);
 }
 
 pub fn disable(state: &ui::State) {
     let tree_view: gtk::TreeView = state.get_tree_view();
     tree_view.set_sensitive(false);
+    referrers::disable(state);
 }
 
 pub fn enable(state: &ui::State) {
     let tree_view: gtk::TreeView = state.get_tree_view();
     tree_view.set_sensitive(true);
+    referrers::enable(state);
 }
 
 fn render_nix_store_res(state: &ui::State) {
@@ -32,8 +41,31 @@ fn render_nix_store_res(state: &ui::State) {
     }
 }
 
+/// Whether the row at `tree_iter` should be shown.  When only missing paths
+/// are shown, this hides every row that isn't missing and doesn't lead to a
+/// missing path.
+fn is_row_visible(
+    state: &ui::State,
+    tree_model: &gtk::TreeModel,
+    tree_iter: &gtk::TreeIter,
+) -> bool {
+    !state.read_missing_only()
+        || tree_model
+            .get_value(tree_iter, columns::Column::HasMissing as i32)
+            .get_some::<bool>()
+            .unwrap_or(false)
+}
+
 pub fn setup(state: &ui::State) {
     signals::connect(state);
+    referrers::connect(state);
+    instances::connect(state);
+
+    state.get_tree_model_filter().set_visible_func(
+        clone!(@strong state => move |tree_model, tree_iter| {
+            is_row_visible(&state, tree_model, tree_iter)
+        }),
+    );
 }
 
 /// Low-level (unsafe) function for setting the sorting function.
@@ -108,6 +140,16 @@ pub fn change_sort_order(state: &ui::State) {
     let tree_model_sort = state.get_tree_model_sort();
 
     match *state.read_sort_order() {
+        // Grouping outputs needs the sort function even when keeping the
+        // original order, since sibling outputs may not be next to each
+        // other in the output of `nix-store`.
+        ui::SortOrder::NixStoreOrigOutput if state.read_group_outputs() => {
+            set_sort_function(state);
+            tree_model_sort.set_sort_column_id(
+                gtk::SortColumn::Index(0),
+                gtk::SortType::Ascending,
+            );
+        }
         ui::SortOrder::NixStoreOrigOutput => {
             tree_model_sort.set_sort_column_id(
                 gtk::SortColumn::Default,
@@ -128,11 +170,186 @@ pub fn change_sort_order(state: &ui::State) {
                 gtk::SortType::Ascending,
             );
         }
+        ui::SortOrder::DrvNameAndVersion => {
+            set_sort_function(state);
+            tree_model_sort.set_sort_column_id(
+                gtk::SortColumn::Index(0),
+                gtk::SortType::Ascending,
+            );
+        }
+        ui::SortOrder::RetainedSize => {
+            set_sort_function(state);
+            tree_model_sort.set_sort_column_id(
+                gtk::SortColumn::Index(0),
+                gtk::SortType::Ascending,
+            );
+        }
+    }
+}
+
+/// Go to `tree_path` in the tree view.
+///
+/// Returns `false` if there is no node at `tree_path`.
+pub fn goto(state: &ui::State, tree_path: &tree::Path) -> bool {
+    let found = state
+        .read_nix_store_res()
+        .as_ref()
+        .and_then(|nix_store_res| nix_store_res.tree.lookup(tree_path.clone()))
+        .is_some();
+    if found {
+        path::goto(state, tree_path);
+    }
+    found
+}
+
+/// Replace the placeholder row under `path` with the references that have
+/// just been loaded, and turn the other instances of the same path into
+/// `[...]` entries.
+pub fn insert_references(
+    state: &ui::State,
+    path: &tree::Path,
+    recursed_paths: &[tree::Path],
+) {
+    if let Some(nix_store_res) = &*state.read_nix_store_res() {
+        let tree_store = state.get_tree_store();
+        store::insert_references(
+            &tree_store,
+            nix_store_res,
+            path,
+            recursed_paths,
+        );
+    }
+
+    // Removing the placeholder row collapses the row, so open it back up.
+    path::expand_to(state, path);
+}
+
+/// Mark the paths that have just been found to be missing from the local
+/// store.
+pub fn update_validity(state: &ui::State) {
+    if let Some(nix_store_res) = &*state.read_nix_store_res() {
+        store::update_validity(&state.get_tree_store(), nix_store_res);
+    }
+}
+
+/// Show or hide the rows that don't lead to a missing path.
+pub fn change_missing_only(state: &ui::State) {
+    state.get_tree_model_filter().refilter();
+
+    if state.read_missing_only() {
+        state.get_tree_view().expand_all();
+    }
+}
+
+/// Show the metadata from `nix path-info` that has just been loaded.
+pub fn update_path_infos(state: &ui::State) {
+    if let Some(nix_store_res) = &*state.read_nix_store_res() {
+        store::update_path_infos(&state.get_tree_store(), nix_store_res);
     }
 }
 
 pub fn change_view_style(state: &ui::State) {
     columns::change_view_style(state);
+    referrers::change_view_style(state);
+}
+
+/// Find the first sibling of the node at `path` that is another output of the
+/// same derivation.  This is `path` itself if the node is the first output
+/// of its derivation under its parent.
+fn output_group_leader(
+    nix_store_res: &NixStoreRes,
+    path: &tree::Path,
+) -> tree::Path {
+    let option_leader = path.parent().and_then(|parent_path| {
+        let drv_name = nix_store_res
+            .tree
+            .lookup(path.clone())?
+            .0
+            .drv_name_without_output();
+        let parent = nix_store_res.tree.0.lookup_tree(parent_path.clone())?;
+        let index = parent.children.iter().position(|child| {
+            child.item.0.drv_name_without_output() == drv_name
+        })?;
+        let mut leader_path = parent_path;
+        leader_path.push_back(index);
+        Some(leader_path)
+    });
+    option_leader.unwrap_or_else(|| path.clone())
+}
+
+/// Compare two nodes by `sort_order`.
+///
+/// `SortOrder::RetainedSize` puts the largest retained size first, and falls
+/// back to the drv name for paths with the same retained size or whose
+/// metadata hasn't been loaded.
+fn cmp_entries(
+    sort_order: ui::SortOrder,
+    nix_store_res: &NixStoreRes,
+    nix_query_entry_a: &NixQueryEntry,
+    nix_query_entry_b: &NixQueryEntry,
+) -> Ordering {
+    match sort_order {
+        ui::SortOrder::NixStoreOrigOutput => {
+            println!("The sort function should never be called when the sort order is NixStoreOrigOutput!!!");
+            Ordering::Equal
+        }
+        ui::SortOrder::AlphabeticalHash => {
+            nix_query_entry_a.cmp_hash(nix_query_entry_b)
+        }
+        ui::SortOrder::AlphabeticalDrvName => {
+            nix_query_entry_a.cmp_drv_name(nix_query_entry_b)
+        }
+        ui::SortOrder::DrvNameAndVersion => {
+            nix_query_entry_a.cmp_version(nix_query_entry_b)
+        }
+        ui::SortOrder::RetainedSize => nix_store_res
+            .retained_size(&nix_query_entry_b.0)
+            .cmp(&nix_store_res.retained_size(&nix_query_entry_a.0))
+            .then_with(|| nix_query_entry_a.cmp_drv_name(nix_query_entry_b)),
+    }
+}
+
+/// Compare two sibling nodes so that all the outputs of one derivation end
+/// up next to each other.
+///
+/// Groups of outputs are ordered by their first output, and the outputs
+/// within a group are ordered with the default output first.
+fn cmp_grouped_outputs(
+    sort_order: ui::SortOrder,
+    nix_store_res: &NixStoreRes,
+    path_a: &tree::Path,
+    path_b: &tree::Path,
+) -> Ordering {
+    let leader_a = output_group_leader(nix_store_res, path_a);
+    let leader_b = output_group_leader(nix_store_res, path_b);
+    let lookup_drv = |path: &tree::Path| {
+        nix_store_res
+            .tree
+            .lookup(path.clone())
+            .map(|nix_query_entry| &nix_query_entry.0)
+    };
+
+    if leader_a == leader_b {
+        match (lookup_drv(path_a), lookup_drv(path_b)) {
+            (Some(drv_a), Some(drv_b)) => drv_a.cmp_output(drv_b),
+            _ => Ordering::Equal,
+        }
+    } else if sort_order == ui::SortOrder::NixStoreOrigOutput {
+        leader_a.0.back().cmp(&leader_b.0.back())
+    } else {
+        match (
+            nix_store_res.tree.lookup(leader_a),
+            nix_store_res.tree.lookup(leader_b),
+        ) {
+            (Some(nix_query_entry_a), Some(nix_query_entry_b)) => cmp_entries(
+                sort_order,
+                nix_store_res,
+                nix_query_entry_a,
+                nix_query_entry_b,
+            ),
+            _ => Ordering::Equal,
+        }
+    }
 }
 
 fn set_sort_func_callback(
@@ -143,34 +360,49 @@ fn set_sort_func_callback(
 ) -> Ordering {
     let sort_order = *state.read_sort_order();
     if let Some(nix_store_res) = &*state.read_nix_store_res() {
-        let tree_store: &gtk::TreeStore = tree_model
+        let tree_model_filter: &gtk::TreeModelFilter = tree_model
             .downcast_ref()
-            .expect("tree_model is not a tree_store");
+            .expect("tree_model is not a tree_model_filter");
+        let tree_store = state.get_tree_store();
 
-        let child_iter_a = path::GtkChildTreeIter::new(tree_model_sort_iter_a);
-        let child_iter_b = path::GtkChildTreeIter::new(tree_model_sort_iter_b);
+        let child_iter_a = path::GtkChildTreeIter::new(
+            tree_model_filter
+                .convert_iter_to_child_iter(&tree_model_sort_iter_a),
+        );
+        let child_iter_b = path::GtkChildTreeIter::new(
+            tree_model_filter
+                .convert_iter_to_child_iter(&tree_model_sort_iter_b),
+        );
+
+        if state.read_group_outputs() {
+            if let (Some(path_a), Some(path_b)) = (
+                child_iter_a.to_path(&tree_store),
+                child_iter_b.to_path(&tree_store),
+            ) {
+                return cmp_grouped_outputs(
+                    sort_order,
+                    nix_store_res,
+                    &path_a,
+                    &path_b,
+                );
+            }
+        }
 
         let option_nix_query_entry_a: Option<
             &crate::nix_query_tree::NixQueryEntry,
-        > = child_iter_a.nix_store_res_lookup(tree_store, &nix_store_res);
+        > = child_iter_a.nix_store_res_lookup(&tree_store, &nix_store_res);
         let option_nix_query_entry_b: Option<
             &crate::nix_query_tree::NixQueryEntry,
-        > = child_iter_b.nix_store_res_lookup(tree_store, &nix_store_res);
+        > = child_iter_b.nix_store_res_lookup(&tree_store, &nix_store_res);
 
         match (option_nix_query_entry_a, option_nix_query_entry_b) {
             (Some(nix_query_entry_a), Some(nix_query_entry_b)) => {
-                match sort_order {
-                    ui::SortOrder::NixStoreOrigOutput => {
-                        println!("The sort function should never be called when the sort order is NixStoreOrigOutput!!!");
-                        Ordering::Equal
-                    }
-                    ui::SortOrder::AlphabeticalHash => {
-                        nix_query_entry_a.cmp_hash(&nix_query_entry_b)
-                    }
-                    ui::SortOrder::AlphabeticalDrvName => {
-                        nix_query_entry_a.cmp_drv_name(&nix_query_entry_b)
-                    }
-                }
+                cmp_entries(
+                    sort_order,
+                    nix_store_res,
+                    nix_query_entry_a,
+                    nix_query_entry_b,
+                )
             }
             _ => panic!("Not able to get an ordering for one of the nix_query_entries.  This should never happen."),
         }
@@ -12,6 +12,27 @@ pub enum Column {
     HashAndDrvName,
     ShortHashAndDrvName,
     OnlyDrvName,
+    Highlight,
+    DrvNameAndOutput,
+    NarSize,
+    NarSizeStr,
+    ClosureSize,
+    ClosureSizeStr,
+    RegistrationTime,
+    RegistrationTimeStr,
+    NarHash,
+    Deriver,
+    Signatures,
+    RetainedSize,
+    RetainedSizeStr,
+    /// Whether this path is missing from the local store.
+    Missing,
+    /// Whether this path, or any path under it in the tree, is missing from
+    /// the local store.
+    HasMissing,
+    /// The excerpts from `nix why-depends --precise` of the files in the
+    /// parent that refer to this path, as markup for the row's tooltip.
+    Excerpts,
 }
 
 impl TryFrom<usize> for Column {
@@ -28,19 +49,66 @@ impl TryFrom<usize> for Column {
 
 impl Column {
     // Is there some way to derive these types of things?
-    const LIST: [Column; 5] = [
+    const LIST: [Column; 21] = [
         Column::FullPath,
         Column::Recurse,
         Column::HashAndDrvName,
         Column::ShortHashAndDrvName,
         Column::OnlyDrvName,
+        Column::Highlight,
+        Column::DrvNameAndOutput,
+        Column::NarSize,
+        Column::NarSizeStr,
+        Column::ClosureSize,
+        Column::ClosureSizeStr,
+        Column::RegistrationTime,
+        Column::RegistrationTimeStr,
+        Column::NarHash,
+        Column::Deriver,
+        Column::Signatures,
+        Column::RetainedSize,
+        Column::RetainedSizeStr,
+        Column::Missing,
+        Column::HasMissing,
+        Column::Excerpts,
     ];
-    pub const INDICIES: [usize; 5] = [
+    pub const INDICIES: [usize; 21] = [
         Column::FullPath as usize,
         Column::Recurse as usize,
         Column::HashAndDrvName as usize,
         Column::ShortHashAndDrvName as usize,
         Column::OnlyDrvName as usize,
+        Column::Highlight as usize,
+        Column::DrvNameAndOutput as usize,
+        Column::NarSize as usize,
+        Column::NarSizeStr as usize,
+        Column::ClosureSize as usize,
+        Column::ClosureSizeStr as usize,
+        Column::RegistrationTime as usize,
+        Column::RegistrationTimeStr as usize,
+        Column::NarHash as usize,
+        Column::Deriver as usize,
+        Column::Signatures as usize,
+        Column::RetainedSize as usize,
+        Column::RetainedSizeStr as usize,
+        Column::Missing as usize,
+        Column::HasMissing as usize,
+        Column::Excerpts as usize,
+    ];
+    /// The columns holding the metadata from `nix path-info`, and the
+    /// retained size computed from it.
+    pub const PATH_INFO_INDICIES: [usize; 11] = [
+        Column::NarSize as usize,
+        Column::NarSizeStr as usize,
+        Column::ClosureSize as usize,
+        Column::ClosureSizeStr as usize,
+        Column::RegistrationTime as usize,
+        Column::RegistrationTimeStr as usize,
+        Column::NarHash as usize,
+        Column::Deriver as usize,
+        Column::Signatures as usize,
+        Column::RetainedSize as usize,
+        Column::RetainedSizeStr as usize,
     ];
 }
 
@@ -50,6 +118,12 @@ pub fn change_view_style(state: &ui::State) {
 
     column.clear_attributes(&item_renderer);
 
+    column.add_attribute(
+        &item_renderer,
+        "cell-background-set",
+        Column::Highlight as i32,
+    );
+
     match *state.read_view_style() {
         ui::ViewStyle::FullPath => {
             column.add_attribute(
@@ -80,6 +154,13 @@ pub fn change_view_style(state: &ui::State) {
                 Column::OnlyDrvName as i32,
             );
         }
+        ui::ViewStyle::DrvNameAndOutput => {
+            column.add_attribute(
+                &item_renderer,
+                "text",
+                Column::DrvNameAndOutput as i32,
+            );
+        }
     }
 
     // Tree needs to be redrawn because changing the renderer on a column don't seem to cause a
@@ -0,0 +1,159 @@
+use glib::clone;
+
+use super::super::super::super::ui;
+use super::super::super::prelude::*;
+use super::path;
+use crate::nix_query_tree::exec_nix_store::NixStoreRes;
+use crate::nix_query_tree::NixQueryDrv;
+use crate::tree;
+
+/// These correspond to the columns in the `instancesListStore`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(i32)]
+enum Column {
+    FullPath = 0,
+    Instance,
+    Ancestors,
+}
+
+/// Which direction to move in when going to another instance of a
+/// `NixQueryDrv`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+    Next,
+    Previous,
+}
+
+/// Find the instance of the `NixQueryDrv` at `path` that comes after (or
+/// before) `path` in the tree.  This wraps around at either end.
+///
+/// Returns the `tree::Path` of that instance, along with its index and the
+/// total number of instances.
+fn sibling_instance(
+    nix_store_res: &NixStoreRes,
+    path: &tree::Path,
+    direction: Direction,
+) -> Option<(tree::Path, usize, usize)> {
+    let nix_query_entry = nix_store_res.tree.lookup(path.clone())?;
+    let paths = nix_store_res.map.lookup_all(&nix_query_entry.0);
+    let curr = paths.iter().position(|p| p == path)?;
+    let len = paths.len();
+    let new = match direction {
+        Direction::Next => (curr + 1) % len,
+        Direction::Previous => (curr + len - 1) % len,
+    };
+    Some((paths[new].clone(), new, len))
+}
+
+pub fn goto_sibling_instance(
+    state: &ui::State,
+    path: &tree::Path,
+    direction: Direction,
+) {
+    let option_sibling =
+        state
+            .read_nix_store_res()
+            .as_ref()
+            .and_then(|nix_store_res| {
+                sibling_instance(nix_store_res, path, direction)
+            });
+
+    if let Some((new_path, instance, len)) = option_sibling {
+        path::goto(state, &new_path);
+        ui::statusbar::show_msg(
+            state,
+            &format!("Instance {} of {}", instance + 1, len),
+        );
+    }
+}
+
+fn render_instances(state: &ui::State, nix_query_drv: &NixQueryDrv) {
+    let list_store = state.get_instances_list_store();
+    list_store.clear();
+
+    if let Some(nix_store_res) = &*state.read_nix_store_res() {
+        let view_style = *state.read_view_style();
+        let full_path = nix_query_drv.to_string();
+        let paths = nix_store_res.map.lookup_all(nix_query_drv);
+
+        for (instance, instance_path) in paths.iter().enumerate() {
+            let ancestors: String = nix_store_res
+                .tree
+                .lookup_with_ancestors(instance_path.clone())
+                .unwrap_or_default()
+                .iter()
+                .map(|nix_query_entry| view_style.render(&nix_query_entry.0))
+                .collect::<Vec<String>>()
+                .join(" → ");
+            list_store.insert_with_values(
+                None,
+                &[
+                    Column::FullPath as u32,
+                    Column::Instance as u32,
+                    Column::Ancestors as u32,
+                ],
+                &[&full_path, &(instance as u32), &ancestors],
+            );
+        }
+
+        state.get_instances_dialog_label().set_text(&format!(
+            "{} appears {} times in the tree",
+            view_style.render(nix_query_drv),
+            paths.len()
+        ));
+    }
+}
+
+/// Show a dialog listing every instance of `nix_query_drv` in the tree.
+pub fn show_dialog(state: &ui::State, nix_query_drv: &NixQueryDrv) {
+    render_instances(state, nix_query_drv);
+
+    let dialog = state.get_instances_dialog();
+    dialog.run();
+    dialog.hide();
+}
+
+/// Jump to the instance that was clicked on in the instances dialog.
+fn handle_row_activated(state: &ui::State, tree_path: &gtk::TreePath) {
+    let list_store = state.get_instances_list_store();
+    let option_full_path_and_instance: Option<(String, u32)> =
+        list_store.get_iter(tree_path).and_then(|iter| {
+            let full_path = list_store
+                .get_value(&iter, Column::FullPath as i32)
+                .get::<String>()
+                .ok()
+                .flatten()?;
+            let instance = list_store
+                .get_value(&iter, Column::Instance as i32)
+                .get_some::<u32>()
+                .ok()?;
+            Some((full_path, instance))
+        });
+
+    if let Some((full_path, instance)) = option_full_path_and_instance {
+        let nix_query_drv = NixQueryDrv::from(&full_path);
+        let option_path: Option<tree::Path> = state
+            .read_nix_store_res()
+            .as_ref()
+            .and_then(|nix_store_res| {
+                nix_store_res
+                    .map
+                    .lookup_nth(&nix_query_drv, instance as usize)
+                    .cloned()
+            });
+        if let Some(instance_path) = option_path {
+            state
+                .get_instances_dialog()
+                .response(gtk::ResponseType::Close);
+            path::goto(state, &instance_path);
+        }
+    }
+}
+
+pub fn connect(state: &ui::State) {
+    state.get_instances_tree_view().connect_row_activated(
+        clone!(@strong state => move |_, tree_path, _| {
+            handle_row_activated(&state, tree_path);
+        }),
+    );
+}
@@ -21,18 +21,23 @@ impl GtkChildTreePath {
         GtkChildTreePath(tree_path)
     }
 
-    fn get(&self) -> &gtk::TreePath {
+    pub fn get(&self) -> &gtk::TreePath {
         &self.0
     }
 
-    pub fn into_parent(
-        &self,
-        tree_model_sort: &gtk::TreeModelSort,
-    ) -> GtkParentTreePath {
-        let parent_tree_path = tree_model_sort
-            .convert_child_path_to_path(self.get())
-            .expect("child_tree_path should always be able to be converted to a parent tree_path");
-        GtkParentTreePath::new(parent_tree_path)
+    /// Convert to the path shown in the tree view, going through the
+    /// `gtk::TreeModelFilter` and the `gtk::TreeModelSort`.
+    ///
+    /// This returns `None` if the row is currently hidden by the filter.
+    pub fn into_parent(&self, state: &ui::State) -> Option<GtkParentTreePath> {
+        let filter_tree_path = state
+            .get_tree_model_filter()
+            .convert_child_path_to_path(self.get())?;
+        let parent_tree_path = state
+            .get_tree_model_sort()
+            .convert_child_path_to_path(&filter_tree_path)
+            .expect("filter_tree_path should always be able to be converted to a parent tree_path");
+        Some(GtkParentTreePath::new(parent_tree_path))
     }
 
     pub fn from_path(path: &tree::Path) -> Self {
@@ -72,7 +77,8 @@ impl GtkChildTreePath {
     }
 }
 
-/// This is a `gtk::TreePath` for the sorted model actually shown to the user.
+/// This is a `gtk::TreePath` for the filtered and sorted model actually shown
+/// to the user.
 ///
 /// This is just a "view" of the non-sorted data.
 pub struct GtkParentTreePath(gtk::TreePath);
@@ -96,47 +102,43 @@ impl GtkParentTreePath {
         &self.0
     }
 
-    pub fn into_child(
-        &self,
-        tree_model_sort: &gtk::TreeModelSort,
-    ) -> GtkChildTreePath {
-        let parent_tree_path = tree_model_sort
+    pub fn into_child(&self, state: &ui::State) -> GtkChildTreePath {
+        let filter_tree_path = state
+            .get_tree_model_sort()
             .convert_path_to_child_path(self.get())
-            .expect("child_tree_path should always be able to be converted to a child_tree_path");
-        GtkChildTreePath::new(parent_tree_path)
+            .expect("parent_tree_path should always be able to be converted to a filter_tree_path");
+        let child_tree_path = state
+            .get_tree_model_filter()
+            .convert_path_to_child_path(&filter_tree_path)
+            .expect("filter_tree_path should always be able to be converted to a child_tree_path");
+        GtkChildTreePath::new(child_tree_path)
     }
 
     #[allow(dead_code)]
-    pub fn from_path(
-        tree_model_sort: &gtk::TreeModelSort,
-        path: &tree::Path,
-    ) -> Self {
-        GtkChildTreePath::from_path(path).into_parent(tree_model_sort)
+    pub fn from_path(state: &ui::State, path: &tree::Path) -> Option<Self> {
+        GtkChildTreePath::from_path(path).into_parent(state)
     }
 
     #[allow(dead_code)]
-    pub fn to_path(&self, tree_model_sort: &gtk::TreeModelSort) -> tree::Path {
-        self.into_child(tree_model_sort).to_path()
+    pub fn to_path(&self, state: &ui::State) -> tree::Path {
+        self.into_child(state).to_path()
     }
 
     #[allow(dead_code)]
     pub fn nix_query_tree_lookup<'a>(
         &self,
-        tree_model_sort: &gtk::TreeModelSort,
+        state: &ui::State,
         nix_query_tree: &'a NixQueryTree,
     ) -> Option<&'a NixQueryEntry> {
-        self.into_child(tree_model_sort)
-            .nix_query_tree_lookup(nix_query_tree)
+        self.into_child(state).nix_query_tree_lookup(nix_query_tree)
     }
 
-    #[allow(dead_code)]
     pub fn nix_store_res_lookup<'a>(
         &self,
-        tree_model_sort: &gtk::TreeModelSort,
+        state: &ui::State,
         nix_store_res: &'a NixStoreRes,
     ) -> Option<&'a NixQueryEntry> {
-        self.into_child(tree_model_sort)
-            .nix_store_res_lookup(nix_store_res)
+        self.into_child(state).nix_store_res_lookup(nix_store_res)
     }
 }
 
@@ -153,6 +155,11 @@ impl GtkChildTreeIter {
         &self.0
     }
 
+    pub fn to_path(&self, tree_store: &gtk::TreeStore) -> Option<tree::Path> {
+        let tree_path = GtkChildTreePath::new(tree_store.get_path(self.get())?);
+        Some(tree_path.to_path())
+    }
+
     pub fn nix_store_res_lookup<'a>(
         &self,
         tree_store: &gtk::TreeStore,
@@ -213,17 +220,34 @@ impl TreeViewCol {
     }
 }
 
+/// Open the tree view recursively upward from the given `tree::Path`, so that
+/// it is visible.
+///
+/// This returns `None` and does nothing if the row is hidden by the filter.
+pub fn expand_to(
+    state: &ui::State,
+    path: &tree::Path,
+) -> Option<GtkParentTreePath> {
+    let child_tree_path = GtkChildTreePath::from_path(path);
+    let parent_tree_path = child_tree_path.into_parent(state)?;
+
+    state
+        .get_tree_view()
+        .expand_to_path(&parent_tree_path.get());
+
+    Some(parent_tree_path)
+}
+
 pub fn goto(state: &ui::State, first_path: &tree::Path) {
     let tree_view = state.get_tree_view();
 
-    let tree_model_sort = state.get_tree_model_sort();
-    let child_tree_path = GtkChildTreePath::from_path(first_path);
-    let parent_tree_path = child_tree_path.into_parent(&tree_model_sort);
-
     let col = tree_view.get_column(TreeViewCol::Item as i32);
 
     // Open recursively upward from this new path.
-    tree_view.expand_to_path(&parent_tree_path.get());
+    let parent_tree_path = match expand_to(state, first_path) {
+        Some(parent_tree_path) => parent_tree_path,
+        None => return,
+    };
 
     // Scroll to the newly opened path.
     tree_view.scroll_to_cell(
@@ -258,13 +282,9 @@ fn event_button_to_child_tree_path_column(
     state: &ui::State,
     event_button: &gdk::EventButton,
 ) -> Option<(GtkChildTreePath, gtk::TreeViewColumn)> {
-    let tree_model_sort = state.get_tree_model_sort();
     event_button_to_parent_tree_path_column(state, event_button).map(
         |(parent_tree_path, tree_view_column)| {
-            (
-                parent_tree_path.into_child(&tree_model_sort),
-                tree_view_column,
-            )
+            (parent_tree_path.into_child(state), tree_view_column)
         },
     )
 }
@@ -301,8 +321,7 @@ pub fn is_for_recurse_column_parent<'a>(
     parent_tree_path: &GtkParentTreePath,
     nix_store_res: &'a NixStoreRes,
 ) -> Option<&'a NixQueryEntry> {
-    let tree_model_sort = state.get_tree_model_sort();
-    let child_tree_path = parent_tree_path.into_child(&tree_model_sort);
+    let child_tree_path = parent_tree_path.into_child(state);
     is_for_recurse_column_child(
         state,
         tree_view_column,
@@ -338,3 +357,12 @@ pub fn nix_query_entry_for_event_button<'a>(
 
     option_child_tree_path.and_then(|x| x.nix_store_res_lookup(nix_store_res))
 }
+
+/// Get the `tree::Path` of the row under the given `gdk::EventButton`.
+pub fn path_for_event_button(
+    state: &ui::State,
+    event_button: &gdk::EventButton,
+) -> Option<tree::Path> {
+    event_button_to_child_tree_path(state, event_button)
+        .map(|child_tree_path| child_tree_path.to_path())
+}
@@ -0,0 +1,107 @@
+use glib::clone;
+
+use super::super::super::super::ui;
+use super::super::super::prelude::*;
+use super::path;
+use crate::nix_query_tree::exec_nix_store::NixStoreRes;
+use crate::nix_query_tree::{NixQueryDrv, NixQueryEntry};
+
+/// These correspond to the columns in the `referrersListStore`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(i32)]
+enum Column {
+    FullPath = 0,
+    Display,
+}
+
+/// Get the `NixQueryEntry` for the row currently selected in the tree view.
+fn selected_nix_query_entry<'a>(
+    state: &ui::State,
+    nix_store_res: &'a NixStoreRes,
+) -> Option<&'a NixQueryEntry> {
+    let tree_model_sort = state.get_tree_model_sort();
+    let (_, tree_iter) =
+        state.get_tree_view().get_selection().get_selected()?;
+    let tree_path = tree_model_sort.get_path(&tree_iter)?;
+    path::GtkParentTreePath::new(tree_path)
+        .nix_store_res_lookup(state, nix_store_res)
+}
+
+fn render_referrers(
+    state: &ui::State,
+    nix_store_res: &NixStoreRes,
+    nix_query_entry: &NixQueryEntry,
+) {
+    let list_store = state.get_referrers_list_store();
+    let view_style = *state.read_view_style();
+
+    for referrer in nix_store_res.referrers(&nix_query_entry.0) {
+        let full_path = referrer.to_string();
+        let display = view_style.render(referrer);
+        list_store.insert_with_values(
+            None,
+            &[Column::FullPath as u32, Column::Display as u32],
+            &[&full_path, &display],
+        );
+    }
+}
+
+fn handle_selection_changed(state: &ui::State) {
+    clear(state);
+
+    if let Some(nix_store_res) = &*state.read_nix_store_res() {
+        if let Some(nix_query_entry) =
+            selected_nix_query_entry(state, nix_store_res)
+        {
+            render_referrers(state, nix_store_res, nix_query_entry);
+        }
+    }
+}
+
+/// Jump to the tree instance of the referrer that was clicked on.
+fn handle_row_activated(state: &ui::State, tree_path: &gtk::TreePath) {
+    let list_store = state.get_referrers_list_store();
+    let option_full_path: Option<String> = list_store
+        .get_iter(tree_path)
+        .map(|iter| list_store.get_value(&iter, Column::FullPath as i32))
+        .and_then(|value| value.get::<String>().ok().flatten());
+
+    if let Some(full_path) = option_full_path {
+        let referrer = NixQueryDrv::from(&full_path);
+        if let Some(nix_store_res) = &*state.read_nix_store_res() {
+            if let Some(first_path) = nix_store_res.lookup_expanded(&referrer) {
+                path::goto(state, first_path);
+            }
+        }
+    }
+}
+
+pub fn clear(state: &ui::State) {
+    state.get_referrers_list_store().clear();
+}
+
+pub fn disable(state: &ui::State) {
+    state.get_referrers_tree_view().set_sensitive(false);
+}
+
+pub fn enable(state: &ui::State) {
+    state.get_referrers_tree_view().set_sensitive(true);
+}
+
+pub fn change_view_style(state: &ui::State) {
+    handle_selection_changed(state);
+}
+
+pub fn connect(state: &ui::State) {
+    state.get_tree_view().get_selection().connect_changed(
+        clone!(@strong state => move |_| {
+            handle_selection_changed(&state);
+        }),
+    );
+
+    state.get_referrers_tree_view().connect_row_activated(
+        clone!(@strong state => move |_, tree_path, _| {
+            handle_row_activated(&state, tree_path);
+        }),
+    );
+}
@@ -2,9 +2,11 @@ use glib::clone;
 
 use super::super::super::super::ui;
 use super::super::super::prelude::*;
+use super::instances;
 use super::path;
+use super::why_depends;
 use crate::nix_query_tree::exec_nix_store::NixStoreRes;
-use crate::nix_query_tree::NixQueryEntry;
+use crate::nix_query_tree::{NixQueryEntry, Recurse};
 
 fn toggle_row_expanded(
     state: &ui::State,
@@ -69,19 +71,47 @@ fn handle_row_activated(
     }
 }
 
-fn handle_copy_drv_path_menu_item_activated(
-    state: &ui::State,
-    nix_query_entry: &NixQueryEntry,
-) {
+/// Start loading the references of the row that was just expanded, if they
+/// haven't been loaded yet.
+fn handle_row_expanded(state: &ui::State, tree_path: &gtk::TreePath) {
+    let child_tree_path =
+        path::GtkParentTreePath::new(tree_path.clone()).into_child(state);
+    let option_nix_query_drv =
+        state
+            .read_nix_store_res()
+            .as_ref()
+            .and_then(|nix_store_res| {
+                child_tree_path
+                    .nix_store_res_lookup(nix_store_res)
+                    .filter(|nix_query_entry| {
+                        nix_query_entry.1 == Recurse::No
+                            && !nix_store_res.is_loaded(&nix_query_entry.0)
+                    })
+                    .map(|nix_query_entry| nix_query_entry.0.clone())
+            });
+
+    if let Some(nix_query_drv) = option_nix_query_drv {
+        ui::load_references(state, child_tree_path.to_path(), nix_query_drv);
+    }
+}
+
+fn set_clipboard_text(state: &ui::State, text: &str) {
     let tree_view = state.get_tree_view();
     if let Some(display) = tree_view.get_display() {
         if let Some(clipboard) = gtk::Clipboard::get_default(&display) {
-            clipboard.set_text(&nix_query_entry.to_string_lossy());
+            clipboard.set_text(text);
             clipboard.store();
         }
     }
 }
 
+fn handle_copy_drv_path_menu_item_activated(
+    state: &ui::State,
+    nix_query_entry: &NixQueryEntry,
+) {
+    set_clipboard_text(state, &nix_query_entry.to_string_lossy());
+}
+
 fn create_copy_drv_path_menu_item(
     state: &ui::State,
     menu: &gtk::Menu,
@@ -106,6 +136,25 @@ fn create_copy_drv_path_menu_item(
     }
 }
 
+fn create_copy_location_menu_item(
+    state: &ui::State,
+    menu: &gtk::Menu,
+    event_button: &gdk::EventButton,
+) {
+    if let Some(tree_path) = path::path_for_event_button(state, event_button) {
+        let copy_location_menu_item =
+            gtk::MenuItem::new_with_label("Copy location");
+
+        copy_location_menu_item.connect_activate(
+            clone!(@strong state, @strong tree_path => move |_| {
+                set_clipboard_text(&state, &tree_path.to_string());
+            }),
+        );
+
+        menu.append(&copy_location_menu_item);
+    }
+}
+
 fn handle_search_for_this_menu_item_activated(
     state: &ui::State,
     nix_query_entry: &NixQueryEntry,
@@ -137,6 +186,41 @@ fn create_search_for_this_menu_item(
     }
 }
 
+/// Create a menu item for switching to the build-time derivation graph of an
+/// output, or to the runtime closure of a `.drv` file.
+fn create_switch_closure_menu_item(
+    state: &ui::State,
+    menu: &gtk::Menu,
+    event_button: &gdk::EventButton,
+    nix_store_res: &NixStoreRes,
+) {
+    if let Some(nix_query_entry) = path::nix_query_entry_for_event_button(
+        state,
+        event_button,
+        nix_store_res,
+    ) {
+        let is_drv = nix_query_entry.0.is_drv();
+        let switch_closure_menu_item =
+            gtk::MenuItem::new_with_label(if is_drv {
+                "Show runtime closure"
+            } else {
+                "Show build dependencies"
+            });
+
+        switch_closure_menu_item.connect_activate(
+            clone!(@strong state, @strong nix_query_entry => move |_| {
+                if is_drv {
+                    ui::show_runtime_closure(&state, &nix_query_entry.0);
+                } else {
+                    ui::show_build_dependencies(&state, &nix_query_entry.0);
+                }
+            }),
+        );
+
+        menu.append(&switch_closure_menu_item);
+    }
+}
+
 fn create_goto_first_instance_menu_item(
     state: &ui::State,
     menu: &gtk::Menu,
@@ -162,6 +246,102 @@ fn create_goto_first_instance_menu_item(
     }
 }
 
+fn create_instances_menu_items(
+    state: &ui::State,
+    menu: &gtk::Menu,
+    event_button: &gdk::EventButton,
+    nix_store_res: &NixStoreRes,
+) {
+    if let Some(tree_path) = path::path_for_event_button(state, event_button) {
+        if let Some(nix_query_entry) =
+            nix_store_res.tree.lookup(tree_path.clone())
+        {
+            let num_instances =
+                nix_store_res.map.lookup_all(&nix_query_entry.0).len();
+
+            if num_instances > 1 {
+                let next_instance_menu_item =
+                    gtk::MenuItem::new_with_label("Go to next instance");
+
+                next_instance_menu_item.connect_activate(
+                    clone!(@strong state, @strong tree_path => move |_| {
+                        instances::goto_sibling_instance(
+                            &state,
+                            &tree_path,
+                            instances::Direction::Next,
+                        );
+                    }),
+                );
+
+                menu.append(&next_instance_menu_item);
+
+                let previous_instance_menu_item =
+                    gtk::MenuItem::new_with_label("Go to previous instance");
+
+                previous_instance_menu_item.connect_activate(
+                    clone!(@strong state, @strong tree_path => move |_| {
+                        instances::goto_sibling_instance(
+                            &state,
+                            &tree_path,
+                            instances::Direction::Previous,
+                        );
+                    }),
+                );
+
+                menu.append(&previous_instance_menu_item);
+            }
+
+            let show_all_instances_menu_item = gtk::MenuItem::new_with_label(
+                &format!("Show all instances ({})", num_instances),
+            );
+
+            show_all_instances_menu_item.connect_activate(
+                clone!(@strong state, @strong nix_query_entry => move |_| {
+                    instances::show_dialog(&state, &nix_query_entry.0);
+                }),
+            );
+
+            menu.append(&show_all_instances_menu_item);
+        }
+    }
+}
+
+fn create_why_depends_menu_items(
+    state: &ui::State,
+    menu: &gtk::Menu,
+    event_button: &gdk::EventButton,
+    nix_store_res: &NixStoreRes,
+) {
+    if let Some(nix_query_entry) = path::nix_query_entry_for_event_button(
+        state,
+        event_button,
+        nix_store_res,
+    ) {
+        let shortest_chain_menu_item = gtk::MenuItem::new_with_label(
+            "Why depends on this (shortest chain)",
+        );
+
+        shortest_chain_menu_item.connect_activate(
+            clone!(@strong state, @strong nix_query_entry => move |_| {
+                why_depends::show_shortest_chain(&state, &nix_query_entry.0);
+            }),
+        );
+
+        menu.append(&shortest_chain_menu_item);
+
+        let all_chains_menu_item =
+            gtk::MenuItem::new_with_label("Why depends on this (all chains)");
+
+        all_chains_menu_item.connect_activate(
+            clone!(@strong state, @strong nix_query_entry => move |_| {
+                why_depends::show_all_chains(&state, &nix_query_entry.0);
+            }),
+        );
+
+        menu.append(&all_chains_menu_item);
+    }
+}
+
 fn handle_button_press_event(
     state: &ui::State,
     tree_view: &gtk::TreeView,
@@ -180,6 +360,8 @@ fn handle_button_press_event(
                 nix_store_res,
             );
 
+            create_copy_location_menu_item(state, &menu, event_button);
+
             create_search_for_this_menu_item(
                 state,
                 &menu,
@@ -187,6 +369,13 @@ fn handle_button_press_event(
                 nix_store_res,
             );
 
+            create_switch_closure_menu_item(
+                state,
+                &menu,
+                event_button,
+                nix_store_res,
+            );
+
             create_goto_first_instance_menu_item(
                 state,
                 &menu,
@@ -194,6 +383,20 @@ fn handle_button_press_event(
                 nix_store_res,
             );
 
+            create_instances_menu_items(
+                state,
+                &menu,
+                event_button,
+                nix_store_res,
+            );
+
+            create_why_depends_menu_items(
+                state,
+                &menu,
+                event_button,
+                nix_store_res,
+            );
+
             // only show the menu if there is at least one child
             if menu.get_children().len() >= 1 {
                 menu.set_property_attach_widget(Some(&tree_view.clone()));
@@ -217,6 +420,12 @@ pub fn connect(state: &ui::State) {
         }),
     );
 
+    state.get_tree_view().connect_row_expanded(
+        clone!(@strong state => move |_, _, tree_path| {
+            handle_row_expanded(&state, tree_path);
+        }),
+    );
+
     state.get_tree_view().connect_button_press_event(
         clone!(@strong state => move |tree_view_ref, event_button| {
             handle_button_press_event(
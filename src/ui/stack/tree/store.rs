@@ -1,59 +1,370 @@
 use crate::nix_query_tree::exec_nix_store::NixStoreRes;
+use crate::nix_query_tree::path_info::{
+    format_size, format_timestamp, PathInfo,
+};
 use crate::nix_query_tree::{
     NixQueryDrv, NixQueryEntry, NixQueryTree, Recurse,
 };
-use crate::tree::Tree;
+use crate::tree::{self, Tree};
 
 use super::super::super::prelude::*;
 use super::columns;
+use super::path::GtkChildTreePath;
 
-fn insert_child(
+/// The text shown in the placeholder row for a path whose references haven't
+/// been loaded yet.
+const LOADING_PLACEHOLDER: &str = "loading…";
+
+const RECURSE_STR: &str = "go to tree instance";
+
+fn insert_row(
     tree_store: &gtk::TreeStore,
     parent: Option<&gtk::TreeIter>,
+    values: &[&dyn ToValue],
+) -> gtk::TreeIter {
+    tree_store.insert_with_values(
+        parent,
+        None,
+        &columns::Column::INDICIES
+            .iter()
+            .map(|&i| i as u32)
+            .collect::<Vec<u32>>(),
+        values,
+    )
+}
+
+/// The values of the `columns::Column::PATH_INFO_INDICIES` columns for a
+/// row.  Sizes and times are stored both as numbers to sort by and as
+/// human-readable strings to show.  These are all empty for a path whose
+/// metadata hasn't been loaded.  The retained size is taken from
+/// `NixStoreRes::retained_size` instead of the path's own metadata.
+struct PathInfoValues {
+    nar_size: u64,
+    nar_size_str: String,
+    closure_size: u64,
+    closure_size_str: String,
+    registration_time: u64,
+    registration_time_str: String,
+    nar_hash: String,
+    deriver: String,
+    signatures: String,
+    retained_size: u64,
+    retained_size_str: String,
+}
+
+impl PathInfoValues {
+    fn new(nix_store_res: &NixStoreRes, drv: &NixQueryDrv) -> Self {
+        let option_path_info: Option<&PathInfo> = nix_store_res.path_info(drv);
+        let option_retained_size = nix_store_res.retained_size(drv);
+        let option_nar_size = option_path_info.and_then(|info| info.nar_size);
+        let option_closure_size =
+            option_path_info.and_then(|info| info.closure_size);
+        let option_registration_time =
+            option_path_info.and_then(|info| info.registration_time);
+        PathInfoValues {
+            nar_size: option_nar_size.unwrap_or(0),
+            nar_size_str: option_nar_size.map(format_size).unwrap_or_default(),
+            closure_size: option_closure_size.unwrap_or(0),
+            closure_size_str: option_closure_size
+                .map(format_size)
+                .unwrap_or_default(),
+            registration_time: option_registration_time.unwrap_or(0),
+            registration_time_str: option_registration_time
+                .map(format_timestamp)
+                .unwrap_or_default(),
+            nar_hash: option_path_info
+                .and_then(|info| info.nar_hash.clone())
+                .unwrap_or_default(),
+            deriver: option_path_info
+                .and_then(|info| info.deriver.as_ref())
+                .map(NixQueryDrv::drv_name)
+                .unwrap_or_default(),
+            signatures: option_path_info
+                .map(|info| info.signature_key_names().join(", "))
+                .unwrap_or_default(),
+            retained_size: option_retained_size.unwrap_or(0),
+            retained_size_str: option_retained_size
+                .map(format_size)
+                .unwrap_or_default(),
+        }
+    }
+
+    fn values(&self) -> [&dyn ToValue; 11] {
+        [
+            &self.nar_size,
+            &self.nar_size_str,
+            &self.closure_size,
+            &self.closure_size_str,
+            &self.registration_time,
+            &self.registration_time_str,
+            &self.nar_hash,
+            &self.deriver,
+            &self.signatures,
+            &self.retained_size,
+            &self.retained_size_str,
+        ]
+    }
+}
+
+/// Insert a placeholder row under `parent`, so that `parent` can be expanded
+/// before its references have been loaded.
+fn insert_placeholder(tree_store: &gtk::TreeStore, parent: &gtk::TreeIter) {
+    insert_row(
+        tree_store,
+        Some(parent),
+        &[
+            &LOADING_PLACEHOLDER,
+            &"",
+            &LOADING_PLACEHOLDER,
+            &LOADING_PLACEHOLDER,
+            &LOADING_PLACEHOLDER,
+            &false,
+            &LOADING_PLACEHOLDER,
+            &0_u64,
+            &"",
+            &0_u64,
+            &"",
+            &0_u64,
+            &"",
+            &"",
+            &"",
+            &"",
+            &0_u64,
+            &"",
+            &false,
+            &false,
+            &None::<String>,
+        ],
+    );
+}
+
+/// The tooltip for a row of `drv` under `parent_drv`, with the excerpts of
+/// the files in `parent_drv` that refer to `drv`.  This is `None` if there
+/// are no excerpts, so that no tooltip is shown.
+fn excerpts_markup(
+    nix_store_res: &NixStoreRes,
+    parent_drv: Option<&NixQueryDrv>,
+    drv: &NixQueryDrv,
+) -> Option<String> {
+    let excerpts = parent_drv
+        .map(|parent_drv| nix_store_res.excerpts(parent_drv, drv))
+        .unwrap_or_default();
+    if excerpts.is_empty() {
+        None
+    } else {
+        Some(glib::markup_escape_text(&excerpts.join("\n")).to_string())
+    }
+}
+
+/// Insert `child` and everything under it.  This returns whether any of the
+/// inserted paths is missing from the local store.
+fn insert_child(
+    tree_store: &gtk::TreeStore,
+    nix_store_res: &NixStoreRes,
+    parent: Option<(&gtk::TreeIter, &NixQueryDrv)>,
     child: &Tree<NixQueryEntry>,
-) {
+) -> bool {
     let Tree { item, children }: &Tree<NixQueryEntry> = child;
     let drv: &NixQueryDrv = &item.0;
     let drv_str = drv.to_string();
     let hash_and_drv_name = drv.hash_and_drv_name();
     let short_hash_and_drv_name = drv.short_hash_and_drv_name();
     let only_drv_name = drv.drv_name();
+    let drv_name_and_output = drv.drv_name_and_output();
+    let path_info_values = PathInfoValues::new(nix_store_res, drv);
     let recurse_str = if item.1 == Recurse::Yes {
-        "go to tree instance"
+        RECURSE_STR
     } else {
         ""
     };
-    let this_iter: gtk::TreeIter = tree_store.insert_with_values(
-        parent,
-        None,
-        &columns::Column::INDICIES
-            .iter()
-            .map(|&i| i as u32)
-            .collect::<Vec<u32>>(),
-        &[
-            &drv_str,
-            &recurse_str,
-            &hash_and_drv_name,
-            &short_hash_and_drv_name,
-            &only_drv_name,
-        ],
-    );
-    insert_children(tree_store, &this_iter, children);
+    let mut values: Vec<&dyn ToValue> = vec![
+        &drv_str,
+        &recurse_str,
+        &hash_and_drv_name,
+        &short_hash_and_drv_name,
+        &only_drv_name,
+        &false,
+        &drv_name_and_output,
+    ];
+    values.extend_from_slice(&path_info_values.values());
+    let missing = nix_store_res.is_invalid(drv);
+    let excerpts =
+        excerpts_markup(nix_store_res, parent.map(|(_, drv)| drv), drv);
+    values.extend_from_slice(&[&missing, &false, &excerpts]);
+    let this_iter: gtk::TreeIter =
+        insert_row(tree_store, parent.map(|(iter, _)| iter), &values);
+    if item.1 == Recurse::No && !nix_store_res.is_loaded(drv) {
+        insert_placeholder(tree_store, &this_iter);
+    }
+    let has_missing =
+        insert_children(tree_store, nix_store_res, &this_iter, drv, children)
+            || missing;
+    if has_missing {
+        tree_store.set_value(
+            &this_iter,
+            columns::Column::HasMissing as u32,
+            &true.to_value(),
+        );
+    }
+    has_missing
 }
 
 fn insert_children(
     tree_store: &gtk::TreeStore,
+    nix_store_res: &NixStoreRes,
     parent: &gtk::TreeIter,
+    parent_drv: &NixQueryDrv,
     children: &[Tree<NixQueryEntry>],
-) {
+) -> bool {
+    let mut has_missing = false;
     for child in children {
         let _: &Tree<NixQueryEntry> = child;
-        insert_child(tree_store, Some(parent), child)
+        has_missing |= insert_child(
+            tree_store,
+            nix_store_res,
+            Some((parent, parent_drv)),
+            child,
+        );
     }
+    has_missing
+}
+
+/// Remove all the children of `parent`.
+fn remove_children(tree_store: &gtk::TreeStore, parent: &gtk::TreeIter) {
+    while let Some(child_iter) = tree_store.iter_children(Some(parent)) {
+        tree_store.remove(&child_iter);
+    }
+}
+
+fn get_iter(
+    tree_store: &gtk::TreeStore,
+    path: &tree::Path,
+) -> Option<gtk::TreeIter> {
+    tree_store.get_iter(GtkChildTreePath::from_path(path).get())
+}
+
+/// Replace the placeholder row under `path` with the references that have
+/// just been loaded into `nix_store_res`.  `recursed_paths` are the other
+/// instances of the same path, which have been turned into `[...]` entries.
+pub fn insert_references(
+    tree_store: &gtk::TreeStore,
+    nix_store_res: &NixStoreRes,
+    path: &tree::Path,
+    recursed_paths: &[tree::Path],
+) {
+    if let (Some(iter), Some(tree)) = (
+        get_iter(tree_store, path),
+        nix_store_res.tree.0.lookup_tree(path.clone()),
+    ) {
+        remove_children(tree_store, &iter);
+        insert_children(
+            tree_store,
+            nix_store_res,
+            &iter,
+            &tree.item.0,
+            &tree.children,
+        );
+    }
+
+    for recursed_path in recursed_paths {
+        if let Some(iter) = get_iter(tree_store, recursed_path) {
+            remove_children(tree_store, &iter);
+            tree_store.set_value(
+                &iter,
+                columns::Column::Recurse as u32,
+                &RECURSE_STR.to_value(),
+            );
+        }
+    }
+
+    // Loading more references can move missing paths to a different part of
+    // the tree.
+    if !nix_store_res.invalid_paths.is_empty() {
+        update_validity(tree_store, nix_store_res);
+    }
+}
+
+/// Set the `Missing` and `HasMissing` columns of the row at `iter` and every
+/// row under it, where `tree` is the part of the tree at that row.  This
+/// returns whether any of those paths is missing.
+fn update_validity_rows(
+    tree_store: &gtk::TreeStore,
+    nix_store_res: &NixStoreRes,
+    iter: &gtk::TreeIter,
+    tree: &Tree<NixQueryEntry>,
+) -> bool {
+    let missing = nix_store_res.is_invalid(&tree.item.0);
+    let mut has_missing = missing;
+    if let Some(child_iter) = tree_store.iter_children(Some(iter)) {
+        for child in &tree.children {
+            has_missing |= update_validity_rows(
+                tree_store,
+                nix_store_res,
+                &child_iter,
+                child,
+            );
+            if !tree_store.iter_next(&child_iter) {
+                break;
+            }
+        }
+    }
+    tree_store.set(
+        iter,
+        &[
+            columns::Column::Missing as u32,
+            columns::Column::HasMissing as u32,
+        ],
+        &[&missing, &has_missing],
+    );
+    has_missing
+}
+
+/// Mark every row whose path is missing from the local store, according to
+/// `nix_store_res`.
+pub fn update_validity(
+    tree_store: &gtk::TreeStore,
+    nix_store_res: &NixStoreRes,
+) {
+    if let Some(iter) = tree_store.get_iter_first() {
+        update_validity_rows(
+            tree_store,
+            nix_store_res,
+            &iter,
+            &nix_store_res.tree.0,
+        );
+    }
+}
+
+/// Fill in the metadata and retained size columns of every row from the
+/// metadata that has been loaded into `nix_store_res`.
+pub fn update_path_infos(
+    tree_store: &gtk::TreeStore,
+    nix_store_res: &NixStoreRes,
+) {
+    let path_info_columns: Vec<u32> = columns::Column::PATH_INFO_INDICIES
+        .iter()
+        .map(|&i| i as u32)
+        .collect();
+
+    tree_store.foreach(|_, tree_path, iter| {
+        let child_tree_path = GtkChildTreePath::new(tree_path.clone());
+        if let Some(nix_query_entry) =
+            child_tree_path.nix_query_tree_lookup(&nix_store_res.tree)
+        {
+            let path_info_values =
+                PathInfoValues::new(nix_store_res, &nix_query_entry.0);
+            tree_store.set(
+                iter,
+                &path_info_columns,
+                &path_info_values.values(),
+            );
+        }
+        false
+    });
 }
 
 pub fn insert(tree_store: &gtk::TreeStore, nix_store_res: &NixStoreRes) {
     let nix_query_tree: &NixQueryTree = &nix_store_res.tree;
     let tree: &Tree<NixQueryEntry> = &nix_query_tree.0;
-    insert_child(tree_store, None, tree);
+    insert_child(tree_store, nix_store_res, None, tree);
 }
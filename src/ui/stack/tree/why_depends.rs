@@ -0,0 +1,132 @@
+use super::super::super::super::ui;
+use super::super::super::prelude::*;
+use super::columns;
+use super::path;
+use crate::nix_query_tree::exec_nix_store::NixStoreRes;
+use crate::nix_query_tree::NixQueryDrv;
+use crate::tree;
+
+/// The maximum number of chains to find when showing all the chains to a
+/// `NixQueryDrv`.
+///
+/// The number of chains can grow exponentially with the size of the closure,
+/// so this stops us from trying to enumerate all of them.
+pub const ALL_CHAINS_LIMIT: usize = 100;
+
+/// Remove all highlighting from the tree view.
+pub fn clear_highlights(state: &ui::State) {
+    let tree_store = state.get_tree_store();
+    tree_store.foreach(|tree_model, _, tree_iter| {
+        let tree_store: &gtk::TreeStore = tree_model
+            .downcast_ref()
+            .expect("tree_model is not a tree_store");
+        tree_store.set_value(
+            tree_iter,
+            columns::Column::Highlight as u32,
+            &false.to_value(),
+        );
+        false
+    });
+}
+
+fn highlight_path(state: &ui::State, path: &tree::Path) {
+    let tree_store = state.get_tree_store();
+    let child_tree_path = path::GtkChildTreePath::from_path(path);
+    if let Some(tree_iter) = tree_store.get_iter(child_tree_path.get()) {
+        tree_store.set_value(
+            &tree_iter,
+            columns::Column::Highlight as u32,
+            &true.to_value(),
+        );
+    }
+}
+
+/// Highlight and expand every row in each of the chains.  Finally, jump to
+/// the target of the chains.
+fn highlight_chains(
+    state: &ui::State,
+    nix_store_res: &NixStoreRes,
+    chains: &[Vec<&NixQueryDrv>],
+) {
+    clear_highlights(state);
+
+    let mut option_target_path: Option<tree::Path> = None;
+    for chain in chains {
+        let paths = nix_store_res.chain_paths(chain);
+        for path in &paths {
+            highlight_path(state, path);
+            path::expand_to(state, path);
+        }
+        if option_target_path.is_none() {
+            option_target_path = paths.last().cloned();
+        }
+    }
+
+    if let Some(target_path) = option_target_path {
+        path::goto(state, &target_path);
+    }
+}
+
+fn render_chain(view_style: ui::ViewStyle, chain: &[&NixQueryDrv]) -> String {
+    chain
+        .iter()
+        .map(|nix_query_drv| view_style.render(nix_query_drv))
+        .collect::<Vec<String>>()
+        .join(" → ")
+}
+
+/// Highlight a shortest chain of references from the root to `target`, and
+/// show it in the statusbar.
+pub fn show_shortest_chain(state: &ui::State, target: &NixQueryDrv) {
+    if let Some(nix_store_res) = &*state.read_nix_store_res() {
+        let view_style = *state.read_view_style();
+        match nix_store_res.shortest_chain(target) {
+            None => {
+                clear_highlights(state);
+                ui::statusbar::show_msg(
+                    state,
+                    &format!(
+                        "{} is not depended on by anything",
+                        view_style.render(target)
+                    ),
+                );
+            }
+            Some(chain) => {
+                highlight_chains(
+                    state,
+                    nix_store_res,
+                    std::slice::from_ref(&chain),
+                );
+                ui::statusbar::show_msg(
+                    state,
+                    &render_chain(view_style, &chain),
+                );
+            }
+        }
+    }
+}
+
+/// Highlight every chain of references from the root to `target` (up to
+/// `ALL_CHAINS_LIMIT`), and show a summary in the statusbar.
+pub fn show_all_chains(state: &ui::State, target: &NixQueryDrv) {
+    if let Some(nix_store_res) = &*state.read_nix_store_res() {
+        let view_style = *state.read_view_style();
+        let chains = nix_store_res.all_chains(target, ALL_CHAINS_LIMIT);
+        highlight_chains(state, nix_store_res, &chains);
+
+        let msg = match chains.len() {
+            0 => format!(
+                "{} is not depended on by anything",
+                view_style.render(target)
+            ),
+            1 => format!("1 chain: {}", render_chain(view_style, &chains[0])),
+            n if n >= ALL_CHAINS_LIMIT => format!(
+                "Showing the {} shortest chains to {}",
+                n,
+                view_style.render(target)
+            ),
+            n => format!("{} chains to {}", n, view_style.render(target)),
+        };
+        ui::statusbar::show_msg(state, &msg);
+    }
+}
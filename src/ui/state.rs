@@ -1,8 +1,14 @@
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock, RwLockReadGuard};
 
+use super::super::nix_query_tree::diff::NixQueryTreeDiff;
 use super::super::nix_query_tree::exec_nix_store::{
-    ExecNixStoreRes, NixStoreRes,
+    ExecNixPathInfoRes, ExecNixStoreReferencesRes, ExecNixStoreRes,
+    ExecNixStoreSwitchRes, ExecNixStoreValidityRes, NixStoreRes,
+    ReferencesFiles,
 };
+use super::super::nix_query_tree::{store_path, NixQueryDrv};
+use super::super::tree::Path;
 use super::builder;
 use super::prelude::*;
 
@@ -13,6 +19,8 @@ pub enum SortOrder {
     NixStoreOrigOutput = 0,
     AlphabeticalHash,
     AlphabeticalDrvName,
+    DrvNameAndVersion,
+    RetainedSize,
 }
 
 impl Default for SortOrder {
@@ -29,6 +37,8 @@ impl TryFrom<u32> for SortOrder {
             0 => Ok(SortOrder::NixStoreOrigOutput),
             1 => Ok(SortOrder::AlphabeticalHash),
             2 => Ok(SortOrder::AlphabeticalDrvName),
+            3 => Ok(SortOrder::DrvNameAndVersion),
+            4 => Ok(SortOrder::RetainedSize),
             n => Err(n),
         }
     }
@@ -42,6 +52,7 @@ pub enum ViewStyle {
     HashAndDrvName,
     ShortHashAndDrvName,
     OnlyDrvName,
+    DrvNameAndOutput,
 }
 
 impl Default for ViewStyle {
@@ -59,14 +70,33 @@ impl TryFrom<u32> for ViewStyle {
             1 => Ok(ViewStyle::HashAndDrvName),
             2 => Ok(ViewStyle::ShortHashAndDrvName),
             3 => Ok(ViewStyle::OnlyDrvName),
+            4 => Ok(ViewStyle::DrvNameAndOutput),
             n => Err(n),
         }
     }
 }
 
+impl ViewStyle {
+    /// Render a `NixQueryDrv` in this view style.
+    pub fn render(self, drv: &NixQueryDrv) -> String {
+        match self {
+            ViewStyle::FullPath => drv.to_string(),
+            ViewStyle::HashAndDrvName => drv.hash_and_drv_name(),
+            ViewStyle::ShortHashAndDrvName => drv.short_hash_and_drv_name(),
+            ViewStyle::OnlyDrvName => drv.drv_name(),
+            ViewStyle::DrvNameAndOutput => drv.drv_name_and_output(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Message {
     Display(ExecNixStoreRes),
+    DisplayDiff(ExecNixStoreRes),
+    DisplayReferences(Path, ExecNixStoreReferencesRes),
+    Switch(ExecNixStoreSwitchRes),
+    DisplayPathInfo(ExecNixPathInfoRes),
+    DisplayValidity(ExecNixStoreValidityRes),
 }
 
 #[derive(Clone, Debug)]
@@ -75,8 +105,17 @@ pub struct State {
     pub builder: gtk::Builder,
     pub sender: glib::Sender<Message>,
     pub nix_store_res: Arc<RwLock<Option<NixStoreRes>>>,
+    pub diff: Arc<RwLock<Option<NixQueryTreeDiff>>>,
     pub sort_order: Arc<RwLock<SortOrder>>,
     pub view_style: Arc<RwLock<ViewStyle>>,
+    pub goto_after_display: Arc<RwLock<Option<Path>>>,
+    pub lazy: Arc<RwLock<bool>>,
+    pub references_files: Arc<RwLock<Option<ReferencesFiles>>>,
+    pub why_depends_file: Arc<RwLock<Option<PathBuf>>>,
+    pub path_info_file: Arc<RwLock<Option<PathBuf>>>,
+    pub store_dir: Arc<RwLock<PathBuf>>,
+    pub group_outputs: Arc<RwLock<bool>>,
+    pub missing_only: Arc<RwLock<bool>>,
 }
 
 impl State {
@@ -86,8 +125,19 @@ impl State {
             builder: builder::create(),
             sender,
             nix_store_res: Arc::new(RwLock::new(None)),
+            diff: Arc::new(RwLock::new(None)),
             sort_order: Default::default(),
             view_style: Default::default(),
+            goto_after_display: Arc::new(RwLock::new(None)),
+            lazy: Default::default(),
+            references_files: Arc::new(RwLock::new(None)),
+            why_depends_file: Arc::new(RwLock::new(None)),
+            path_info_file: Arc::new(RwLock::new(None)),
+            store_dir: Arc::new(RwLock::new(PathBuf::from(
+                store_path::DEFAULT_STORE_DIR,
+            ))),
+            group_outputs: Default::default(),
+            missing_only: Default::default(),
         }
     }
 
@@ -95,6 +145,10 @@ impl State {
         self.nix_store_res.read().unwrap()
     }
 
+    pub fn read_diff(&self) -> RwLockReadGuard<Option<NixQueryTreeDiff>> {
+        self.diff.read().unwrap()
+    }
+
     pub fn read_sort_order(&self) -> RwLockReadGuard<SortOrder> {
         self.sort_order.read().unwrap()
     }
@@ -103,12 +157,57 @@ impl State {
         self.view_style.read().unwrap()
     }
 
+    pub fn read_lazy(&self) -> bool {
+        *self.lazy.read().unwrap()
+    }
+
+    pub fn read_references_files(
+        &self,
+    ) -> RwLockReadGuard<Option<ReferencesFiles>> {
+        self.references_files.read().unwrap()
+    }
+
+    pub fn read_why_depends_file(&self) -> RwLockReadGuard<Option<PathBuf>> {
+        self.why_depends_file.read().unwrap()
+    }
+
+    pub fn read_path_info_file(&self) -> RwLockReadGuard<Option<PathBuf>> {
+        self.path_info_file.read().unwrap()
+    }
+
+    pub fn read_group_outputs(&self) -> bool {
+        *self.group_outputs.read().unwrap()
+    }
+
+    pub fn read_missing_only(&self) -> bool {
+        *self.missing_only.read().unwrap()
+    }
+
+    pub fn read_store_dir(&self) -> RwLockReadGuard<PathBuf> {
+        self.store_dir.read().unwrap()
+    }
+
     pub fn write_nix_store_res(&self, new_nix_store_res: NixStoreRes) {
         let state_option_nix_store_res: &mut Option<NixStoreRes> =
             &mut *self.nix_store_res.write().unwrap();
         *state_option_nix_store_res = Some(new_nix_store_res);
     }
 
+    /// Modify the current `NixStoreRes` in place.  This returns `None` if
+    /// there is no `NixStoreRes` yet.
+    pub fn modify_nix_store_res<T>(
+        &self,
+        f: impl FnOnce(&mut NixStoreRes) -> Option<T>,
+    ) -> Option<T> {
+        self.nix_store_res.write().unwrap().as_mut().and_then(f)
+    }
+
+    pub fn write_diff(&self, new_diff: Option<NixQueryTreeDiff>) {
+        let state_option_diff: &mut Option<NixQueryTreeDiff> =
+            &mut *self.diff.write().unwrap();
+        *state_option_diff = new_diff;
+    }
+
     pub fn write_sort_order(&self, new_sort_order: SortOrder) {
         let state_sort_order: &mut SortOrder =
             &mut *self.sort_order.write().unwrap();
@@ -121,6 +220,66 @@ impl State {
         *state_view_style = new_view_style;
     }
 
+    pub fn write_lazy(&self, new_lazy: bool) {
+        let state_lazy: &mut bool = &mut *self.lazy.write().unwrap();
+        *state_lazy = new_lazy;
+    }
+
+    pub fn write_references_files(
+        &self,
+        new_references_files: Option<ReferencesFiles>,
+    ) {
+        let state_references_files: &mut Option<ReferencesFiles> =
+            &mut *self.references_files.write().unwrap();
+        *state_references_files = new_references_files;
+    }
+
+    pub fn write_why_depends_file(
+        &self,
+        new_why_depends_file: Option<PathBuf>,
+    ) {
+        let state_why_depends_file: &mut Option<PathBuf> =
+            &mut *self.why_depends_file.write().unwrap();
+        *state_why_depends_file = new_why_depends_file;
+    }
+
+    pub fn write_path_info_file(&self, new_path_info_file: Option<PathBuf>) {
+        let state_path_info_file: &mut Option<PathBuf> =
+            &mut *self.path_info_file.write().unwrap();
+        *state_path_info_file = new_path_info_file;
+    }
+
+    pub fn write_group_outputs(&self, new_group_outputs: bool) {
+        let state_group_outputs: &mut bool =
+            &mut *self.group_outputs.write().unwrap();
+        *state_group_outputs = new_group_outputs;
+    }
+
+    pub fn write_missing_only(&self, new_missing_only: bool) {
+        let state_missing_only: &mut bool =
+            &mut *self.missing_only.write().unwrap();
+        *state_missing_only = new_missing_only;
+    }
+
+    pub fn write_store_dir(&self, new_store_dir: PathBuf) {
+        let state_store_dir: &mut PathBuf =
+            &mut *self.store_dir.write().unwrap();
+        *state_store_dir = new_store_dir;
+    }
+
+    /// Set a `Path` to go to the next time a `NixStoreRes` is displayed.
+    pub fn write_goto_after_display(&self, new_path: Option<Path>) {
+        let state_goto_after_display: &mut Option<Path> =
+            &mut *self.goto_after_display.write().unwrap();
+        *state_goto_after_display = new_path;
+    }
+
+    /// Take the `Path` to go to after displaying a `NixStoreRes`, leaving
+    /// `None` in its place.
+    pub fn take_goto_after_display(&self) -> Option<Path> {
+        self.goto_after_display.write().unwrap().take()
+    }
+
     pub fn get_app_win(&self) -> gtk::ApplicationWindow {
         self.builder.get_object_expect("appWindow")
     }
@@ -133,6 +292,18 @@ impl State {
         self.builder.get_object_expect("quitMenuItem")
     }
 
+    pub fn get_closure_summary_menu_item(&self) -> gtk::MenuItem {
+        self.builder.get_object_expect("closureSummaryMenuItem")
+    }
+
+    pub fn get_closure_summary_dialog(&self) -> gtk::Dialog {
+        self.builder.get_object_expect("closureSummaryDialog")
+    }
+
+    pub fn get_closure_summary_label(&self) -> gtk::Label {
+        self.builder.get_object_expect("closureSummaryLabel")
+    }
+
     pub fn get_about_dialog(&self) -> gtk::AboutDialog {
         self.builder.get_object_expect("aboutDialog")
     }
@@ -141,10 +312,30 @@ impl State {
         self.builder.get_object_expect("errorDialog")
     }
 
+    pub fn get_instances_dialog(&self) -> gtk::Dialog {
+        self.builder.get_object_expect("instancesDialog")
+    }
+
+    pub fn get_instances_dialog_label(&self) -> gtk::Label {
+        self.builder.get_object_expect("instancesDialogLabel")
+    }
+
+    pub fn get_instances_tree_view(&self) -> gtk::TreeView {
+        self.builder.get_object_expect("instancesTreeView")
+    }
+
+    pub fn get_instances_list_store(&self) -> gtk::ListStore {
+        self.builder.get_object_expect("instancesListStore")
+    }
+
     pub fn get_raw_text_buffer(&self) -> gtk::TextBuffer {
         self.builder.get_object_expect("rawTextBuffer")
     }
 
+    pub fn get_raw_text_view(&self) -> gtk::TextView {
+        self.builder.get_object_expect("rawTextView")
+    }
+
     pub fn get_statusbar(&self) -> gtk::Statusbar {
         self.builder.get_object_expect("statusbar")
     }
@@ -171,6 +362,50 @@ impl State {
         self.builder.get_object_expect("cellRendererTextRepeat")
     }
 
+    pub fn get_referrers_tree_view(&self) -> gtk::TreeView {
+        self.builder.get_object_expect("referrersTreeView")
+    }
+
+    pub fn get_referrers_list_store(&self) -> gtk::ListStore {
+        self.builder.get_object_expect("referrersListStore")
+    }
+
+    pub fn get_stack(&self) -> gtk::Stack {
+        self.builder.get_object_expect("stack")
+    }
+
+    pub fn get_diff_search_entry(&self) -> gtk::SearchEntry {
+        self.builder.get_object_expect("diffSearchEntry")
+    }
+
+    pub fn get_diff_button(&self) -> gtk::Button {
+        self.builder.get_object_expect("diffButton")
+    }
+
+    pub fn get_diff_tree_view(&self) -> gtk::TreeView {
+        self.builder.get_object_expect("diffTreeView")
+    }
+
+    pub fn get_diff_tree_store(&self) -> gtk::TreeStore {
+        self.builder.get_object_expect("diffTreeStore")
+    }
+
+    pub fn get_duplicates_tree_view(&self) -> gtk::TreeView {
+        self.builder.get_object_expect("duplicatesTreeView")
+    }
+
+    pub fn get_duplicates_tree_store(&self) -> gtk::TreeStore {
+        self.builder.get_object_expect("duplicatesTreeStore")
+    }
+
+    pub fn get_dominators_tree_view(&self) -> gtk::TreeView {
+        self.builder.get_object_expect("dominatorsTreeView")
+    }
+
+    pub fn get_dominators_tree_store(&self) -> gtk::TreeStore {
+        self.builder.get_object_expect("dominatorsTreeStore")
+    }
+
     pub fn get_search_entry(&self) -> gtk::SearchEntry {
         self.builder.get_object_expect("searchEntry")
     }
@@ -179,18 +414,42 @@ impl State {
         self.builder.get_object_expect("searchButton")
     }
 
+    pub fn get_build_dependencies_button(&self) -> gtk::Button {
+        self.builder.get_object_expect("buildDependenciesButton")
+    }
+
+    pub fn get_runtime_closure_button(&self) -> gtk::Button {
+        self.builder.get_object_expect("runtimeClosureButton")
+    }
+
     pub fn get_tree_store(&self) -> gtk::TreeStore {
         self.builder.get_object_expect("treeStore")
     }
 
+    pub fn get_tree_model_filter(&self) -> gtk::TreeModelFilter {
+        self.builder.get_object_expect("treeModelFilter")
+    }
+
     pub fn get_tree_model_sort(&self) -> gtk::TreeModelSort {
         self.builder.get_object_expect("treeModelSort")
     }
 
+    pub fn get_location_entry(&self) -> gtk::Entry {
+        self.builder.get_object_expect("locationEntry")
+    }
+
     pub fn get_sort_combo_box(&self) -> gtk::ComboBoxText {
         self.builder.get_object_expect("sortComboBox")
     }
 
+    pub fn get_group_outputs_check_button(&self) -> gtk::CheckButton {
+        self.builder.get_object_expect("groupOutputsCheckButton")
+    }
+
+    pub fn get_missing_only_check_button(&self) -> gtk::CheckButton {
+        self.builder.get_object_expect("missingOnlyCheckButton")
+    }
+
     pub fn get_view_combo_box(&self) -> gtk::ComboBoxText {
         self.builder.get_object_expect("viewComboBox")
     }
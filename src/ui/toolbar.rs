@@ -10,6 +10,12 @@ fn handle_search(state: &ui::State) {
     ui::search_for(state, std::path::Path::new(&search_text));
 }
 
+fn handle_goto_location(state: &ui::State) {
+    let location_text = state.get_location_entry().get_buffer().get_text();
+
+    ui::goto_location(state, &location_text);
+}
+
 fn handle_select_sort_order(state: &ui::State) {
     let combo_box = state.get_sort_combo_box();
     let active_id: u32 = combo_box.get_active().expect(
@@ -30,6 +36,28 @@ fn handle_select_view_style(state: &ui::State) {
     ui::set_view_style(state, view_style);
 }
 
+fn handle_build_dependencies(state: &ui::State) {
+    if let Some(root) = ui::read_root(state) {
+        ui::show_build_dependencies(state, &root);
+    }
+}
+
+fn handle_runtime_closure(state: &ui::State) {
+    if let Some(root) = ui::read_root(state) {
+        ui::show_runtime_closure(state, &root);
+    }
+}
+
+fn handle_toggle_group_outputs(state: &ui::State) {
+    let group_outputs = state.get_group_outputs_check_button().get_active();
+    ui::set_group_outputs(state, group_outputs);
+}
+
+fn handle_toggle_missing_only(state: &ui::State) {
+    let missing_only = state.get_missing_only_check_button().get_active();
+    ui::set_missing_only(state, missing_only);
+}
+
 pub fn connect_signals(state: &ui::State) {
     state.get_search_entry().connect_activate(
         clone!(@strong state => move |_| {
@@ -44,6 +72,24 @@ pub fn connect_signals(state: &ui::State) {
         }),
     );
 
+    state.get_build_dependencies_button().connect_clicked(
+        clone!(@strong state => move |_| {
+            handle_build_dependencies(&state);
+        }),
+    );
+
+    state.get_runtime_closure_button().connect_clicked(
+        clone!(@strong state => move |_| {
+            handle_runtime_closure(&state);
+        }),
+    );
+
+    state.get_location_entry().connect_activate(
+        clone!(@strong state => move |_| {
+            handle_goto_location(&state);
+        }),
+    );
+
     state.get_sort_combo_box().connect_changed(
         clone!(@strong state => move |_| {
             handle_select_sort_order(&state);
@@ -55,18 +101,44 @@ pub fn connect_signals(state: &ui::State) {
             handle_select_view_style(&state);
         }),
     );
+
+    state.get_group_outputs_check_button().connect_toggled(
+        clone!(@strong state => move |_| {
+            handle_toggle_group_outputs(&state);
+        }),
+    );
+
+    state.get_missing_only_check_button().connect_toggled(
+        clone!(@strong state => move |_| {
+            handle_toggle_missing_only(&state);
+        }),
+    );
 }
 
 pub fn disable(state: &ui::State) {
     state.get_search_entry().set_sensitive(false);
     state.get_search_button().set_sensitive(false);
     state.get_sort_combo_box().set_sensitive(false);
+    state.get_location_entry().set_sensitive(false);
+    state.get_build_dependencies_button().set_sensitive(false);
+    state.get_runtime_closure_button().set_sensitive(false);
 }
 
 pub fn enable(state: &ui::State) {
     state.get_search_entry().set_sensitive(true);
     state.get_search_button().set_sensitive(true);
     state.get_sort_combo_box().set_sensitive(true);
+    state.get_location_entry().set_sensitive(true);
+
+    // Only one of these makes sense at a time, depending on whether the root
+    // is a `.drv` file.
+    let option_root_is_drv = ui::read_root(state).map(|root| root.is_drv());
+    state
+        .get_build_dependencies_button()
+        .set_sensitive(option_root_is_drv == Some(false));
+    state
+        .get_runtime_closure_button()
+        .set_sensitive(option_root_is_drv == Some(true));
 }
 
 pub fn setup(state: &ui::State) {
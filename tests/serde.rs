@@ -0,0 +1,74 @@
+extern crate nix_query_tree_viewer;
+
+use indoc::indoc;
+use std::str::FromStr;
+
+use nix_query_tree_viewer::nix_query_tree::exec_nix_store::NixStoreRes;
+use nix_query_tree_viewer::nix_query_tree::*;
+use nix_query_tree_viewer::tree::*;
+
+#[test]
+fn test_serialize_nix_query_tree() {
+    let raw_input = indoc!(
+        "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+        +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+        +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+        "
+    );
+    let nix_query_tree = NixQueryTree::from_str(raw_input).unwrap();
+
+    let expected = serde_json::json!({
+        "item": {
+            "path": "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10",
+            "recurse": false,
+        },
+        "children": [
+            {
+                "item": {
+                    "path": "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27",
+                    "recurse": false,
+                },
+                "children": [],
+            },
+            {
+                "item": {
+                    "path": "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10",
+                    "recurse": true,
+                },
+                "children": [],
+            },
+        ],
+    });
+
+    assert_eq!(serde_json::to_value(&nix_query_tree).unwrap(), expected);
+    assert_eq!(
+        serde_json::from_value::<NixQueryTree>(expected).unwrap(),
+        nix_query_tree
+    );
+}
+
+#[test]
+fn test_serialize_path() {
+    let path: Path = vec![2, 0, 1].into();
+
+    assert_eq!(serde_json::to_string(&path).unwrap(), "[2,0,1]");
+    assert_eq!(serde_json::from_str::<Path>("[2,0,1]").unwrap(), path);
+}
+
+#[test]
+fn test_nix_store_res_roundtrip() {
+    let raw_input = indoc!(
+        "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+        +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+        |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+        +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+        "
+    );
+    let nix_store_res =
+        NixStoreRes::new(raw_input, NixQueryTree::from_str(raw_input).unwrap());
+
+    let json = serde_json::to_string(&nix_store_res).unwrap();
+    let res: NixStoreRes = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(res, nix_store_res);
+}